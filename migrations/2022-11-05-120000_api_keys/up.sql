@@ -0,0 +1,14 @@
+CREATE TABLE api_keys (
+    id uuid DEFAULT uuid_generate_v4 (),
+    owner_id VARCHAR NOT NULL,
+    name VARCHAR NOT NULL,
+    prefix VARCHAR NOT NULL,
+    key_hash VARCHAR NOT NULL UNIQUE,
+    read_only BOOLEAN NOT NULL,
+    expires_at TIMESTAMP,
+    last_used_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX api_keys_owner_id_idx ON api_keys (owner_id);
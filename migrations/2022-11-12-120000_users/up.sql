@@ -0,0 +1,8 @@
+CREATE TABLE users (
+    id uuid DEFAULT uuid_generate_v4 (),
+    username VARCHAR NOT NULL UNIQUE,
+    password_hash VARCHAR NOT NULL,
+    created_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL,
+    PRIMARY KEY (id)
+);
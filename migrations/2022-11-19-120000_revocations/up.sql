@@ -0,0 +1,15 @@
+-- Single tokens revoked by their `jti`, kept until the token would have expired anyway
+CREATE TABLE revoked_tokens (
+    jti VARCHAR NOT NULL,
+    owner_id VARCHAR NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    created_at TIMESTAMP NOT NULL,
+    PRIMARY KEY (jti)
+);
+
+-- Every token of the user issued at or before `issued_before` is revoked
+CREATE TABLE revoked_users (
+    owner_id VARCHAR NOT NULL,
+    issued_before TIMESTAMP NOT NULL,
+    PRIMARY KEY (owner_id)
+);
@@ -0,0 +1 @@
+DROP INDEX tasks_owner_created_at_id_idx;
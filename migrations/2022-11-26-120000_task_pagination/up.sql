@@ -0,0 +1,2 @@
+-- Serves the keyset pagination of task listings
+CREATE INDEX tasks_owner_created_at_id_idx ON tasks (owner_id, created_at, id);
@@ -0,0 +1,4 @@
+DROP INDEX tasks_body_trgm_idx;
+DROP INDEX tasks_title_trgm_idx;
+DROP INDEX tasks_search_vector_idx;
+ALTER TABLE tasks DROP COLUMN search_vector;
@@ -0,0 +1,2 @@
+-- Bumped on every update, exposed as the strong ETag of a task
+ALTER TABLE tasks ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
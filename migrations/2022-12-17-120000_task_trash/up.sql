@@ -0,0 +1,3 @@
+-- Set when a task is moved to the trash, trashed tasks are purged after the retention period
+ALTER TABLE tasks ADD COLUMN deleted_at TIMESTAMP;
+CREATE INDEX tasks_deleted_at_idx ON tasks (deleted_at) WHERE deleted_at IS NOT NULL;
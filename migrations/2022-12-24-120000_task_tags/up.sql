@@ -0,0 +1,20 @@
+CREATE TABLE tags (
+    id uuid DEFAULT uuid_generate_v4 (),
+    owner_id VARCHAR NOT NULL,
+    name VARCHAR NOT NULL,
+    color VARCHAR,
+    created_at TIMESTAMP NOT NULL,
+    PRIMARY KEY (id)
+);
+
+-- Tag names are unique per owner regardless of their case
+CREATE UNIQUE INDEX tags_owner_id_name_idx ON tags (owner_id, lower(name));
+
+-- Links are removed along with the task or the tag
+CREATE TABLE task_tags (
+    task_id uuid REFERENCES tasks (id) ON DELETE CASCADE,
+    tag_id uuid REFERENCES tags (id) ON DELETE CASCADE,
+    PRIMARY KEY (task_id, tag_id)
+);
+
+CREATE INDEX task_tags_tag_id_idx ON task_tags (tag_id);
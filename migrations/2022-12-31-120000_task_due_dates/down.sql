@@ -0,0 +1,4 @@
+DROP INDEX tasks_owner_id_due_at_idx;
+ALTER TABLE tasks DROP COLUMN priority;
+ALTER TABLE tasks DROP COLUMN due_at;
+DROP TYPE task_priority;
@@ -0,0 +1,6 @@
+CREATE TYPE task_priority AS ENUM ('low', 'medium', 'high');
+
+ALTER TABLE tasks ADD COLUMN due_at TIMESTAMPTZ;
+ALTER TABLE tasks ADD COLUMN priority task_priority;
+
+CREATE INDEX tasks_owner_id_due_at_idx ON tasks (owner_id, due_at) WHERE due_at IS NOT NULL;
@@ -0,0 +1,20 @@
+CREATE TABLE reminders (
+    id uuid DEFAULT uuid_generate_v4 (),
+    task_id uuid NOT NULL REFERENCES tasks (id) ON DELETE CASCADE,
+    owner_id VARCHAR NOT NULL,
+    -- NULL while a reminder relative to the due date belongs to a task without one
+    remind_at TIMESTAMPTZ,
+    minutes_before_due INTEGER,
+    email VARCHAR,
+    fired_at TIMESTAMPTZ,
+    dismissed_at TIMESTAMPTZ,
+    -- Lease of the scheduler delivering the reminder, doubles as the retry delay after failures
+    claimed_until TIMESTAMPTZ,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX reminders_task_id_idx ON reminders (task_id);
+CREATE INDEX reminders_pending_idx ON reminders (remind_at)
+    WHERE fired_at IS NULL AND dismissed_at IS NULL;
@@ -0,0 +1,6 @@
+-- Subtasks are deleted along with their parent, soft deletes are cascaded by the application
+ALTER TABLE tasks ADD COLUMN parent_id UUID REFERENCES tasks (id) ON DELETE CASCADE
+    CHECK (parent_id <> id);
+ALTER TABLE tasks ADD COLUMN auto_complete BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE INDEX tasks_parent_id_idx ON tasks (parent_id) WHERE parent_id IS NOT NULL;
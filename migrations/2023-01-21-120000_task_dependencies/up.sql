@@ -0,0 +1,10 @@
+-- The task can't be started before the task it depends on is done
+CREATE TABLE task_dependencies (
+    task_id uuid NOT NULL REFERENCES tasks (id) ON DELETE CASCADE,
+    depends_on_id uuid NOT NULL REFERENCES tasks (id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (task_id, depends_on_id),
+    CHECK (task_id <> depends_on_id)
+);
+
+CREATE INDEX task_dependencies_depends_on_id_idx ON task_dependencies (depends_on_id);
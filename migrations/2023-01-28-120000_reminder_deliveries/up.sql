@@ -0,0 +1,9 @@
+-- Channels that have accepted a firing of the reminder, so retries & other schedulers skip them.
+-- A snoozed or rescheduled reminder fires at a new time & is delivered on every channel again
+CREATE TABLE reminder_deliveries (
+    reminder_id uuid NOT NULL REFERENCES reminders (id) ON DELETE CASCADE,
+    remind_at TIMESTAMPTZ NOT NULL,
+    channel VARCHAR NOT NULL,
+    delivered_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (reminder_id, remind_at, channel)
+);
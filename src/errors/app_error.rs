@@ -1,20 +1,62 @@
 use actix_http::StatusCode;
-use actix_web::{HttpResponse, ResponseError};
+use actix_web::{http::header, HttpResponse, ResponseError};
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
 use log::debug;
 use oauth2::{basic::BasicErrorResponseType, RequestTokenError, StandardErrorResponse};
 use serde::{Deserialize, Serialize};
-use std::fmt::Display;
+use std::{collections::BTreeMap, fmt::Display};
+use validator::{ValidationError, ValidationErrors};
 
 // Wrapper for generic backend errors to convert them to readable & returnable responses
 
+/// Seconds clients are asked to wait before retrying a transaction that failed to serialize
+const SERIALIZATION_RETRY_AFTER: u32 = 1;
+
+pub const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+/// Prefix of the `type` URIs of problem details, followed by a stable slug per kind of error
+pub const PROBLEM_TYPE_PREFIX: &str = "urn:zeronote:problem:";
+
+// Database errors are reported by their kind only, the messages can contain queries & values
+fn diesel_error_response(e: &DieselError) -> (StatusCode, &'static str, &'static str) {
+    match e {
+        DieselError::NotFound => (StatusCode::NOT_FOUND, "not-found", "Resource not found"),
+        DieselError::DatabaseError(kind, _) => match kind {
+            DatabaseErrorKind::UniqueViolation => {
+                (StatusCode::CONFLICT, "conflict", "Resource already exists")
+            }
+            DatabaseErrorKind::CheckViolation | DatabaseErrorKind::ForeignKeyViolation => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "constraint-violation",
+                "Request violates a data constraint",
+            ),
+            DatabaseErrorKind::SerializationFailure => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "concurrent-update",
+                "Conflicting concurrent request, please retry",
+            ),
+            _ => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal",
+                "Internal Server Error",
+            ),
+        },
+        _ => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal",
+            "Internal Server Error",
+        ),
+    }
+}
+
 #[derive(Debug)]
 pub enum AppError {
-    DieselResult(diesel::result::Error),
+    DieselResult(DieselError),
     DieselPool(diesel::r2d2::PoolError),
     WebBlocking(actix_web::error::BlockingError),
     Validator(validator::ValidationErrors),
     Uuid(uuid::Error),
     JsonPayLoad(actix_web::error::JsonPayloadError),
+    QueryPayload(actix_web::error::QueryPayloadError),
     OAuth2Token(
         RequestTokenError<
             oauth2::reqwest::Error<reqwest::Error>,
@@ -22,13 +64,30 @@ pub enum AppError {
         >,
     ),
     Oauth2Parse(oauth2::url::ParseError),
+    OAuth2State(String),
     JwtGeneric(jsonwebtokens::error::Error),
-    JwtCognito(jsonwebtokens_cognito::Error),
-    JwtParse(jwt::Error),
+    JwksFetch(reqwest::Error),
+    JwtUnknownKey(String),
+    JwtUntrustedIssuer(String),
     JwtMissingClaim(String),
+    JwtRevoked,
+    PasswordHash(argon2::password_hash::Error),
     HeaderToStr(actix_http::header::ToStrError),
     MissingConfig(String),
     AuthNotFound(String),
+    BadRequest(String),
+    Forbidden(String),
+    NotFound(String),
+    Conflict(String),
+    UnprocessableEntity(String),
+    UnsupportedMediaType(String),
+    PreconditionFailed(String),
+    /// Task can't be started or finished before the tasks it depends on are done
+    TaskBlocked(String),
+    /// Failed delivery of a notification, only logged by the reminder scheduler
+    Notify(String),
+    /// Failure of the operation at the index of a batch, reported with the status of the cause
+    BatchOperation(usize, Box<AppError>),
 }
 
 impl Display for AppError {
@@ -37,39 +96,187 @@ impl Display for AppError {
     }
 }
 
+impl std::error::Error for AppError {}
+
 impl ResponseError for AppError {
     fn status_code(&self) -> actix_http::StatusCode {
         match *self {
-            Self::DieselResult(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::DieselResult(ref e) => diesel_error_response(e).0,
             Self::DieselPool(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Self::WebBlocking(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Self::MissingConfig(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::PasswordHash(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Self::Validator(_) => StatusCode::BAD_REQUEST,
             Self::Uuid(_) => StatusCode::BAD_REQUEST,
             Self::JsonPayLoad(_) => StatusCode::BAD_REQUEST,
+            Self::QueryPayload(_) => StatusCode::BAD_REQUEST,
             Self::OAuth2Token(_) => StatusCode::UNAUTHORIZED,
             Self::Oauth2Parse(_) => StatusCode::UNAUTHORIZED,
+            Self::OAuth2State(_) => StatusCode::UNAUTHORIZED,
             Self::JwtGeneric(_) => StatusCode::UNAUTHORIZED,
-            Self::JwtCognito(_) => StatusCode::UNAUTHORIZED,
-            Self::JwtParse(_) => StatusCode::UNAUTHORIZED,
+            Self::JwksFetch(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::JwtUnknownKey(_) => StatusCode::UNAUTHORIZED,
+            Self::JwtUntrustedIssuer(_) => StatusCode::UNAUTHORIZED,
             Self::JwtMissingClaim(_) => StatusCode::UNAUTHORIZED,
+            Self::JwtRevoked => StatusCode::UNAUTHORIZED,
             Self::HeaderToStr(_) => StatusCode::UNAUTHORIZED,
             Self::AuthNotFound(_) => StatusCode::UNAUTHORIZED,
+            Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::Conflict(_) => StatusCode::CONFLICT,
+            Self::UnprocessableEntity(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Self::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+            Self::TaskBlocked(_) => StatusCode::CONFLICT,
+            Self::Notify(_) => StatusCode::BAD_GATEWAY,
+            Self::BatchOperation(_, ref e) => e.status_code(),
         }
     }
 
+    // Rendered without a request ID, the ProblemResponses middleware fills it in or switches to
+    // the legacy `AppErrorResponse` body depending on the request
     fn error_response(&self) -> actix_web::HttpResponse<actix_http::body::BoxBody> {
         debug!(target: "errors_file", "{}", self);
-        HttpResponse::build(self.status_code()).json(AppErrorResponse::new(self))
+        let mut res = HttpResponse::build(self.status_code());
+        if self.is_serialization_failure() {
+            res.insert_header((header::RETRY_AFTER, SERIALIZATION_RETRY_AFTER));
+        }
+
+        res.content_type(PROBLEM_CONTENT_TYPE)
+            .body(serde_json::to_string(&self.problem(None)).unwrap_or_default())
+    }
+}
+
+impl AppError {
+    /// Stable, machine-readable kind of the error, used as the slug of the problem `type`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::DieselResult(e) => diesel_error_response(e).1,
+            Self::DieselPool(_) => "internal",
+            Self::WebBlocking(_) => "internal",
+            Self::MissingConfig(_) => "internal",
+            Self::PasswordHash(_) => "internal",
+            Self::Validator(_) => "validation",
+            Self::Uuid(_) => "invalid-uuid",
+            Self::JsonPayLoad(_) => "invalid-json",
+            Self::QueryPayload(_) => "invalid-query",
+            Self::OAuth2Token(_) => "invalid-token",
+            Self::Oauth2Parse(_) => "invalid-token",
+            Self::OAuth2State(_) => "invalid-login-state",
+            Self::JwtGeneric(_) => "invalid-token",
+            Self::JwksFetch(_) => "identity-provider-unavailable",
+            Self::JwtUnknownKey(_) => "invalid-token",
+            Self::JwtUntrustedIssuer(_) => "invalid-token",
+            Self::JwtMissingClaim(_) => "invalid-token",
+            Self::JwtRevoked => "token-revoked",
+            Self::HeaderToStr(_) => "invalid-token",
+            Self::AuthNotFound(_) => "unauthenticated",
+            Self::BadRequest(_) => "bad-request",
+            Self::Forbidden(_) => "forbidden",
+            Self::NotFound(_) => "not-found",
+            Self::Conflict(_) => "conflict",
+            Self::UnprocessableEntity(_) => "unprocessable-entity",
+            Self::UnsupportedMediaType(_) => "unsupported-media-type",
+            Self::PreconditionFailed(_) => "precondition-failed",
+            Self::TaskBlocked(_) => "task-blocked",
+            Self::Notify(_) => "notification-failed",
+            Self::BatchOperation(_, e) => e.kind(),
+        }
+    }
+
+    fn is_serialization_failure(&self) -> bool {
+        match self {
+            Self::DieselResult(DieselError::DatabaseError(
+                DatabaseErrorKind::SerializationFailure,
+                _,
+            )) => true,
+            Self::BatchOperation(_, e) => e.is_serialization_failure(),
+            _ => false,
+        }
+    }
+
+    pub fn problem(&self, request_id: Option<String>) -> ProblemDetails {
+        // The fields of a failed batch operation are prefixed with its position in the batch
+        if let Self::BatchOperation(index, e) = self {
+            let mut problem = e.problem(request_id);
+            problem.detail = format!("Operation {}: {}", index, problem.detail);
+            problem.errors = problem
+                .errors
+                .into_iter()
+                .map(|(field, messages)| (format!("operations[{}].{}", index, field), messages))
+                .collect();
+            return problem;
+        }
+
+        let status = self.status_code();
+        let detail = match self {
+            Self::Validator(_) => "Request validation failed".into(),
+            _ => AppErrorResponse::new(self).message,
+        };
+        let errors = match self {
+            Self::Validator(e) => field_errors(e),
+            _ => BTreeMap::new(),
+        };
+
+        ProblemDetails {
+            problem_type: format!("{}{}", PROBLEM_TYPE_PREFIX, self.kind()),
+            title: status.canonical_reason().unwrap_or_default().into(),
+            status: status.as_u16(),
+            detail,
+            request_id,
+            errors,
+        }
     }
 }
 
-impl From<diesel::result::Error> for AppError {
-    fn from(_: diesel::result::Error) -> Self {
-        AppError::AuthNotFound("nice".to_string())
+// Numeric validator parameters like `{max}` are filled into the message, so it can't drift from
+// the limit it describes
+fn error_message(error: &ValidationError) -> String {
+    let message = error.message.as_ref().unwrap_or(&error.code).to_string();
+    error
+        .params
+        .iter()
+        .filter(|(_, value)| value.is_number())
+        .fold(message, |message, (name, value)| {
+            message.replace(&format!("{{{}}}", name), &value.to_string())
+        })
+}
+
+// Struct level errors (`#[validate(schema)]`) are listed under `__all__`
+fn field_errors(errors: &ValidationErrors) -> BTreeMap<String, Vec<String>> {
+    errors
+        .field_errors()
+        .into_iter()
+        .map(|(field, errors)| {
+            let messages = errors.iter().map(error_message).collect();
+            (field.to_string(), messages)
+        })
+        .collect()
+}
+
+impl From<DieselError> for AppError {
+    fn from(e: DieselError) -> Self {
+        AppError::DieselResult(e)
     }
 }
 
+/// RFC 7807 problem details, the default body of error responses
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProblemDetails {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// Messages of the invalid fields
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub errors: BTreeMap<String, Vec<String>>,
+}
+
+/// Error body used before problem details, still sent to clients that only accept application/json
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AppErrorResponse {
     pub code: String,
@@ -77,25 +284,154 @@ pub struct AppErrorResponse {
 }
 
 impl AppErrorResponse {
-    fn new(app_error: &AppError) -> Self {
+    pub fn new(app_error: &AppError) -> Self {
         let (code, message) = match app_error {
-            AppError::DieselResult(_) => ("500".into(), "Internal Server Error".into()),
+            AppError::DieselResult(e) => {
+                let (status, _, message) = diesel_error_response(e);
+                (status.as_u16().to_string(), message.into())
+            }
             AppError::DieselPool(_) => ("500".into(), "Internal Server Error".into()),
             AppError::WebBlocking(_) => ("500".into(), "Internal Server Error".into()),
             AppError::MissingConfig(s) => ("500".into(), s.into()),
+            AppError::PasswordHash(_) => ("500".into(), "Internal Server Error".into()),
             AppError::Validator(_) => ("400".into(), "Invalid JSON payload".into()),
             AppError::Uuid(_) => ("400".into(), "Invalid UUID".into()),
             AppError::JsonPayLoad(_) => ("400".into(), "Invalid JSON payload".into()),
+            AppError::QueryPayload(_) => ("400".into(), "Invalid query parameters".into()),
             AppError::OAuth2Token(_) => ("401".into(), "Invalid JWT token".into()),
             AppError::Oauth2Parse(_) => ("401".into(), "Invalid JWT token".into()),
+            AppError::OAuth2State(s) => ("401".into(), s.into()),
             AppError::JwtGeneric(_) => ("401".into(), "Invalid JWT token".into()),
-            AppError::JwtCognito(_) => ("401".into(), "Invalid JWT token".into()),
-            AppError::JwtParse(_) => ("401".into(), "Invalid JWT token".into()),
+            AppError::JwksFetch(_) => ("503".into(), "Identity provider unavailable".into()),
+            AppError::JwtUnknownKey(_) => ("401".into(), "Invalid JWT token".into()),
+            AppError::JwtUntrustedIssuer(_) => ("401".into(), "Invalid JWT token".into()),
             AppError::JwtMissingClaim(_) => ("401".into(), "Invalid JWT token".into()),
+            AppError::JwtRevoked => ("401".into(), "JWT token has been revoked".into()),
             AppError::HeaderToStr(_) => ("401".into(), "Invalid JWT token".into()),
             AppError::AuthNotFound(s) => ("401".into(), s.into()),
+            AppError::BadRequest(s) => ("400".into(), s.into()),
+            AppError::Forbidden(s) => ("403".into(), s.into()),
+            AppError::NotFound(s) => ("404".into(), s.into()),
+            AppError::Conflict(s) => ("409".into(), s.into()),
+            AppError::UnprocessableEntity(s) => ("422".into(), s.into()),
+            AppError::UnsupportedMediaType(s) => ("415".into(), s.into()),
+            AppError::PreconditionFailed(s) => ("412".into(), s.into()),
+            AppError::TaskBlocked(s) => ("409".into(), s.into()),
+            AppError::Notify(s) => ("502".into(), s.into()),
+            AppError::BatchOperation(index, e) => {
+                let cause = AppErrorResponse::new(e);
+                (
+                    cause.code,
+                    format!("Operation {}: {}", index, cause.message),
+                )
+            }
         };
 
         AppErrorResponse { code, message }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::to_bytes;
+
+    fn database_error(kind: DatabaseErrorKind) -> AppError {
+        AppError::from(DieselError::DatabaseError(
+            kind,
+            Box::new(String::from(
+                "constraint \"tasks_secret_key\" of relation \"tasks\"",
+            )),
+        ))
+    }
+
+    async fn response(error: AppError) -> (StatusCode, Option<String>, ProblemDetails) {
+        let res = error.error_response();
+        let retry_after = res
+            .headers()
+            .get(header::RETRY_AFTER)
+            .map(|value| value.to_str().unwrap().to_owned());
+        let status = res.status();
+        let body = to_bytes(res.into_body()).await.unwrap();
+
+        (status, retry_after, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[actix_web::test]
+    async fn test_not_found() {
+        let (status, _, body) = response(AppError::from(DieselError::NotFound)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body.status, 404);
+        assert_eq!(body.problem_type, "urn:zeronote:problem:not-found");
+    }
+
+    #[actix_web::test]
+    async fn test_unique_violation() {
+        let (status, _, body) = response(database_error(DatabaseErrorKind::UniqueViolation)).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body.status, 409);
+        assert_eq!(body.problem_type, "urn:zeronote:problem:conflict");
+    }
+
+    #[actix_web::test]
+    async fn test_constraint_violations() {
+        for kind in [
+            DatabaseErrorKind::CheckViolation,
+            DatabaseErrorKind::ForeignKeyViolation,
+        ] {
+            let (status, _, body) = response(database_error(kind)).await;
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+            assert_eq!(body.status, 422);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_serialization_failure() {
+        let (status, retry_after, body) =
+            response(database_error(DatabaseErrorKind::SerializationFailure)).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(retry_after.as_deref(), Some("1"));
+        assert!(body.detail.contains("retry"));
+    }
+
+    #[actix_web::test]
+    async fn test_other_errors() {
+        for error in [
+            database_error(DatabaseErrorKind::NotNullViolation),
+            AppError::from(DieselError::RollbackTransaction),
+        ] {
+            let (status, retry_after, body) = response(error).await;
+            assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+            assert!(retry_after.is_none());
+            assert_eq!(body.detail, "Internal Server Error");
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_internals_are_not_leaked() {
+        for kind in [
+            DatabaseErrorKind::UniqueViolation,
+            DatabaseErrorKind::CheckViolation,
+            DatabaseErrorKind::ForeignKeyViolation,
+            DatabaseErrorKind::SerializationFailure,
+            DatabaseErrorKind::Unknown,
+        ] {
+            let (_, _, body) = response(database_error(kind)).await;
+            assert!(!body.detail.contains("tasks"), "{}", body.detail);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_validation_message_params() {
+        let mut error = ValidationError::new("length");
+        error.message = Some("Tasks can have at most {max} tags".into());
+        error.add_param("max".into(), &20);
+        error.add_param("value".into(), &"{max}");
+        let mut errors = ValidationErrors::new();
+        errors.add("tags", error);
+
+        let (status, _, body) = response(AppError::Validator(errors)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body.errors["tags"], ["Tasks can have at most 20 tags"]);
+    }
+}
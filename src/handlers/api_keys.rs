@@ -0,0 +1,49 @@
+use crate::{
+    database::connection::Pool,
+    errors::app_error::AppError,
+    models::{api_key::*, principal::AuthenticatedUser},
+    services::api_keys,
+};
+use actix_web::{delete, get, post, web, HttpResponse};
+use validator::Validate;
+
+// Handlers for managing the caller's API keys, the secret is only included in the creation response
+
+#[post("/keys")]
+pub async fn create_api_key(
+    user: AuthenticatedUser,
+    pool: web::Data<Pool>,
+    key: web::Json<CreateApiKey>,
+) -> Result<HttpResponse, AppError> {
+    key.validate().map_err(AppError::Validator)?;
+    let res = web::block(move || api_keys::create(pool, key.into_inner(), user))
+        .await
+        .map_err(AppError::WebBlocking)??;
+
+    Ok(HttpResponse::Created().json(res))
+}
+
+#[get("/keys")]
+pub async fn get_api_keys(
+    user: AuthenticatedUser,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, AppError> {
+    let keys_vec = web::block(move || api_keys::get_all(pool, user))
+        .await
+        .map_err(AppError::WebBlocking)??;
+
+    Ok(HttpResponse::Ok().json(keys_vec))
+}
+
+#[delete("/keys/{id}")]
+pub async fn revoke_api_key(
+    user: AuthenticatedUser,
+    pool: web::Data<Pool>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let res = web::block(move || api_keys::revoke(pool, id.into_inner(), user))
+        .await
+        .map_err(AppError::WebBlocking)??;
+
+    Ok(HttpResponse::Ok().json(res))
+}
@@ -0,0 +1,191 @@
+use crate::{
+    database::connection::Pool,
+    errors::app_error::AppError,
+    middlewares::{
+        auth::{Authorization, CognitoConfig},
+        policy::Admin,
+    },
+    models::{auth::*, principal::AuthenticatedUser},
+    services::{
+        auth::{self, PendingLogins},
+        device::{PendingDevices, DEVICE_CODE_GRANT_TYPE},
+        revocations::{self, RevocationCache},
+    },
+};
+use actix_web::{
+    get,
+    http::header::{self, ContentType},
+    post, web, HttpResponse,
+};
+use oauth2::{
+    basic::BasicErrorResponseType,
+    devicecode::{DeviceCodeErrorResponse, DeviceCodeErrorResponseType},
+    StandardErrorResponse,
+};
+use validator::Validate;
+
+// Handlers for the server-side OAuth2 authorization code flow with PKCE & the device
+// authorization grant built on it (no auth required) as well as logging out & revoking tokens
+// server-side
+
+const DEVICE_CODE_FORM: &str = r#"<!DOCTYPE html>
+<html>
+  <head><title>zeronote - Connect a device</title></head>
+  <body>
+    <form method="get" action="device">
+      <label>Code shown on your device <input name="user_code" autocomplete="off" required></label>
+      <button type="submit">Continue</button>
+    </form>
+  </body>
+</html>
+"#;
+
+const DEVICE_APPROVED: &str = r#"<!DOCTYPE html>
+<html>
+  <head><title>zeronote - Device connected</title></head>
+  <body><p>Your device is now signed in, you can close this page.</p></body>
+</html>
+"#;
+
+#[get("/login")]
+pub async fn login(
+    config: web::Data<CognitoConfig>,
+    pending: web::Data<PendingLogins>,
+) -> Result<HttpResponse, AppError> {
+    let url = auth::login_url(&config, &pending, None)?;
+
+    Ok(HttpResponse::Found()
+        .insert_header((header::LOCATION, url))
+        .finish())
+}
+
+#[get("/callback")]
+pub async fn login_callback(
+    config: web::Data<CognitoConfig>,
+    pending: web::Data<PendingLogins>,
+    devices: web::Data<PendingDevices>,
+    query: web::Query<LoginCallback>,
+) -> Result<HttpResponse, AppError> {
+    let LoginCallback { code, state, error } = query.into_inner();
+    // The state is consumed either way so that it can't be replayed
+    let pending_login = pending.take(&state)?;
+    let code = match (code, error) {
+        (Some(code), None) => code,
+        (_, error) => {
+            if let Some(device_code) = &pending_login.device_code {
+                devices.deny(device_code);
+            }
+            return Err(AppError::OAuth2State(format!(
+                "Login failed: {}",
+                error.unwrap_or_else(|| "missing authorization code".into())
+            )));
+        }
+    };
+    let token = auth::exchange_code(&config, code, pending_login.verifier).await?;
+
+    match pending_login.device_code {
+        Some(device_code) => {
+            devices.approve(&device_code, token);
+            Ok(HttpResponse::Ok()
+                .content_type(ContentType::html())
+                .body(DEVICE_APPROVED))
+        }
+        None => Ok(HttpResponse::Ok().json(token)),
+    }
+}
+
+#[post("/refresh")]
+pub async fn refresh_token(
+    config: web::Data<CognitoConfig>,
+    req: web::Json<RefreshTokenRequest>,
+) -> Result<HttpResponse, AppError> {
+    req.validate().map_err(AppError::Validator)?;
+    let token = auth::refresh(&config, req.into_inner().refresh_token).await?;
+
+    Ok(HttpResponse::Ok().json(token))
+}
+
+#[post("/device/code")]
+pub async fn device_authorization(
+    config: web::Data<CognitoConfig>,
+    devices: web::Data<PendingDevices>,
+) -> Result<HttpResponse, AppError> {
+    let res = devices.start(&config)?;
+
+    Ok(HttpResponse::Ok().json(res))
+}
+
+/// Verification page of the device flow, logs the user in to approve the device
+#[get("/device")]
+pub async fn device_verification(
+    config: web::Data<CognitoConfig>,
+    pending: web::Data<PendingLogins>,
+    devices: web::Data<PendingDevices>,
+    query: web::Query<DeviceVerification>,
+) -> Result<HttpResponse, AppError> {
+    let user_code = match &query.user_code {
+        Some(user_code) => user_code,
+        None => {
+            return Ok(HttpResponse::Ok()
+                .content_type(ContentType::html())
+                .body(DEVICE_CODE_FORM))
+        }
+    };
+    let device_code = devices.device_code(user_code)?;
+    let url = auth::login_url(&config, &pending, Some(device_code))?;
+
+    Ok(HttpResponse::Found()
+        .insert_header((header::LOCATION, url))
+        .finish())
+}
+
+/// Polled by the device, errors are answered in the OAuth2 format the device's client expects
+#[post("/device/token")]
+pub async fn device_token(
+    devices: web::Data<PendingDevices>,
+    form: web::Form<DeviceTokenRequest>,
+) -> HttpResponse {
+    let res = match form.grant_type.as_str() {
+        DEVICE_CODE_GRANT_TYPE => devices.poll(&form.device_code),
+        _ => Err(DeviceCodeErrorResponseType::Basic(
+            BasicErrorResponseType::UnsupportedGrantType,
+        )),
+    };
+
+    match res {
+        Ok(token) => HttpResponse::Ok().json(token),
+        Err(error) => {
+            let body: DeviceCodeErrorResponse = StandardErrorResponse::new(error, None, None);
+            HttpResponse::BadRequest().json(body)
+        }
+    }
+}
+
+#[post("/logout", wrap = "Authorization")]
+pub async fn logout(
+    user: AuthenticatedUser,
+    pool: web::Data<Pool>,
+    cache: web::Data<RevocationCache>,
+    query: web::Query<LogoutQuery>,
+) -> Result<HttpResponse, AppError> {
+    let all = query.into_inner().all;
+    web::block(move || revocations::logout(pool, cache, user, all))
+        .await
+        .map_err(AppError::WebBlocking)??;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[post("/admin/revocations", wrap = "Admin")]
+pub async fn revoke_tokens(
+    pool: web::Data<Pool>,
+    cache: web::Data<RevocationCache>,
+    req: web::Json<RevokeTokens>,
+) -> Result<HttpResponse, AppError> {
+    req.validate().map_err(AppError::Validator)?;
+    web::block(move || revocations::revoke(pool, cache, req.into_inner()))
+        .await
+        .map_err(AppError::WebBlocking)??;
+
+    Ok(HttpResponse::NoContent().finish())
+}
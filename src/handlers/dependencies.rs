@@ -0,0 +1,57 @@
+use crate::{
+    database::connection::Pool,
+    errors::app_error::AppError,
+    middlewares::policy::{ReadTasks, WriteTasks},
+    models::{dependency::*, principal::AuthenticatedUser},
+    services::dependencies,
+};
+use actix_web::{delete, get, post, web, HttpResponse};
+use uuid::Uuid;
+
+// Handlers for the dependencies between the user's tasks, mounted under /api/v1 & guarded by the
+// same scopes as tasks
+
+/// Tasks that have to be done before the task can be started or finished
+#[get("/tasks/{id}/dependencies", wrap = "ReadTasks")]
+pub async fn list_dependencies(
+    user: AuthenticatedUser,
+    pool: web::Data<Pool>,
+    task_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let task_id = task_id.into_inner();
+    let res = web::block(move || dependencies::get_all(pool, task_id, user))
+        .await
+        .map_err(AppError::WebBlocking)??;
+
+    Ok(HttpResponse::Ok().json(res))
+}
+
+/// Dependencies that would make a task (indirectly) depend on itself are rejected
+#[post("/tasks/{id}/dependencies", wrap = "WriteTasks")]
+pub async fn add_dependency(
+    user: AuthenticatedUser,
+    pool: web::Data<Pool>,
+    task_id: web::Path<Uuid>,
+    dependency: web::Json<AddDependency>,
+) -> Result<HttpResponse, AppError> {
+    let task_id = task_id.into_inner();
+    let res = web::block(move || dependencies::add(pool, task_id, dependency.into_inner(), user))
+        .await
+        .map_err(AppError::WebBlocking)??;
+
+    Ok(HttpResponse::Created().json(res))
+}
+
+#[delete("/tasks/{id}/dependencies/{depends_on_id}", wrap = "WriteTasks")]
+pub async fn remove_dependency(
+    user: AuthenticatedUser,
+    pool: web::Data<Pool>,
+    path: web::Path<(Uuid, Uuid)>,
+) -> Result<HttpResponse, AppError> {
+    let (task_id, depends_on_id) = path.into_inner();
+    web::block(move || dependencies::remove(pool, task_id, depends_on_id, user))
+        .await
+        .map_err(AppError::WebBlocking)??;
+
+    Ok(HttpResponse::NoContent().finish())
+}
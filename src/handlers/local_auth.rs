@@ -0,0 +1,81 @@
+use crate::{
+    database::connection::Pool,
+    errors::app_error::AppError,
+    middlewares::{
+        auth::Authorization,
+        policy::{SCOPE_TASKS_READ, SCOPE_TASKS_WRITE},
+    },
+    models::{principal::AuthenticatedUser, user::*},
+    services::local_auth,
+    utils::local_issuer::LocalIssuer,
+};
+use actix_web::{get, post, put, web, HttpResponse};
+use validator::Validate;
+
+// Handlers of the built-in local identity provider (mounted under /auth/local) & of the offline
+// development mode (mounted under /auth/dev, which serves its JWKS through `local_jwks` as well)
+
+#[post("/register")]
+pub async fn local_register(
+    pool: web::Data<Pool>,
+    credentials: web::Json<Credentials>,
+) -> Result<HttpResponse, AppError> {
+    credentials.validate().map_err(AppError::Validator)?;
+    let res = web::block(move || local_auth::register(pool, credentials.into_inner()))
+        .await
+        .map_err(AppError::WebBlocking)??;
+
+    Ok(HttpResponse::Created().json(res))
+}
+
+#[post("/login")]
+pub async fn local_login(
+    pool: web::Data<Pool>,
+    issuer: web::Data<LocalIssuer>,
+    credentials: web::Json<Credentials>,
+) -> Result<HttpResponse, AppError> {
+    let res = web::block(move || local_auth::login(pool, issuer, credentials.into_inner()))
+        .await
+        .map_err(AppError::WebBlocking)??;
+
+    Ok(HttpResponse::Ok().json(res))
+}
+
+#[put("/password", wrap = "Authorization")]
+pub async fn change_password(
+    user: AuthenticatedUser,
+    pool: web::Data<Pool>,
+    passwords: web::Json<ChangePassword>,
+) -> Result<HttpResponse, AppError> {
+    passwords.validate().map_err(AppError::Validator)?;
+    web::block(move || local_auth::change_password(pool, passwords.into_inner(), user))
+        .await
+        .map_err(AppError::WebBlocking)??;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[get("/jwks.json")]
+pub async fn local_jwks(issuer: web::Data<LocalIssuer>) -> HttpResponse {
+    HttpResponse::Ok().json(issuer.jwks())
+}
+
+#[post("/token")]
+pub async fn dev_token(
+    issuer: web::Data<LocalIssuer>,
+    req: web::Json<DevTokenRequest>,
+) -> Result<HttpResponse, AppError> {
+    req.validate().map_err(AppError::Validator)?;
+    let scopes = match &req.scopes {
+        Some(scopes) => scopes.iter().map(String::as_str).collect(),
+        None => vec![SCOPE_TASKS_READ, SCOPE_TASKS_WRITE],
+    };
+    let groups: Vec<&str> = req.groups.iter().map(String::as_str).collect();
+    let token = issuer.issue_with_groups(&req.sub, req.username.as_deref(), &groups, &scopes)?;
+
+    Ok(HttpResponse::Ok().json(AccessToken {
+        access_token: token,
+        token_type: "Bearer".into(),
+        expires_in: issuer.token_ttl(),
+    }))
+}
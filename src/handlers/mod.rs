@@ -1 +1,7 @@
+pub mod api_keys;
+pub mod auth;
+pub mod dependencies;
+pub mod local_auth;
+pub mod reminders;
+pub mod tags;
 pub mod tasks;
@@ -0,0 +1,98 @@
+use crate::{
+    database::connection::Pool,
+    errors::app_error::AppError,
+    middlewares::policy::{ReadTasks, WriteTasks},
+    models::{principal::AuthenticatedUser, reminder::*},
+    services::reminders,
+    utils::notifier::EmailRecipients,
+};
+use actix_web::{delete, get, post, web, HttpResponse};
+use uuid::Uuid;
+use validator::Validate;
+
+// Handlers for the reminders of the user's tasks, mounted under /api/v1 & guarded by the same
+// scopes as tasks. Reminders are created under their task & managed by their own ID afterwards
+
+#[get("/tasks/{id}/reminders", wrap = "ReadTasks")]
+pub async fn list_reminders(
+    user: AuthenticatedUser,
+    pool: web::Data<Pool>,
+    task_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let task_id = task_id.into_inner();
+    let res = web::block(move || reminders::get_all(pool, task_id, user))
+        .await
+        .map_err(AppError::WebBlocking)??;
+
+    Ok(HttpResponse::Ok().json(res))
+}
+
+/// Reminders relative to the due date (`minutes_before_due`) follow it when it changes
+/// Emails can only be sent to the domains of `SMTP_ALLOWED_DOMAINS`
+#[post("/tasks/{id}/reminders", wrap = "WriteTasks")]
+pub async fn create_reminder(
+    user: AuthenticatedUser,
+    pool: web::Data<Pool>,
+    recipients: web::Data<EmailRecipients>,
+    task_id: web::Path<Uuid>,
+    reminder: web::Json<CreateReminder>,
+) -> Result<HttpResponse, AppError> {
+    reminder.validate().map_err(AppError::Validator)?;
+    if let Some(email) = &reminder.email {
+        if !recipients.allows(email) {
+            return Err(AppError::UnprocessableEntity(
+                "Reminders can't be emailed to this domain".into(),
+            ));
+        }
+    }
+    let task_id = task_id.into_inner();
+    let res = web::block(move || reminders::create(pool, task_id, reminder.into_inner(), user))
+        .await
+        .map_err(AppError::WebBlocking)??;
+
+    Ok(HttpResponse::Created().json(res))
+}
+
+#[delete("/reminders/{id}", wrap = "WriteTasks")]
+pub async fn delete_reminder(
+    user: AuthenticatedUser,
+    pool: web::Data<Pool>,
+    reminder_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let reminder_id = reminder_id.into_inner();
+    web::block(move || reminders::delete(pool, reminder_id, user))
+        .await
+        .map_err(AppError::WebBlocking)??;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[post("/reminders/{id}/snooze", wrap = "WriteTasks")]
+pub async fn snooze_reminder(
+    user: AuthenticatedUser,
+    pool: web::Data<Pool>,
+    reminder_id: web::Path<Uuid>,
+    snooze: web::Json<SnoozeReminder>,
+) -> Result<HttpResponse, AppError> {
+    snooze.validate().map_err(AppError::Validator)?;
+    let reminder_id = reminder_id.into_inner();
+    let res = web::block(move || reminders::snooze(pool, reminder_id, snooze.into_inner(), user))
+        .await
+        .map_err(AppError::WebBlocking)??;
+
+    Ok(HttpResponse::Ok().json(res))
+}
+
+#[post("/reminders/{id}/dismiss", wrap = "WriteTasks")]
+pub async fn dismiss_reminder(
+    user: AuthenticatedUser,
+    pool: web::Data<Pool>,
+    reminder_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let reminder_id = reminder_id.into_inner();
+    let res = web::block(move || reminders::dismiss(pool, reminder_id, user))
+        .await
+        .map_err(AppError::WebBlocking)??;
+
+    Ok(HttpResponse::Ok().json(res))
+}
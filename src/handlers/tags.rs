@@ -0,0 +1,89 @@
+use crate::{
+    database::connection::Pool,
+    errors::app_error::AppError,
+    middlewares::policy::{ReadTasks, WriteTasks},
+    models::{principal::AuthenticatedUser, tag::*},
+    services::tags,
+};
+use actix_web::{delete, get, http::header, post, put, web, HttpRequest, HttpResponse};
+use uuid::Uuid;
+use validator::Validate;
+
+// Handlers for the user's tags, mounted under /api/v1 & guarded by the same scopes as tasks
+// Tags are attached to tasks through the `tags` of the task endpoints
+
+#[get("/tags", wrap = "ReadTasks")]
+pub async fn list_tags(
+    user: AuthenticatedUser,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, AppError> {
+    let res = web::block(move || tags::get_all(pool, user))
+        .await
+        .map_err(AppError::WebBlocking)??;
+
+    Ok(HttpResponse::Ok().json(res))
+}
+
+/// Tag names are unique per user regardless of their case, duplicates are answered with 409
+#[post("/tags", wrap = "WriteTasks")]
+pub async fn create_tag(
+    req: HttpRequest,
+    user: AuthenticatedUser,
+    pool: web::Data<Pool>,
+    tag: web::Json<EditTag>,
+) -> Result<HttpResponse, AppError> {
+    tag.validate().map_err(AppError::Validator)?;
+    let res = web::block(move || tags::create(pool, tag.into_inner(), user))
+        .await
+        .map_err(AppError::WebBlocking)??;
+    let location = format!("{}/{}", req.path().trim_end_matches('/'), res.id);
+
+    Ok(HttpResponse::Created()
+        .insert_header((header::LOCATION, location))
+        .json(res))
+}
+
+#[get("/tags/{id}", wrap = "ReadTasks")]
+pub async fn get_tag(
+    user: AuthenticatedUser,
+    pool: web::Data<Pool>,
+    tag_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let tag_id = tag_id.into_inner();
+    let res = web::block(move || tags::get(pool, tag_id, user))
+        .await
+        .map_err(AppError::WebBlocking)??;
+
+    Ok(HttpResponse::Ok().json(res))
+}
+
+#[put("/tags/{id}", wrap = "WriteTasks")]
+pub async fn replace_tag(
+    user: AuthenticatedUser,
+    pool: web::Data<Pool>,
+    tag_id: web::Path<Uuid>,
+    tag: web::Json<EditTag>,
+) -> Result<HttpResponse, AppError> {
+    tag.validate().map_err(AppError::Validator)?;
+    let tag_id = tag_id.into_inner();
+    let res = web::block(move || tags::replace(pool, tag_id, tag.into_inner(), user))
+        .await
+        .map_err(AppError::WebBlocking)??;
+
+    Ok(HttpResponse::Ok().json(res))
+}
+
+/// Deletes the tag & detaches it from the tasks it was attached to
+#[delete("/tags/{id}", wrap = "WriteTasks")]
+pub async fn delete_tag(
+    user: AuthenticatedUser,
+    pool: web::Data<Pool>,
+    tag_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let tag_id = tag_id.into_inner();
+    web::block(move || tags::delete(pool, tag_id, user))
+        .await
+        .map_err(AppError::WebBlocking)??;
+
+    Ok(HttpResponse::NoContent().finish())
+}
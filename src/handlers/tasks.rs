@@ -1,63 +1,376 @@
 use crate::{
-    database::connection::Pool, errors::app_error::AppError, models::task::*, services::tasks,
+    database::connection::Pool,
+    errors::app_error::AppError,
+    middlewares::policy::{ReadTasks, WriteTasks},
+    models::{principal::AuthenticatedUser, task::*},
+    services::{tasks, trash},
 };
-use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse};
+use actix_web::{
+    delete,
+    error::JsonPayloadError,
+    get,
+    http::header::{self, Header},
+    patch, post, put, web, HttpMessage, HttpRequest, HttpResponse,
+};
+use oauth2::url::form_urlencoded;
+use uuid::Uuid;
 use validator::Validate;
 
-// Handlers for basic CRUD functionality regarding tasks
+// Handlers for basic CRUD functionality regarding tasks, mounted under /api/v1 with the task's ID
+// taken from the path. The earlier /api/new, /api/all, /api/update & /api/delete routes remain as
+// deprecated aliases
+// Reads require the `tasks:read` scope & mutations `tasks:write`, members of `admin` may do both
 
-#[get("/all")]
-pub async fn get_all_tasks(
+async fn list(
     req: HttpRequest,
+    user: AuthenticatedUser,
     pool: web::Data<Pool>,
+    list: web::Query<TaskListQuery>,
 ) -> Result<HttpResponse, AppError> {
-    let headers = req.headers().clone();
-    let tasks_vec = web::block(move || tasks::get_all(pool, headers))
+    list.validate().map_err(AppError::Validator)?;
+    let page = web::block(move || tasks::get_all(pool, list.into_inner(), user))
         .await
         .map_err(AppError::WebBlocking)??;
 
-    Ok(HttpResponse::Ok().json(tasks_vec))
+    let mut res = HttpResponse::Ok();
+    if let Some(cursor) = &page.next_cursor {
+        // The next page keeps the filters, sort order & limit of the current one
+        let query = form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(
+                form_urlencoded::parse(req.query_string().as_bytes())
+                    .filter(|(key, _)| key != "cursor"),
+            )
+            .append_pair("cursor", cursor)
+            .finish();
+        let next = format!("{}?{}", req.path(), query);
+        res.insert_header((header::LINK, format!("<{}>; rel=\"next\"", next)))
+            .insert_header(("X-Next-Cursor", cursor.as_str()));
+    }
+
+    Ok(res.json(page.tasks))
 }
 
-#[post("/new")]
-pub async fn create_new_task(
+async fn update(
+    user: AuthenticatedUser,
+    pool: web::Data<Pool>,
+    task_id: Uuid,
+    changes: TaskChanges,
+    precondition: TaskPrecondition,
+) -> Result<TaskWithTags, AppError> {
+    web::block(move || tasks::update(pool, task_id, changes, precondition, user))
+        .await
+        .map_err(AppError::WebBlocking)?
+}
+
+// If-Match uses the strong comparison, so weak & malformed tags never match
+fn precondition(req: &HttpRequest) -> TaskPrecondition {
+    if !req.headers().contains_key(header::IF_MATCH) {
+        return TaskPrecondition::Any;
+    }
+
+    match header::IfMatch::parse(req) {
+        Ok(header::IfMatch::Any) => TaskPrecondition::Any,
+        Ok(header::IfMatch::Items(tags)) => TaskPrecondition::Versions(
+            tags.iter()
+                .filter(|tag| !tag.weak)
+                .filter_map(|tag| tag.tag().parse().ok())
+                .collect(),
+        ),
+        Err(_) => TaskPrecondition::Versions(vec![]),
+    }
+}
+
+/// Lists a filtered & sorted page of the user's tasks, the cursor of the next page is returned in
+/// the `Link` & `X-Next-Cursor` headers
+#[get("/tasks", wrap = "ReadTasks")]
+pub async fn list_tasks(
+    req: HttpRequest,
+    user: AuthenticatedUser,
+    pool: web::Data<Pool>,
+    query: web::Query<TaskListQuery>,
+) -> Result<HttpResponse, AppError> {
+    list(req, user, pool, query).await
+}
+
+#[post("/tasks", wrap = "WriteTasks")]
+pub async fn create_task(
     req: HttpRequest,
+    user: AuthenticatedUser,
     pool: web::Data<Pool>,
     task: web::Json<CreateTask>,
 ) -> Result<HttpResponse, AppError> {
     task.validate().map_err(AppError::Validator)?;
-    let headers = req.headers().clone();
-    let res = web::block(move || tasks::create(pool, task.into_inner(), headers))
+    let res = web::block(move || tasks::create(pool, task.into_inner(), user))
+        .await
+        .map_err(AppError::WebBlocking)??;
+    let location = format!("{}/{}", req.path().trim_end_matches('/'), res.task.id);
+
+    Ok(HttpResponse::Created()
+        .insert_header((header::LOCATION, location))
+        .insert_header(header::ETag(res.task.etag()))
+        .json(res))
+}
+
+/// Ranked full-text search over the titles & bodies of the user's tasks
+#[get("/search", wrap = "ReadTasks")]
+pub async fn search_tasks(
+    user: AuthenticatedUser,
+    pool: web::Data<Pool>,
+    search: web::Query<SearchQuery>,
+) -> Result<HttpResponse, AppError> {
+    search.validate().map_err(AppError::Validator)?;
+    let hits = web::block(move || tasks::search(pool, search.into_inner(), user))
+        .await
+        .map_err(AppError::WebBlocking)??;
+
+    Ok(HttpResponse::Ok().json(hits))
+}
+
+/// Answers with 304 Not Modified when the task still matches one of the `If-None-Match` tags
+/// With `?include=children` the whole subtree is embedded, which the task's ETag doesn't cover, so
+/// it's always returned in full & without one
+#[get("/tasks/{id}", wrap = "ReadTasks")]
+pub async fn get_task(
+    req: HttpRequest,
+    user: AuthenticatedUser,
+    pool: web::Data<Pool>,
+    task_id: web::Path<Uuid>,
+    query: web::Query<GetTaskQuery>,
+) -> Result<HttpResponse, AppError> {
+    query.validate().map_err(AppError::Validator)?;
+    let task_id = task_id.into_inner();
+    if query.include_children() {
+        let tree = web::block(move || tasks::get_tree(pool, task_id, user))
+            .await
+            .map_err(AppError::WebBlocking)??;
+
+        return Ok(HttpResponse::Ok().json(tree));
+    }
+    let res = web::block(move || tasks::get(pool, task_id, user))
+        .await
+        .map_err(AppError::WebBlocking)??;
+
+    let etag = res.task.etag();
+    let not_modified = match header::IfNoneMatch::parse(&req) {
+        Ok(header::IfNoneMatch::Any) => true,
+        Ok(header::IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(&etag)),
+        Err(_) => false,
+    };
+    if not_modified {
+        return Ok(HttpResponse::NotModified()
+            .insert_header(header::ETag(etag))
+            .finish());
+    }
+
+    Ok(HttpResponse::Ok()
+        .insert_header(header::ETag(etag))
+        .json(res))
+}
+
+/// Direct subtasks of the task, `GET /tasks/{id}?include=children` returns the whole subtree
+#[get("/tasks/{id}/children", wrap = "ReadTasks")]
+pub async fn list_children(
+    user: AuthenticatedUser,
+    pool: web::Data<Pool>,
+    task_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let task_id = task_id.into_inner();
+    let children = web::block(move || tasks::get_children(pool, task_id, user))
+        .await
+        .map_err(AppError::WebBlocking)??;
+
+    Ok(HttpResponse::Ok().json(children))
+}
+
+#[put("/tasks/{id}", wrap = "WriteTasks")]
+pub async fn replace_task(
+    req: HttpRequest,
+    user: AuthenticatedUser,
+    pool: web::Data<Pool>,
+    task_id: web::Path<Uuid>,
+    task: web::Json<ReplaceTask>,
+) -> Result<HttpResponse, AppError> {
+    task.validate().map_err(AppError::Validator)?;
+    let changes = TaskChanges::try_from(task.into_inner())?;
+    let res = update(
+        user,
+        pool,
+        task_id.into_inner(),
+        changes,
+        precondition(&req),
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header(header::ETag(res.task.etag()))
+        .json(res))
+}
+
+/// Applies a JSON Merge Patch, which has to be sent as `application/merge-patch+json`
+/// The body is only parsed once the media type has been checked, so other types get a 415
+#[patch("/tasks/{id}", wrap = "WriteTasks")]
+pub async fn patch_task(
+    req: HttpRequest,
+    user: AuthenticatedUser,
+    pool: web::Data<Pool>,
+    task_id: web::Path<Uuid>,
+    body: web::Bytes,
+) -> Result<HttpResponse, AppError> {
+    let merge_patch = req
+        .mime_type()
+        .ok()
+        .flatten()
+        .is_some_and(|mime| mime.essence_str() == MERGE_PATCH_CONTENT_TYPE);
+    if !merge_patch {
+        return Err(AppError::UnsupportedMediaType(format!(
+            "Patches must be sent as {}",
+            MERGE_PATCH_CONTENT_TYPE
+        )));
+    }
+    let task: PatchTask = serde_json::from_slice(&body)
+        .map_err(|e| AppError::JsonPayLoad(JsonPayloadError::Deserialize(e)))?;
+    task.validate().map_err(AppError::Validator)?;
+    let changes = TaskChanges::try_from(task)?;
+    let res = update(
+        user,
+        pool,
+        task_id.into_inner(),
+        changes,
+        precondition(&req),
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header(header::ETag(res.task.etag()))
+        .insert_header(("Accept-Patch", MERGE_PATCH_CONTENT_TYPE))
+        .json(res))
+}
+
+/// Moves the task to the trash
+#[delete("/tasks/{id}", wrap = "WriteTasks")]
+pub async fn remove_task(
+    req: HttpRequest,
+    user: AuthenticatedUser,
+    pool: web::Data<Pool>,
+    task_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let task_id = task_id.into_inner();
+    let precondition = precondition(&req);
+    let deleted = web::block(move || tasks::delete(pool, task_id, precondition, user))
+        .await
+        .map_err(AppError::WebBlocking)??;
+
+    match deleted {
+        0 => Err(AppError::NotFound("Task not found".into())),
+        _ => Ok(HttpResponse::NoContent().finish()),
+    }
+}
+
+/// Runs up to 100 create, update, delete & condition change operations in one transaction
+#[post("/tasks/batch", wrap = "WriteTasks")]
+pub async fn batch_tasks(
+    user: AuthenticatedUser,
+    pool: web::Data<Pool>,
+    batch: web::Json<BatchRequest>,
+) -> Result<HttpResponse, AppError> {
+    batch.validate().map_err(AppError::Validator)?;
+    let results = web::block(move || tasks::batch(pool, batch.into_inner(), user))
+        .await
+        .map_err(AppError::WebBlocking)??;
+
+    Ok(HttpResponse::Ok().json(BatchResponse { results }))
+}
+
+/// Lists the user's trashed tasks, most recently deleted first
+#[get("/tasks/trash", wrap = "ReadTasks")]
+pub async fn list_trash(
+    user: AuthenticatedUser,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, AppError> {
+    let res = web::block(move || trash::get_all(pool, user))
         .await
         .map_err(AppError::WebBlocking)??;
 
     Ok(HttpResponse::Ok().json(res))
 }
 
-#[put("/update")]
-pub async fn update_task(
+#[post("/tasks/trash/{id}/restore", wrap = "WriteTasks")]
+pub async fn restore_task(
+    user: AuthenticatedUser,
+    pool: web::Data<Pool>,
+    task_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let task_id = task_id.into_inner();
+    let res = web::block(move || trash::restore(pool, task_id, user))
+        .await
+        .map_err(AppError::WebBlocking)??;
+
+    Ok(HttpResponse::Ok()
+        .insert_header(header::ETag(res.task.etag()))
+        .json(res))
+}
+
+/// Permanently deletes the tasks in the user's trash
+#[delete("/tasks/trash", wrap = "WriteTasks")]
+pub async fn empty_trash(
+    user: AuthenticatedUser,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, AppError> {
+    web::block(move || trash::empty(pool, user))
+        .await
+        .map_err(AppError::WebBlocking)??;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// Deprecated aliases, mounted directly under /api
+
+#[get("/all", wrap = "ReadTasks")]
+pub async fn get_all_tasks(
     req: HttpRequest,
+    user: AuthenticatedUser,
     pool: web::Data<Pool>,
-    task: web::Json<UpdateTask>,
+    query: web::Query<TaskListQuery>,
+) -> Result<HttpResponse, AppError> {
+    list(req, user, pool, query).await
+}
+
+#[post("/new", wrap = "WriteTasks")]
+pub async fn create_new_task(
+    user: AuthenticatedUser,
+    pool: web::Data<Pool>,
+    task: web::Json<CreateTask>,
 ) -> Result<HttpResponse, AppError> {
     task.validate().map_err(AppError::Validator)?;
-    let headers = req.headers().clone();
-    let res = web::block(move || tasks::update(pool, task.into_inner(), headers))
+    let res = web::block(move || tasks::create(pool, task.into_inner(), user))
         .await
         .map_err(AppError::WebBlocking)??;
 
     Ok(HttpResponse::Ok().json(res))
 }
 
-#[delete("/delete")]
+#[put("/update", wrap = "WriteTasks")]
+pub async fn update_task(
+    user: AuthenticatedUser,
+    pool: web::Data<Pool>,
+    task: web::Json<UpdateTask>,
+) -> Result<HttpResponse, AppError> {
+    task.validate().map_err(AppError::Validator)?;
+    let (task_id, changes) = task.into_inner().into_changes()?;
+    let res = update(user, pool, task_id, changes, TaskPrecondition::Any).await?;
+
+    Ok(HttpResponse::Ok().json(res))
+}
+
+#[delete("/delete", wrap = "WriteTasks")]
 pub async fn delete_task(
-    req: HttpRequest,
+    user: AuthenticatedUser,
     pool: web::Data<Pool>,
     task: web::Json<DeleteTask>,
 ) -> Result<HttpResponse, AppError> {
     task.validate().map_err(AppError::Validator)?;
-    let headers = req.headers().clone();
-    let res = web::block(move || tasks::delete(pool, task.into_inner().id, headers))
+    let task_id = Uuid::parse_str(&task.id).map_err(AppError::Uuid)?;
+    let res = web::block(move || tasks::delete(pool, task_id, TaskPrecondition::Any, user))
         .await
         .map_err(AppError::WebBlocking)??;
 
@@ -1,18 +1,41 @@
 use actix_web::{middleware::Logger, web, App, HttpResponse, HttpServer};
+use chrono::Duration as ChronoDuration;
 use dotenv::dotenv;
-use std::env;
+use log::warn;
+use std::{env, sync::Arc};
 use zeronote::{
     database::connection::{init_pool, run_migrations},
     errors::app_error::AppError,
-    handlers::tasks::*,
+    handlers::{
+        api_keys::*, auth::*, dependencies::*, local_auth::*, reminders::*, tags::*, tasks::*,
+    },
     middlewares::{
         auth::{self, CognitoConfig},
         cors::cors,
+        deprecation::{deprecated, deprecated_paths},
+        problem::ProblemResponses,
         security_headers::security_headers,
     },
-    utils::{log::init_logger, ssl_builder::create_builder},
+    services::{
+        auth::PendingLogins, device::PendingDevices, reminders::ReminderScheduler,
+        revocations::RevocationCache, trash::TrashPurge,
+    },
+    utils::{
+        jwks::JwksCache,
+        local_issuer::{dev_auth_enabled, LocalAuthConfig, LocalIssuer},
+        log::init_logger,
+        notifier::{EmailRecipients, Notifiers},
+        ssl_builder::create_builder,
+        verifier::{
+            CognitoVerifier, IssuerSettings, OidcIssuerConfig, OidcVerifier, TokenVerifier,
+            TrustedIssuers,
+        },
+    },
 };
 
+/// Default format of the Logger middleware with the ID set by `ProblemResponses` appended
+const LOG_FORMAT: &str = r#"%a "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T %{x-request-id}o"#;
+
 fn parse_env() -> (String, String) {
     dotenv().ok();
 
@@ -33,37 +56,188 @@ fn parse_env() -> (String, String) {
     )
 }
 
+// Cognito (if COGNITO_DOMAIN is set), the local provider (if LOCAL_AUTH is enabled), the
+// development issuer (if DEV_AUTH is enabled) & every issuer listed in OIDC_ISSUERS are trusted
+async fn trusted_issuers(
+    cognito_cfg: Option<&CognitoConfig>,
+    local_issuers: impl IntoIterator<Item = Arc<LocalIssuer>>,
+) -> Result<TrustedIssuers, AppError> {
+    let mut issuers = TrustedIssuers::new();
+
+    for local in local_issuers {
+        issuers = issuers.with(local.issuer().to_owned(), local);
+    }
+
+    if let Some(config) = cognito_cfg {
+        let keys = Arc::new(JwksCache::new(config.jwks_url.clone()));
+        JwksCache::spawn_refresh(keys.clone());
+        let settings = IssuerSettings {
+            audiences: Vec::new(),
+            leeway: env::var("COGNITO_LEEWAY").map_or(0, |l| {
+                l.parse()
+                    .expect("COGNITO_LEEWAY must be a number of seconds")
+            }),
+        };
+        let verifier = CognitoVerifier::new(config, keys, settings)?;
+        issuers = issuers.with(verifier.issuer().to_owned(), Arc::new(verifier));
+    }
+
+    for oidc in OidcIssuerConfig::from_env()? {
+        let verifier = OidcVerifier::discover(&oidc.issuer, oidc.settings).await?;
+        JwksCache::spawn_refresh(verifier.keys());
+        issuers = issuers.with(oidc.issuer, Arc::new(verifier));
+    }
+
+    Ok(issuers)
+}
+
 #[actix_web::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let (cors_url, db_url) = parse_env();
-    let cognito_cfg = CognitoConfig::default();
+    let cognito_cfg = env::var("COGNITO_DOMAIN")
+        .is_ok()
+        .then(CognitoConfig::default);
+    let local_issuer = LocalAuthConfig::from_env()
+        .map(|config| LocalIssuer::new(&config).map(Arc::new))
+        .transpose()?;
+    let dev_issuer = dev_auth_enabled()
+        .then(|| LocalIssuer::dev().map(Arc::new))
+        .transpose()?;
+    let issuers = trusted_issuers(
+        cognito_cfg.as_ref(),
+        local_issuer.iter().chain(&dev_issuer).cloned(),
+    )
+    .await?;
+    if issuers.is_empty() {
+        panic!("Either COGNITO_DOMAIN, LOCAL_AUTH, DEV_AUTH or OIDC_ISSUERS must be set");
+    }
+    let verifier: Arc<dyn TokenVerifier> = Arc::new(issuers);
+    let pending_logins = web::Data::new(PendingLogins::default());
+    let pending_devices = web::Data::new(PendingDevices::default());
+    // Tokens of the local issuers may outlive the default revocation window
+    let revocations =
+        local_issuer
+            .iter()
+            .chain(&dev_issuer)
+            .fold(RevocationCache::default(), |cache, issuer| {
+                cache.with_token_lifetime(ChronoDuration::seconds(issuer.token_ttl() as i64))
+            });
+    let revocations = web::Data::new(revocations);
 
     let pool = init_pool(db_url);
     let mut conn = pool.get()?;
     let builder = create_builder()?;
     run_migrations(&mut conn);
+    TrashPurge::from_env()?.spawn(pool.clone());
     init_logger()?;
+    let notifiers = Notifiers::from_env()?;
+    let recipients = web::Data::new(EmailRecipients::from_env());
+    match notifiers.is_empty() {
+        true => warn!("Neither NOTIFY_WEBHOOK_URL nor SMTP_HOST is set, reminders won't be sent"),
+        false => ReminderScheduler::from_env()?.spawn(pool.clone(), Arc::new(notifiers)),
+    }
+    if dev_issuer.is_some() {
+        warn!("DEV_AUTH is enabled, anyone can mint tokens through /auth/dev/token");
+    }
 
     HttpServer::new(move || {
         App::new()
-            .wrap(Logger::default())
+            .wrap(ProblemResponses)
+            .wrap(Logger::new(LOG_FORMAT))
             .wrap(cors(&cors_url))
             .wrap(security_headers())
             .app_data(
                 web::JsonConfig::default()
                     .error_handler(|err, _| AppError::JsonPayLoad(err).into()),
             )
+            .app_data(
+                web::QueryConfig::default()
+                    .error_handler(|err, _| AppError::QueryPayload(err).into()),
+            )
             .app_data(web::Data::new(pool.clone()))
-            .app_data(web::Data::new(cognito_cfg.clone()))
+            .app_data(web::Data::from(verifier.clone()))
+            .app_data(revocations.clone())
+            .app_data(recipients.clone())
+            .service(web::scope("/auth").service(logout).configure(|cfg| {
+                if let Some(issuer) = &local_issuer {
+                    cfg.service(
+                        web::scope("/local")
+                            .app_data(web::Data::from(issuer.clone()))
+                            .service(local_register)
+                            .service(local_login)
+                            .service(change_password)
+                            .service(local_jwks),
+                    );
+                }
+                if let Some(issuer) = &dev_issuer {
+                    cfg.service(
+                        web::scope("/dev")
+                            .app_data(web::Data::from(issuer.clone()))
+                            .service(dev_token)
+                            .service(local_jwks),
+                    );
+                }
+                if let Some(config) = &cognito_cfg {
+                    cfg.app_data(web::Data::new(config.clone()))
+                        .app_data(pending_logins.clone())
+                        .app_data(pending_devices.clone())
+                        .service(login)
+                        .service(login_callback)
+                        .service(refresh_token)
+                        .service(device_authorization)
+                        .service(device_verification)
+                        .service(device_token);
+                }
+            }))
             .service(
                 web::scope("/api")
-                    .service(create_new_task)
-                    .service(get_all_tasks)
-                    .service(delete_task)
-                    .service(update_task)
+                    .service(
+                        web::scope("/v1")
+                            .service(list_tasks)
+                            .service(create_task)
+                            .service(search_tasks)
+                            .service(batch_tasks)
+                            // The trash routes have to be matched before /tasks/{id}
+                            .service(list_trash)
+                            .service(empty_trash)
+                            .service(restore_task)
+                            .service(get_task)
+                            .service(list_children)
+                            .service(list_dependencies)
+                            .service(add_dependency)
+                            .service(remove_dependency)
+                            .service(replace_task)
+                            .service(patch_task)
+                            .service(remove_task)
+                            .service(list_tags)
+                            .service(create_tag)
+                            .service(get_tag)
+                            .service(replace_tag)
+                            .service(delete_tag)
+                            .service(list_reminders)
+                            .service(create_reminder)
+                            .service(delete_reminder)
+                            .service(snooze_reminder)
+                            .service(dismiss_reminder),
+                    )
+                    .service(create_api_key)
+                    .service(get_api_keys)
+                    .service(revoke_api_key)
+                    .service(revoke_tokens)
+                    // Guarded to the deprecated aliases, it'd match every remaining path otherwise
+                    .service(
+                        web::scope("")
+                            .guard(deprecated_paths())
+                            .wrap(deprecated())
+                            .service(create_new_task)
+                            .service(get_all_tasks)
+                            .service(search_tasks)
+                            .service(delete_task)
+                            .service(update_task),
+                    )
                     .wrap(auth::Authorization),
             )
-            .default_service(web::to(|| HttpResponse::NotFound()))
+            .default_service(web::to(HttpResponse::NotFound))
     })
     .bind_openssl("0.0.0.0:443", builder)?
     .run()
@@ -1,9 +1,18 @@
-use crate::errors::app_error::AppError;
+use crate::{
+    database::connection::Pool,
+    errors::app_error::AppError,
+    models::principal::AuthenticatedUser,
+    services::{
+        api_keys::{self, API_KEY_PREFIX},
+        revocations::RevocationCache,
+    },
+    utils::verifier::TokenVerifier,
+};
 use actix_web::{
     dev::{Service, ServiceRequest, ServiceResponse, Transform},
-    web, Error,
+    http::header,
+    web, Error, HttpMessage,
 };
-use jsonwebtokens_cognito::KeySet;
 use std::env;
 use std::{
     future::{ready, Future, Ready},
@@ -12,6 +21,9 @@ use std::{
 };
 
 // Authorizes the incoming request (for /api endpoints) based on the JWT in Authentication header
+// The token is checked by whichever `TokenVerifier` is registered in app data (`TrustedIssuers` in main)
+// & the resulting `AuthenticatedUser` is stored in the request extensions for the handlers
+// Bearer values starting with `zn_` are API keys, which are looked up from the DB instead
 
 #[derive(Debug, Clone)]
 pub struct CognitoConfig {
@@ -20,8 +32,14 @@ pub struct CognitoConfig {
     pub token_url: String,
     pub client_id: String,
     pub client_secret: String,
+    pub redirect_url: String,
+    /// Page users visit to approve a device authorization (served by the device_verification handler)
+    pub device_verification_url: String,
+    pub scopes: Vec<String>,
     pub keyset_region: String,
     pub keyset_pool_id: String,
+    pub issuer: String,
+    pub jwks_url: String,
 }
 
 impl Default for CognitoConfig {
@@ -31,20 +49,80 @@ impl Default for CognitoConfig {
         let token_url = cognito_domain + "/oauth2/token";
         let client_id = env::var("CLIENT_ID").expect("CLIENT_ID must be set");
         let client_secret = env::var("CLIENT_SECRET").expect("CLIENT_SECRET must be set");
+        let redirect_url = env::var("OAUTH_REDIRECT_URL")
+            .unwrap_or_else(|_| "https://localhost/auth/callback".into());
+        let device_verification_url = env::var("OAUTH_DEVICE_VERIFICATION_URL")
+            .unwrap_or_else(|_| "https://localhost/auth/device".into());
+        let scopes = env::var("OAUTH_SCOPES")
+            .unwrap_or_else(|_| "openid".into())
+            .split_whitespace()
+            .map(String::from)
+            .collect();
         let keyset_pool_id = env::var("KEYSET_POOL_ID").expect("KEYSET_POOL_ID must be set");
-        let keyset_region: String = keyset_pool_id.split("_").next().unwrap().into();
+        let keyset_region: String = keyset_pool_id.split('_').next().unwrap().into();
+        let issuer = format!(
+            "https://cognito-idp.{}.amazonaws.com/{}",
+            keyset_region, keyset_pool_id
+        );
+        let jwks_url = issuer.clone() + "/.well-known/jwks.json";
 
         Self {
             auth_url,
             token_url,
             client_id,
             client_secret,
+            redirect_url,
+            device_verification_url,
+            scopes,
             keyset_region,
             keyset_pool_id,
+            issuer,
+            jwks_url,
         }
     }
 }
 
+fn bearer_token(req: &ServiceRequest) -> Result<&str, AppError> {
+    let auth_header = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .ok_or(AppError::AuthNotFound(
+            "Authorization header missing".into(),
+        ))?
+        .to_str()
+        .map_err(AppError::HeaderToStr)?;
+
+    match auth_header.split_once(' ') {
+        Some((scheme, token)) if scheme.eq_ignore_ascii_case("bearer") && !token.is_empty() => {
+            Ok(token.trim())
+        }
+        _ => Err(AppError::AuthNotFound(
+            "Authorization header must contain a bearer token".into(),
+        )),
+    }
+}
+
+// Revocations are only enforced when a `RevocationCache` is registered in app data (always in main)
+async fn check_revocation(
+    req: &ServiceRequest,
+    user: AuthenticatedUser,
+) -> Result<AuthenticatedUser, AppError> {
+    let (cache, pool) = match (
+        req.app_data::<web::Data<RevocationCache>>(),
+        req.app_data::<web::Data<Pool>>(),
+    ) {
+        (Some(cache), Some(pool)) => (cache.clone(), pool.clone()),
+        _ => return Ok(user),
+    };
+
+    web::block(move || match cache.is_revoked(&pool, &user)? {
+        true => Err(AppError::JwtRevoked),
+        false => Ok(user),
+    })
+    .await
+    .map_err(AppError::WebBlocking)?
+}
+
 pub struct Authorization;
 
 impl<S: 'static, B> Transform<S, ServiceRequest> for Authorization
@@ -91,34 +169,31 @@ where
         let svc = self.service.clone();
 
         Box::pin(async move {
-            let config = req
-                .app_data::<web::Data<CognitoConfig>>()
-                .ok_or(AppError::MissingConfig(
-                    "Missing internal AWS Cognito configuration".into(),
-                ))?
-                .clone()
-                .into_inner();
-            let keyset = KeySet::new(config.keyset_region.clone(), config.keyset_pool_id.clone())
-                .map_err(AppError::JwtCognito)?;
-            let verifier = keyset
-                .new_access_token_verifier(&[config.client_id.as_str()])
-                .build()
-                .map_err(AppError::JwtGeneric)?;
-            let auth_header = req
-                .headers()
-                .get("Authorization")
-                .ok_or(AppError::AuthNotFound(
-                    "Authorization header missing".into(),
-                ))?
-                .to_str()
-                .map_err(AppError::HeaderToStr)?
-                .split(" ")
-                .collect::<Vec<&str>>()[1]; // Strips prefix from the header
-
-            keyset
-                .verify(auth_header, &verifier)
-                .await
-                .map_err(AppError::JwtCognito)?;
+            let token = bearer_token(&req)?;
+            let user = if token.starts_with(API_KEY_PREFIX) {
+                let pool = req
+                    .app_data::<web::Data<Pool>>()
+                    .ok_or(AppError::MissingConfig(
+                        "Missing internal database configuration".into(),
+                    ))?
+                    .clone();
+                let secret = token.to_owned();
+                web::block(move || api_keys::authenticate(pool, &secret))
+                    .await
+                    .map_err(AppError::WebBlocking)??
+            } else {
+                let verifier = req
+                    .app_data::<web::Data<dyn TokenVerifier>>()
+                    .ok_or(AppError::MissingConfig(
+                        "Missing internal token verifier configuration".into(),
+                    ))?
+                    .clone()
+                    .into_inner();
+                let claims = verifier.verify(token).await?;
+                let user = AuthenticatedUser::from_claims(&claims)?;
+                check_revocation(&req, user).await?
+            };
+            req.extensions_mut().insert(user);
 
             let fut = svc.call(req);
             let res = fut.await?;
@@ -1,10 +1,35 @@
-use actix_cors::Cors;
-use actix_web::http::{header, Method};
-
-pub fn cors(client_origin_url: &str) -> Cors {
-    Cors::default()
-        .allowed_origin(client_origin_url)
-        .allowed_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
-        .allowed_headers([header::AUTHORIZATION, header::CONTENT_TYPE])
-        .max_age(86400)
-}
+use crate::middlewares::problem::REQUEST_ID_HEADER;
+use actix_cors::Cors;
+use actix_web::http::{
+    header::{self, HeaderName},
+    Method,
+};
+
+pub fn cors(client_origin_url: &str) -> Cors {
+    Cors::default()
+        .allowed_origin(client_origin_url)
+        .allowed_methods([
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::PATCH,
+            Method::DELETE,
+        ])
+        .allowed_headers([
+            header::AUTHORIZATION,
+            header::CONTENT_TYPE,
+            header::IF_MATCH,
+            header::IF_NONE_MATCH,
+            REQUEST_ID_HEADER,
+        ])
+        // Pagination metadata of the task listing, versions of single tasks, request IDs & the
+        // deprecation notice of the pre-v1 routes
+        .expose_headers([
+            header::LINK,
+            HeaderName::from_static("deprecation"),
+            HeaderName::from_static("x-next-cursor"),
+            header::ETAG,
+            REQUEST_ID_HEADER,
+        ])
+        .max_age(86400)
+}
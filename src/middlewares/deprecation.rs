@@ -0,0 +1,27 @@
+use actix_web::{
+    guard::{self, Guard},
+    http::header,
+    middleware::DefaultHeaders,
+};
+
+// Marks the pre-v1 task routes as deprecated aliases of /api/v1 (draft-ietf-httpapi-deprecation-header)
+
+/// Paths of the pre-v1 task routes
+const DEPRECATED_PATHS: [&str; 5] = [
+    "/api/new",
+    "/api/all",
+    "/api/search",
+    "/api/update",
+    "/api/delete",
+];
+
+pub fn deprecated() -> DefaultHeaders {
+    DefaultHeaders::new()
+        .add(("Deprecation", "true"))
+        .add((header::LINK, "</api/v1/tasks>; rel=\"successor-version\""))
+}
+
+/// Only lets the deprecated aliases into their scope, so unknown paths stay plain 404s
+pub fn deprecated_paths() -> impl Guard {
+    guard::fn_guard(|ctx| DEPRECATED_PATHS.contains(&ctx.head().uri.path()))
+}
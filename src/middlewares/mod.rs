@@ -1,3 +1,6 @@
-pub mod auth;
-pub mod cors;
-pub mod security_headers;
+pub mod auth;
+pub mod cors;
+pub mod deprecation;
+pub mod policy;
+pub mod problem;
+pub mod security_headers;
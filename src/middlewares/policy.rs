@@ -0,0 +1,218 @@
+use crate::{errors::app_error::AppError, models::principal::AuthenticatedUser};
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    Error, HttpMessage,
+};
+use std::{
+    future::{ready, Future, Ready},
+    pin::Pin,
+    rc::Rc,
+};
+
+// Route level authorization based on the scopes & groups of the verified caller
+// Must run inside `Authorization`, which stores the `AuthenticatedUser` checked here
+
+pub const SCOPE_TASKS_READ: &str = "tasks:read";
+pub const SCOPE_TASKS_WRITE: &str = "tasks:write";
+pub const GROUP_ADMIN: &str = "admin";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Requirement {
+    Scope(String),
+    Group(String),
+}
+
+impl Requirement {
+    fn is_met_by(&self, user: &AuthenticatedUser) -> bool {
+        match self {
+            Self::Scope(scope) => user.has_scope(scope),
+            Self::Group(group) => user.in_group(group),
+        }
+    }
+}
+
+/// Grants access when the caller meets any one of the requirements
+/// e.g. `Policy::scope(SCOPE_TASKS_WRITE).or_group(GROUP_ADMIN)`
+#[derive(Debug, Clone)]
+pub struct Policy {
+    any_of: Vec<Requirement>,
+}
+
+impl Policy {
+    pub fn scope(scope: &str) -> Self {
+        Self {
+            any_of: vec![Requirement::Scope(scope.into())],
+        }
+    }
+
+    pub fn group(group: &str) -> Self {
+        Self {
+            any_of: vec![Requirement::Group(group.into())],
+        }
+    }
+
+    pub fn or_scope(mut self, scope: &str) -> Self {
+        self.any_of.push(Requirement::Scope(scope.into()));
+        self
+    }
+
+    pub fn or_group(mut self, group: &str) -> Self {
+        self.any_of.push(Requirement::Group(group.into()));
+        self
+    }
+
+    pub fn check(&self, user: &AuthenticatedUser) -> Result<(), AppError> {
+        if self.any_of.iter().any(|req| req.is_met_by(user)) {
+            return Ok(());
+        }
+        let required = self
+            .any_of
+            .iter()
+            .map(|req| match req {
+                Requirement::Scope(scope) => format!("scope '{}'", scope),
+                Requirement::Group(group) => format!("group '{}'", group),
+            })
+            .collect::<Vec<_>>()
+            .join(" or ");
+
+        Err(AppError::Forbidden(format!("Requires {}", required)))
+    }
+}
+
+impl<S: 'static, B> Transform<S, ServiceRequest> for Policy
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Error = Error;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+    type InitError = ();
+    type Response = ServiceResponse<B>;
+    type Transform = PolicyMiddleware<S>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(PolicyMiddleware {
+            service: Rc::new(service),
+            policy: Rc::new(self.clone()),
+        }))
+    }
+}
+
+/* Route macros only accept a type for `wrap`, so fixed policies are declared as unit structs
+e.g. `#[get("/all", wrap = "ReadTasks")]` */
+macro_rules! named_policy {
+    ($(#[$meta:meta])* $name:ident => $policy:expr) => {
+        $(#[$meta])*
+        pub struct $name;
+
+        impl<S: 'static, B> Transform<S, ServiceRequest> for $name
+        where
+            S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+            S::Future: 'static,
+            B: 'static,
+        {
+            type Error = Error;
+            type Future = Ready<Result<Self::Transform, Self::InitError>>;
+            type InitError = ();
+            type Response = ServiceResponse<B>;
+            type Transform = PolicyMiddleware<S>;
+
+            fn new_transform(&self, service: S) -> Self::Future {
+                $policy.new_transform(service)
+            }
+        }
+    };
+}
+
+named_policy!(
+    /// `tasks:read` scope or membership of `admin`
+    ReadTasks => Policy::scope(SCOPE_TASKS_READ).or_group(GROUP_ADMIN)
+);
+named_policy!(
+    /// `tasks:write` scope or membership of `admin`
+    WriteTasks => Policy::scope(SCOPE_TASKS_WRITE).or_group(GROUP_ADMIN)
+);
+named_policy!(
+    /// Membership of `admin`
+    Admin => Policy::group(GROUP_ADMIN)
+);
+
+pub struct PolicyMiddleware<S> {
+    service: Rc<S>,
+    policy: Rc<Policy>,
+}
+
+impl<S, B> Service<ServiceRequest> for PolicyMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+    type Response = ServiceResponse<B>;
+
+    fn poll_ready(
+        &self,
+        ctx: &mut core::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(ctx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let svc = self.service.clone();
+        let policy = self.policy.clone();
+
+        Box::pin(async move {
+            // A missing principal means the route isn't wrapped by `Authorization` at all
+            let user = req
+                .extensions()
+                .get::<AuthenticatedUser>()
+                .cloned()
+                .ok_or(AppError::AuthNotFound("Request isn't authenticated".into()))?;
+            policy.check(&user)?;
+
+            let fut = svc.call(req);
+            let res = fut.await?;
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn user(groups: &[&str], scopes: &[&str]) -> AuthenticatedUser {
+        AuthenticatedUser {
+            sub: "abc-123".into(),
+            username: None,
+            groups: groups.iter().map(|g| g.to_string()).collect(),
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+            expires_at: Some(Utc::now()),
+            api_key_id: None,
+            token_id: None,
+            issued_at: None,
+        }
+    }
+
+    #[test]
+    fn test_any_requirement_grants_access() {
+        let policy = Policy::scope(SCOPE_TASKS_WRITE).or_group(GROUP_ADMIN);
+        assert!(policy.check(&user(&[], &[SCOPE_TASKS_WRITE])).is_ok());
+        assert!(policy.check(&user(&[GROUP_ADMIN], &[])).is_ok());
+        assert!(policy
+            .check(&user(&["staff"], &[SCOPE_TASKS_READ]))
+            .is_err());
+    }
+
+    #[test]
+    fn test_denial_is_forbidden() {
+        let err = Policy::group(GROUP_ADMIN)
+            .check(&user(&[], &[SCOPE_TASKS_READ]))
+            .unwrap_err();
+        assert!(matches!(err, AppError::Forbidden(msg) if msg == "Requires group 'admin'"));
+    }
+}
@@ -0,0 +1,159 @@
+use crate::errors::app_error::{AppError, AppErrorResponse, PROBLEM_CONTENT_TYPE};
+use actix_web::{
+    body::{BoxBody, EitherBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    error::InternalError,
+    http::header::{self, Header, HeaderMap, HeaderName, HeaderValue, Quality},
+    Error, HttpMessage, HttpRequest,
+};
+use std::{
+    future::{ready, Future, Ready},
+    pin::Pin,
+    rc::Rc,
+};
+use uuid::Uuid;
+
+// Tags every request with an ID (taken from a well-formed `X-Request-Id` or generated) & renders
+// `AppError`s as RFC 7807 problem details carrying that ID
+// Clients accepting application/json but not application/problem+json still get the legacy
+// `{code, message}` body until they have migrated
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+const MAX_REQUEST_ID_LEN: usize = 128;
+const JSON_CONTENT_TYPE: &str = "application/json";
+
+/// ID of the request, stored in the request extensions
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+fn request_id(req: &ServiceRequest) -> String {
+    req.headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= MAX_REQUEST_ID_LEN
+                && id
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b"-_.".contains(&b))
+        })
+        .map(String::from)
+        .unwrap_or_else(|| Uuid::new_v4().to_string())
+}
+
+fn prefers_legacy(req: &HttpRequest) -> bool {
+    let accept = match header::Accept::parse(req) {
+        Ok(accept) => accept,
+        Err(_) => return false,
+    };
+    let accepts = |essence: &str| {
+        accept
+            .iter()
+            .any(|mime| mime.item.essence_str() == essence && mime.quality > Quality::ZERO)
+    };
+
+    !accepts(PROBLEM_CONTENT_TYPE) && accepts(JSON_CONTENT_TYPE)
+}
+
+pub struct ProblemResponses;
+
+impl<S: 'static, B> Transform<S, ServiceRequest> for ProblemResponses
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Error = Error;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+    type InitError = ();
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Transform = ProblemMiddleware<S>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ProblemMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct ProblemMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for ProblemMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+    type Response = ServiceResponse<EitherBody<B>>;
+
+    fn poll_ready(
+        &self,
+        ctx: &mut core::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(ctx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let svc = self.service.clone();
+        let id = request_id(&req);
+        let legacy = prefers_legacy(req.request());
+        req.extensions_mut().insert(RequestId(id.clone()));
+
+        Box::pin(async move {
+            match svc.call(req).await {
+                Ok(res) => {
+                    let mut res = match render(res.response().error(), legacy, &id) {
+                        Some((content_type, body)) => res.map_body(|head, _| {
+                            head.headers_mut()
+                                .insert(header::CONTENT_TYPE, content_type);
+                            EitherBody::right(BoxBody::new(body))
+                        }),
+                        None => res.map_into_left_body(),
+                    };
+                    set_request_id(res.headers_mut(), &id);
+                    Ok(res)
+                }
+                // Errors returned by inner middlewares (e.g. Authorization) are rendered here
+                Err(e) => {
+                    let mut res = match render(Some(&e), legacy, &id) {
+                        Some((content_type, body)) => {
+                            let mut res = e.error_response().set_body(BoxBody::new(body));
+                            res.headers_mut().insert(header::CONTENT_TYPE, content_type);
+                            res
+                        }
+                        None => e.error_response(),
+                    };
+                    set_request_id(res.headers_mut(), &id);
+                    Err(InternalError::from_response(e, res).into())
+                }
+            }
+        })
+    }
+}
+
+// Only `AppError`s are re-rendered, other errors keep the body of their own `error_response`
+fn render(error: Option<&Error>, legacy: bool, id: &str) -> Option<(HeaderValue, String)> {
+    let error = error?.as_error::<AppError>()?;
+    let (content_type, body) = match legacy {
+        true => (
+            JSON_CONTENT_TYPE,
+            serde_json::to_string(&AppErrorResponse::new(error)),
+        ),
+        false => (
+            PROBLEM_CONTENT_TYPE,
+            serde_json::to_string(&error.problem(Some(id.into()))),
+        ),
+    };
+
+    Some((HeaderValue::from_static(content_type), body.ok()?))
+}
+
+fn set_request_id(headers: &mut HeaderMap, id: &str) {
+    if let Ok(value) = HeaderValue::from_str(id) {
+        headers.insert(REQUEST_ID_HEADER, value);
+    }
+}
@@ -0,0 +1,56 @@
+use crate::models::schema::api_keys;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+// Named API keys for scripts & CI, only the SHA-256 hash of the secret is ever stored
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = api_keys)]
+pub struct NewApiKey<'a> {
+    pub owner_id: &'a str,
+    pub name: &'a str,
+    pub prefix: &'a str,
+    pub key_hash: &'a str,
+    pub read_only: bool,
+    pub expires_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Queryable, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub id: uuid::Uuid,
+    pub owner_id: String,
+    pub name: String,
+    /// First characters of the secret, enough to tell keys apart in listings
+    pub prefix: String,
+    #[serde(skip)]
+    pub key_hash: String,
+    pub read_only: bool,
+    pub expires_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+/// Returned only once on creation, the secret can't be recovered afterwards
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreatedApiKey {
+    #[serde(flatten)]
+    pub key: ApiKey,
+    pub secret: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct CreateApiKey {
+    #[validate(length(
+        min = 1,
+        max = 60,
+        message = "Name must be between 1 and 60 characters long"
+    ))]
+    pub name: String,
+    #[validate(range(min = 1, max = 365, message = "Expiry must be between 1 and 365 days"))]
+    pub expires_in_days: Option<i64>,
+    #[serde(default)]
+    pub read_only: bool,
+}
@@ -0,0 +1,79 @@
+use crate::models::schema::{revoked_tokens, revoked_users};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use oauth2::{DeviceCode, EndUserVerificationUrl, UserCode};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+// Query & payload types of the OAuth2 login, device authorization, logout & token revocation
+// endpoints
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoginCallback {
+    pub code: Option<String>,
+    pub state: String,
+    // Set by the IdP instead of `code` when the login was denied or failed
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct RefreshTokenRequest {
+    #[validate(length(min = 1, message = "Refresh token must not be empty"))]
+    pub refresh_token: String,
+}
+
+/// Device authorization response (RFC 8628 section 3.2)
+#[derive(Debug, Serialize)]
+pub struct DeviceAuthorization {
+    pub device_code: DeviceCode,
+    pub user_code: UserCode,
+    pub verification_uri: EndUserVerificationUrl,
+    pub verification_uri_complete: String,
+    pub expires_in: u64,
+    pub interval: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeviceVerification {
+    // Without a code the verification page asks the user to enter one
+    pub user_code: Option<String>,
+}
+
+/// Form polled by the device at the token endpoint (RFC 8628 section 3.4)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeviceTokenRequest {
+    pub grant_type: String,
+    pub device_code: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LogoutQuery {
+    /// Revokes every token of the user issued so far instead of only the presented one
+    #[serde(default)]
+    pub all: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct RevokeTokens {
+    #[validate(length(min = 1, message = "Subject must not be empty"))]
+    pub sub: String,
+    /// Revokes only this token, otherwise every token of the subject issued so far
+    #[validate(length(min = 1, message = "Token ID must not be empty"))]
+    pub jti: Option<String>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = revoked_tokens)]
+pub struct NewRevokedToken<'a> {
+    pub jti: &'a str,
+    pub owner_id: &'a str,
+    pub expires_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = revoked_users)]
+pub struct NewRevokedUser<'a> {
+    pub owner_id: &'a str,
+    pub issued_before: NaiveDateTime,
+}
@@ -0,0 +1,28 @@
+use crate::models::schema::task_dependencies;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+// Dependencies between the user's tasks, a task is blocked while any of the tasks it depends on
+// isn't done yet
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = task_dependencies)]
+pub struct NewTaskDependency {
+    pub task_id: Uuid,
+    pub depends_on_id: Uuid,
+}
+
+#[derive(Debug, Clone, Queryable, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TaskDependency {
+    pub task_id: Uuid,
+    /// Task that has to be done before this one can be started or finished
+    pub depends_on_id: Uuid,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AddDependency {
+    pub depends_on_id: Uuid,
+}
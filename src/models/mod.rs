@@ -1,2 +1,9 @@
+pub mod api_key;
+pub mod auth;
+pub mod dependency;
+pub mod principal;
+pub mod reminder;
 pub mod schema;
+pub mod tag;
 pub mod task;
+pub mod user;
@@ -0,0 +1,200 @@
+use crate::{
+    errors::app_error::AppError,
+    middlewares::policy::{SCOPE_TASKS_READ, SCOPE_TASKS_WRITE},
+    models::api_key::ApiKey,
+};
+use actix_web::{dev::Payload, FromRequest, HttpMessage, HttpRequest};
+use chrono::{DateTime, Local, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::future::{ready, Ready};
+use uuid::Uuid;
+
+// Verified identity of the caller, inserted into the request extensions by the Authorization
+// middleware & extracted by handlers (the token is never re-parsed after verification)
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuthenticatedUser {
+    pub sub: String,
+    pub username: Option<String>,
+    pub groups: Vec<String>,
+    pub scopes: Vec<String>,
+    /// `None` only for API keys created without an expiry
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Set when the request was authenticated with an API key instead of a JWT
+    pub api_key_id: Option<Uuid>,
+    /// `jti` & `iat` of the JWT, consulted for revocations
+    pub token_id: Option<String>,
+    pub issued_at: Option<DateTime<Utc>>,
+}
+
+fn string_list(value: Option<&Value>) -> Vec<String> {
+    match value {
+        // OAuth2 `scope` is a space separated string, other list claims are arrays
+        Some(Value::String(s)) => s.split_whitespace().map(String::from).collect(),
+        Some(Value::Array(values)) => values
+            .iter()
+            .filter_map(Value::as_str)
+            .map(String::from)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+impl AuthenticatedUser {
+    /// Maps verified claims of both Cognito (`username`, `cognito:groups`) & generic OIDC
+    /// providers (`preferred_username`, `groups`, `scp`) to a principal
+    pub fn from_claims(claims: &Value) -> Result<Self, AppError> {
+        let sub = claims
+            .get("sub")
+            .and_then(Value::as_str)
+            .ok_or(AppError::JwtMissingClaim(
+                "No subject claim found in JWT".into(),
+            ))?
+            .to_owned();
+        let username = ["username", "cognito:username", "preferred_username"]
+            .iter()
+            .find_map(|claim| claims.get(claim).and_then(Value::as_str))
+            .map(String::from);
+        let groups = string_list(claims.get("cognito:groups").or(claims.get("groups")));
+        let scopes = string_list(claims.get("scope").or(claims.get("scp")));
+        let expires_at = claims
+            .get("exp")
+            .and_then(Value::as_i64)
+            .and_then(|exp| Utc.timestamp_opt(exp, 0).single())
+            .ok_or(AppError::JwtMissingClaim(
+                "No expiry claim found in JWT".into(),
+            ))?;
+        let token_id = claims.get("jti").and_then(Value::as_str).map(String::from);
+        let issued_at = claims
+            .get("iat")
+            .and_then(Value::as_i64)
+            .and_then(|iat| Utc.timestamp_opt(iat, 0).single());
+
+        Ok(Self {
+            sub,
+            username,
+            groups,
+            scopes,
+            expires_at: Some(expires_at),
+            api_key_id: None,
+            token_id,
+            issued_at,
+        })
+    }
+
+    /// API keys act on behalf of their owner but never carry groups, only task scopes
+    pub fn from_api_key(key: &ApiKey) -> Self {
+        let mut scopes = vec![SCOPE_TASKS_READ.to_owned()];
+        if !key.read_only {
+            scopes.push(SCOPE_TASKS_WRITE.to_owned());
+        }
+
+        Self {
+            sub: key.owner_id.clone(),
+            username: None,
+            groups: Vec::new(),
+            scopes,
+            // Timestamps are stored in local time like the rest of the tables
+            expires_at: key.expires_at.and_then(|exp| {
+                Local
+                    .from_local_datetime(&exp)
+                    .earliest()
+                    .map(|exp| exp.with_timezone(&Utc))
+            }),
+            api_key_id: Some(key.id),
+            token_id: None,
+            issued_at: None,
+        }
+    }
+
+    /// Cognito prefixes custom scopes with their resource server (`<identifier>/tasks:write`)
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|granted| {
+            granted == scope
+                || granted
+                    .rsplit_once('/')
+                    .is_some_and(|(_, name)| name == scope)
+        })
+    }
+
+    pub fn in_group(&self, group: &str) -> bool {
+        self.groups.iter().any(|g| g == group)
+    }
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = AppError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<AuthenticatedUser>()
+                .cloned()
+                .ok_or(AppError::AuthNotFound("Request isn't authenticated".into())),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_cognito_claims() {
+        let claims = json!({
+            "sub": "abc-123",
+            "username": "alice",
+            "cognito:groups": ["admin"],
+            "scope": "openid tasks:read tasks:write",
+            "jti": "token-1",
+            "iat": 1699996400,
+            "exp": 1700000000
+        });
+        let user = AuthenticatedUser::from_claims(&claims).unwrap();
+        assert_eq!(user.sub, "abc-123");
+        assert_eq!(user.username.as_deref(), Some("alice"));
+        assert_eq!(user.groups, vec!["admin"]);
+        assert_eq!(user.scopes, vec!["openid", "tasks:read", "tasks:write"]);
+        assert_eq!(user.expires_at.unwrap().timestamp(), 1700000000);
+        assert_eq!(user.api_key_id, None);
+        assert_eq!(user.token_id.as_deref(), Some("token-1"));
+        assert_eq!(user.issued_at.unwrap().timestamp(), 1699996400);
+    }
+
+    #[test]
+    fn test_oidc_claims() {
+        let claims = json!({
+            "sub": "def-456",
+            "preferred_username": "bob",
+            "groups": ["staff"],
+            "scp": ["tasks:read"],
+            "exp": 1700000000
+        });
+        let user = AuthenticatedUser::from_claims(&claims).unwrap();
+        assert_eq!(user.username.as_deref(), Some("bob"));
+        assert_eq!(user.groups, vec!["staff"]);
+        assert_eq!(user.scopes, vec!["tasks:read"]);
+    }
+
+    #[test]
+    fn test_resource_server_scopes() {
+        let claims = json!({
+            "sub": "abc-123",
+            "scope": "https://api.zeronote.dev/tasks:read",
+            "exp": 1700000000
+        });
+        let user = AuthenticatedUser::from_claims(&claims).unwrap();
+        assert!(user.has_scope("tasks:read"));
+        assert!(!user.has_scope("tasks:write"));
+        assert!(!user.in_group("admin"));
+    }
+
+    #[test]
+    fn test_missing_subject() {
+        let claims = json!({"username": "alice", "exp": 1700000000});
+        assert!(AuthenticatedUser::from_claims(&claims).is_err());
+    }
+}
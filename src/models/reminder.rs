@@ -0,0 +1,144 @@
+use crate::models::schema::{reminder_deliveries, reminders};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
+
+// Reminders of tasks, either at a fixed time or a number of minutes before the task's due date.
+// They're delivered once by the reminder scheduler & can be snoozed or dismissed by their owners
+
+/// Reminders can be set at most 4 weeks before the due date
+pub const MAX_MINUTES_BEFORE_DUE: i32 = 40_320;
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = reminders)]
+pub struct NewReminder<'a> {
+    pub task_id: Uuid,
+    pub owner_id: &'a str,
+    pub remind_at: Option<DateTime<Utc>>,
+    pub minutes_before_due: Option<i32>,
+    pub email: Option<&'a str>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Queryable, QueryableByName, Serialize, Deserialize)]
+#[diesel(table_name = reminders)]
+pub struct Reminder {
+    pub id: Uuid,
+    pub task_id: Uuid,
+    pub owner_id: String,
+    /// `None` while relative to the due date of a task without one
+    pub remind_at: Option<DateTime<Utc>>,
+    /// Set for reminders that follow the task's due date
+    pub minutes_before_due: Option<i32>,
+    /// Address the reminder is emailed to, in one of the allowed domains, webhooks receive every
+    /// reminder
+    pub email: Option<String>,
+    pub fired_at: Option<DateTime<Utc>>,
+    pub dismissed_at: Option<DateTime<Utc>>,
+    #[serde(skip)]
+    pub claimed_until: Option<DateTime<Utc>>,
+    #[serde(skip)]
+    pub attempts: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Either `remind_at` or `minutes_before_due` has to be given
+#[derive(Debug, Serialize, Deserialize, Validate)]
+#[validate(schema(function = "validate_reminder_time"))]
+pub struct CreateReminder {
+    #[validate(custom = "validate_future")]
+    pub remind_at: Option<DateTime<Utc>>,
+    #[validate(range(
+        min = 0,
+        max = "MAX_MINUTES_BEFORE_DUE",
+        message = "Reminders can be set at most 4 weeks before the due date"
+    ))]
+    pub minutes_before_due: Option<i32>,
+    #[validate(email(message = "Invalid email address"))]
+    pub email: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct SnoozeReminder {
+    #[validate(range(
+        min = 1,
+        max = 10_080,
+        message = "Reminders can be snoozed for 1 minute to 1 week"
+    ))]
+    pub minutes: i64,
+}
+
+/// Channel that has accepted a firing of the reminder
+#[derive(Debug, Insertable)]
+#[diesel(table_name = reminder_deliveries)]
+pub struct NewReminderDelivery<'a> {
+    pub reminder_id: Uuid,
+    pub remind_at: DateTime<Utc>,
+    pub channel: &'a str,
+}
+
+/// Payload of a delivered reminder
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Notification {
+    pub reminder_id: Uuid,
+    pub task_id: Uuid,
+    pub owner_id: String,
+    pub title: String,
+    pub due_at: Option<DateTime<Utc>>,
+    pub remind_at: DateTime<Utc>,
+    #[serde(skip)]
+    pub email: Option<String>,
+}
+
+impl Notification {
+    /// Same for redeliveries of the reminder, so receivers can drop duplicates
+    pub fn idempotency_key(&self) -> String {
+        format!("{}:{}", self.reminder_id, self.remind_at.timestamp())
+    }
+}
+
+fn validate_future(remind_at: &DateTime<Utc>) -> Result<(), ValidationError> {
+    match *remind_at > Utc::now() {
+        true => Ok(()),
+        false => Err(ValidationError::new("Reminder must be in the future")),
+    }
+}
+
+fn validate_reminder_time(reminder: &CreateReminder) -> Result<(), ValidationError> {
+    match (reminder.remind_at, reminder.minutes_before_due) {
+        (Some(_), None) | (None, Some(_)) => Ok(()),
+        _ => Err(ValidationError::new(
+            "Either remind_at or minutes_before_due must be given",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_reminder_validation() {
+        let reminder = |remind_at, minutes_before_due| CreateReminder {
+            remind_at,
+            minutes_before_due,
+            email: None,
+        };
+        let later = Utc::now() + Duration::hours(1);
+        assert!(reminder(Some(later), None).validate().is_ok());
+        assert!(reminder(None, Some(30)).validate().is_ok());
+
+        let earlier = Utc::now() - Duration::hours(1);
+        for invalid in [
+            reminder(None, None),
+            reminder(Some(later), Some(30)),
+            reminder(Some(earlier), None),
+            reminder(None, Some(MAX_MINUTES_BEFORE_DUE + 1)),
+        ] {
+            assert!(invalid.validate().is_err(), "{:?}", invalid);
+        }
+    }
+}
@@ -2,11 +2,97 @@ pub mod sql_types {
     #[derive(diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "task_condition"))]
     pub struct TaskCondition;
+
+    #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "task_priority"))]
+    pub struct TaskPriority;
+}
+
+diesel::table! {
+    api_keys (id) {
+        id -> Uuid,
+        owner_id -> Varchar,
+        name -> Varchar,
+        prefix -> Varchar,
+        key_hash -> Varchar,
+        read_only -> Bool,
+        expires_at -> Nullable<Timestamp>,
+        last_used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    reminder_deliveries (reminder_id, remind_at, channel) {
+        reminder_id -> Uuid,
+        remind_at -> Timestamptz,
+        channel -> Varchar,
+        delivered_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    reminders (id) {
+        id -> Uuid,
+        task_id -> Uuid,
+        owner_id -> Varchar,
+        remind_at -> Nullable<Timestamptz>,
+        minutes_before_due -> Nullable<Int4>,
+        email -> Nullable<Varchar>,
+        fired_at -> Nullable<Timestamptz>,
+        dismissed_at -> Nullable<Timestamptz>,
+        claimed_until -> Nullable<Timestamptz>,
+        attempts -> Int4,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    revoked_tokens (jti) {
+        jti -> Varchar,
+        owner_id -> Varchar,
+        expires_at -> Timestamp,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    revoked_users (owner_id) {
+        owner_id -> Varchar,
+        issued_before -> Timestamp,
+    }
+}
+
+diesel::table! {
+    tags (id) {
+        id -> Uuid,
+        owner_id -> Varchar,
+        name -> Varchar,
+        color -> Nullable<Varchar>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    task_dependencies (task_id, depends_on_id) {
+        task_id -> Uuid,
+        depends_on_id -> Uuid,
+        created_at -> Timestamptz,
+    }
 }
 
+diesel::table! {
+    task_tags (task_id, tag_id) {
+        task_id -> Uuid,
+        tag_id -> Uuid,
+    }
+}
+
+// The generated `search_vector` column of tasks is left out, it's only read by the raw SQL of
+// `services::tasks::search`
 diesel::table! {
     use diesel::sql_types::*;
-    use super::sql_types::TaskCondition;
+    use super::sql_types::{TaskCondition, TaskPriority};
 
     tasks (id) {
         id -> Uuid,
@@ -16,5 +102,39 @@ diesel::table! {
         condition -> TaskCondition,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        version -> Int4,
+        deleted_at -> Nullable<Timestamp>,
+        due_at -> Nullable<Timestamptz>,
+        priority -> Nullable<TaskPriority>,
+        parent_id -> Nullable<Uuid>,
+        auto_complete -> Bool,
     }
 }
+
+diesel::table! {
+    users (id) {
+        id -> Uuid,
+        username -> Varchar,
+        password_hash -> Varchar,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::joinable!(reminder_deliveries -> reminders (reminder_id));
+diesel::joinable!(reminders -> tasks (task_id));
+diesel::joinable!(task_tags -> tags (tag_id));
+diesel::joinable!(task_tags -> tasks (task_id));
+
+diesel::allow_tables_to_appear_in_same_query!(
+    api_keys,
+    reminder_deliveries,
+    reminders,
+    revoked_tokens,
+    revoked_users,
+    tags,
+    task_dependencies,
+    task_tags,
+    tasks,
+    users,
+);
@@ -0,0 +1,96 @@
+use crate::models::schema::{tags, task_tags};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize};
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
+
+// Per-owner tags for grouping tasks by project or context, attached to tasks through `task_tags`
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = tags)]
+pub struct NewTag<'a> {
+    pub owner_id: &'a str,
+    pub name: &'a str,
+    pub color: Option<&'a str>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Queryable, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Tag {
+    pub id: Uuid,
+    pub owner_id: String,
+    pub name: String,
+    /// Hex color code, e.g. `#1e90ff`
+    pub color: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = task_tags)]
+pub struct NewTaskTag {
+    pub task_id: Uuid,
+    pub tag_id: Uuid,
+}
+
+/// Name & color of a tag, used both to create & to replace one
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct EditTag {
+    #[serde(deserialize_with = "trimmed")]
+    #[validate(length(
+        min = 1,
+        max = 30,
+        message = "Name must be between 1 and 30 characters long"
+    ))]
+    pub name: String,
+    #[validate(custom = "validate_color_str")]
+    pub color: Option<String>,
+}
+
+// Trimmed before validation, so blank names & queries are rejected as empty ones
+pub(crate) fn trimmed<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    String::deserialize(deserializer).map(|name| name.trim().to_owned())
+}
+
+fn validate_color_str(color_str: &str) -> Result<(), ValidationError> {
+    match color_str.len() == 7
+        && color_str.starts_with('#')
+        && color_str[1..].bytes().all(|b| b.is_ascii_hexdigit())
+    {
+        true => Ok(()),
+        false => Err(ValidationError::new(
+            "Color must be a hex color code, e.g. #1e90ff",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_name_validation() {
+        let tag: EditTag = serde_json::from_str(r#"{"name": "  work  "}"#).unwrap();
+        assert_eq!(tag.name, "work");
+        assert!(tag.validate().is_ok());
+
+        let padded = format!(r#"{{"name": " {} "}}"#, "a".repeat(30));
+        let tag: EditTag = serde_json::from_str(&padded).unwrap();
+        assert!(tag.validate().is_ok());
+        for invalid in [r#"{"name": "   "}"#, r#"{"name": ""}"#] {
+            let tag: EditTag = serde_json::from_str(invalid).unwrap();
+            assert!(tag.validate().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_color_validation() {
+        assert!(validate_color_str("#1E90ff").is_ok());
+        for invalid in ["1e90ff", "#1e90f", "#1e90fg", "#1e90ff0", "#ééé"] {
+            assert!(validate_color_str(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
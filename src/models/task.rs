@@ -1,27 +1,45 @@
-use crate::{errors::app_error::AppError, models::schema::tasks};
-use actix_web::error::JsonPayloadError;
-use chrono::NaiveDateTime;
+use crate::{
+    errors::app_error::{AppError, ProblemDetails},
+    models::{
+        schema::tasks,
+        tag::{trimmed, Tag},
+    },
+};
+use actix_web::{
+    error::JsonPayloadError,
+    http::{header::EntityTag, StatusCode},
+    ResponseError,
+};
+use base64::{
+    alphabet::URL_SAFE,
+    decode_engine, encode_engine,
+    engine::fast_portable::{FastPortable, NO_PAD},
+};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use chrono_tz::Tz;
 use diesel::prelude::*;
 use diesel_derive_enum::*;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::{fmt::Display, str::FromStr};
 use uuid::Uuid;
-use validator::{Validate, ValidationError};
+use validator::{Validate, ValidationError, ValidationErrors};
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, DbEnum)]
+const URL_SAFE_NO_PAD: FastPortable = FastPortable::from(&URL_SAFE, NO_PAD);
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+/// Larger limits requested by clients are lowered to this
+pub const MAX_PAGE_SIZE: i64 = 100;
+pub const MERGE_PATCH_CONTENT_TYPE: &str = "application/merge-patch+json";
+pub const MAX_TASK_TAGS: usize = 20;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, DbEnum)]
 #[DieselTypePath = "crate::models::schema::sql_types::TaskCondition"]
 pub enum TaskCondition {
+    #[default]
     Undone,
     Active,
     Done,
 }
 
-impl Default for TaskCondition {
-    fn default() -> Self {
-        TaskCondition::Undone
-    }
-}
-
 impl FromStr for TaskCondition {
     type Err = AppError;
 
@@ -49,6 +67,41 @@ impl Display for TaskCondition {
     }
 }
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, DbEnum)]
+#[DieselTypePath = "crate::models::schema::sql_types::TaskPriority"]
+pub enum TaskPriority {
+    Low,
+    Medium,
+    High,
+}
+
+impl FromStr for TaskPriority {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, AppError> {
+        match s.trim().to_lowercase().as_str() {
+            "low" => Ok(Self::Low),
+            "medium" => Ok(Self::Medium),
+            "high" => Ok(Self::High),
+            _ => Err(AppError::BadRequest("Invalid task priority".into())),
+        }
+    }
+}
+
+impl Display for TaskPriority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                TaskPriority::Low => "Low",
+                TaskPriority::Medium => "Medium",
+                TaskPriority::High => "High",
+            }
+        )
+    }
+}
+
 #[derive(Debug, Insertable, Serialize, Deserialize)]
 #[diesel(table_name = tasks)]
 pub struct NewTask<'a> {
@@ -58,9 +111,14 @@ pub struct NewTask<'a> {
     pub condition: TaskCondition,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub due_at: Option<DateTime<Utc>>,
+    pub priority: Option<TaskPriority>,
+    pub parent_id: Option<Uuid>,
+    pub auto_complete: bool,
 }
 
-#[derive(Debug, Queryable, AsChangeset, Serialize, Deserialize)]
+#[derive(Debug, Queryable, QueryableByName, AsChangeset, Serialize, Deserialize)]
+#[diesel(table_name = tasks)]
 pub struct Task {
     pub id: uuid::Uuid, // Requires uuid-ossp extension
     pub owner_id: String,
@@ -69,6 +127,77 @@ pub struct Task {
     pub condition: TaskCondition,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    /// Bumped on every update
+    pub version: i32,
+    /// Set while the task is in the trash
+    pub deleted_at: Option<NaiveDateTime>,
+    pub due_at: Option<DateTime<Utc>>,
+    pub priority: Option<TaskPriority>,
+    /// Task this one is a subtask of
+    pub parent_id: Option<Uuid>,
+    /// Marks the task done once all of its subtasks are
+    pub auto_complete: bool,
+}
+
+impl Task {
+    /// Strong validator of the task's representation, changes whenever the task is updated
+    pub fn etag(&self) -> EntityTag {
+        EntityTag::new_strong(self.version.to_string())
+    }
+}
+
+/// Task as returned by the API, with its tags embedded
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TaskWithTags {
+    #[serde(flatten)]
+    pub task: Task,
+    pub tags: Vec<Tag>,
+}
+
+/// Task with its subtasks nested recursively (`?include=children`)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TaskTree {
+    #[serde(flatten)]
+    pub task: TaskWithTags,
+    pub children: Vec<TaskTree>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Validate)]
+pub struct GetTaskQuery {
+    /// `children` embeds the whole subtree of the task
+    #[validate(custom = "validate_include_str")]
+    pub include: Option<String>,
+}
+
+impl GetTaskQuery {
+    pub fn include_children(&self) -> bool {
+        self.include.is_some()
+    }
+}
+
+/// Whether moving a task under a new parent would make it its own ancestor
+#[derive(Debug, QueryableByName)]
+pub struct CycleCheck {
+    #[diesel(sql_type = diesel::sql_types::Bool)]
+    pub creates_cycle: bool,
+}
+
+/// Versions of a task an update or delete is conditional on, taken from `If-Match`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum TaskPrecondition {
+    /// No `If-Match` or `If-Match: *`, the task only has to exist
+    #[default]
+    Any,
+    Versions(Vec<i32>),
+}
+
+impl TaskPrecondition {
+    pub fn matches(&self, task: &Task) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Versions(versions) => versions.contains(&task.version),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Validate)]
@@ -81,6 +210,18 @@ pub struct CreateTask {
     pub title: String,
     #[validate(length(min = 1, message = "Body must be at least 1 character long"))]
     pub body: String,
+    /// RFC 3339 timestamp, converted to UTC. Past due dates are accepted, e.g. for overdue tasks
+    pub due_at: Option<DateTime<Utc>>,
+    #[validate(custom = "validate_task_priority_str")]
+    pub priority: Option<String>,
+    /// IDs of the user's tags to attach
+    #[serde(default)]
+    #[validate(length(max = "MAX_TASK_TAGS", message = "Tasks can have at most {max} tags"))]
+    pub tags: Vec<Uuid>,
+    /// Creates the task as a subtask of another one of the user's tasks
+    pub parent_id: Option<Uuid>,
+    #[serde(default)]
+    pub auto_complete: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Validate)]
@@ -100,6 +241,221 @@ pub struct UpdateTask {
     pub body: String,
     #[validate(custom = "validate_task_cond_str")]
     pub condition: String,
+    /// Left as it is when omitted, like `priority`
+    pub due_at: Option<DateTime<Utc>>,
+    #[validate(custom = "validate_task_priority_str")]
+    pub priority: Option<String>,
+}
+
+impl UpdateTask {
+    pub fn into_changes(self) -> Result<(Uuid, TaskChanges), AppError> {
+        let id = Uuid::parse_str(&self.id).map_err(AppError::Uuid)?;
+        let changes = TaskChanges {
+            title: Some(self.title),
+            body: Some(self.body),
+            condition: Some(TaskCondition::from_str(&self.condition)?),
+            due_at: self.due_at.map(Some),
+            priority: self
+                .priority
+                .as_deref()
+                .map(|priority| TaskPriority::from_str(priority).map(Some))
+                .transpose()?,
+            ..Default::default()
+        };
+
+        Ok((id, changes))
+    }
+}
+
+/// Full replacement of a task (PUT /api/v1/tasks/{id}), `due_at` & `priority` are cleared when
+/// omitted while the tags, the parent & `auto_complete` are left as they are
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct ReplaceTask {
+    #[validate(length(
+        min = 1,
+        max = 60,
+        message = "Title must be between 1 and 60 characters long"
+    ))]
+    pub title: String,
+    #[validate(length(min = 1, message = "Body must be at least 1 character long"))]
+    pub body: String,
+    #[validate(custom = "validate_task_cond_str")]
+    pub condition: String,
+    pub due_at: Option<DateTime<Utc>>,
+    #[validate(custom = "validate_task_priority_str")]
+    pub priority: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(length(max = "MAX_TASK_TAGS", message = "Tasks can have at most {max} tags"))]
+    pub tags: Option<Vec<Uuid>>,
+    /// `null` moves the task to the top level
+    #[serde(
+        default,
+        deserialize_with = "nullable",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub parent_id: Option<Option<Uuid>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_complete: Option<bool>,
+}
+
+/// JSON Merge Patch (RFC 7396) of a task (PATCH /api/v1/tasks/{id}), omitted fields are left
+/// untouched & only the supplied ones are validated
+/// Only `due_at`, `priority` & `parent_id` can be removed with `null`, other `null` members are
+/// rejected along with unknown ones
+#[derive(Debug, Default, Serialize, Deserialize, Validate)]
+#[serde(deny_unknown_fields)]
+pub struct PatchTask {
+    #[serde(
+        default,
+        deserialize_with = "non_null",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[validate(length(
+        min = 1,
+        max = 60,
+        message = "Title must be between 1 and 60 characters long"
+    ))]
+    pub title: Option<String>,
+    #[serde(
+        default,
+        deserialize_with = "non_null",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[validate(length(min = 1, message = "Body must be at least 1 character long"))]
+    pub body: Option<String>,
+    #[serde(
+        default,
+        deserialize_with = "non_null",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[validate(custom = "validate_task_cond_str")]
+    pub condition: Option<String>,
+    #[serde(
+        default,
+        deserialize_with = "nullable",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub due_at: Option<Option<DateTime<Utc>>>,
+    #[serde(
+        default,
+        deserialize_with = "nullable",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[validate(custom = "validate_task_priority_str")]
+    pub priority: Option<Option<String>>,
+    /// Replaces the attached tags, like any other array of a merge patch
+    #[serde(
+        default,
+        deserialize_with = "non_null",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[validate(length(max = "MAX_TASK_TAGS", message = "Tasks can have at most {max} tags"))]
+    pub tags: Option<Vec<Uuid>>,
+    #[serde(
+        default,
+        deserialize_with = "nullable",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub parent_id: Option<Option<Uuid>>,
+    #[serde(
+        default,
+        deserialize_with = "non_null",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub auto_complete: Option<bool>,
+}
+
+// Only called for members present in the document, so `null` fails to deserialize as `T`
+fn non_null<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+// Tells `null` (`Some(None)`) apart from omitted members (`None`)
+fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// Changes made by an update, `None`s are left untouched (`updated_at` is always set)
+#[derive(Debug, Default)]
+pub struct TaskChanges {
+    pub title: Option<String>,
+    pub body: Option<String>,
+    pub condition: Option<TaskCondition>,
+    /// `Some(None)` clears the due date, like the priority
+    pub due_at: Option<Option<DateTime<Utc>>>,
+    pub priority: Option<Option<TaskPriority>>,
+    /// Replaces the attached tags
+    pub tags: Option<Vec<Uuid>>,
+    /// `Some(None)` moves the task to the top level
+    pub parent_id: Option<Option<Uuid>>,
+    pub auto_complete: Option<bool>,
+}
+
+impl TaskChanges {
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none()
+            && self.body.is_none()
+            && self.condition.is_none()
+            && self.due_at.is_none()
+            && self.priority.is_none()
+            && self.tags.is_none()
+            && self.parent_id.is_none()
+            && self.auto_complete.is_none()
+    }
+}
+
+impl TryFrom<ReplaceTask> for TaskChanges {
+    type Error = AppError;
+
+    fn try_from(task: ReplaceTask) -> Result<Self, AppError> {
+        Ok(Self {
+            title: Some(task.title),
+            body: Some(task.body),
+            condition: Some(TaskCondition::from_str(&task.condition)?),
+            due_at: Some(task.due_at),
+            priority: Some(
+                task.priority
+                    .as_deref()
+                    .map(TaskPriority::from_str)
+                    .transpose()?,
+            ),
+            tags: task.tags,
+            parent_id: task.parent_id,
+            auto_complete: task.auto_complete,
+        })
+    }
+}
+
+impl TryFrom<PatchTask> for TaskChanges {
+    type Error = AppError;
+
+    fn try_from(task: PatchTask) -> Result<Self, AppError> {
+        Ok(Self {
+            title: task.title,
+            body: task.body,
+            condition: task
+                .condition
+                .as_deref()
+                .map(TaskCondition::from_str)
+                .transpose()?,
+            due_at: task.due_at,
+            priority: task
+                .priority
+                .map(|priority| priority.as_deref().map(TaskPriority::from_str).transpose())
+                .transpose()?,
+            tags: task.tags,
+            parent_id: task.parent_id,
+            auto_complete: task.auto_complete,
+        })
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Validate)]
@@ -111,6 +467,352 @@ pub struct DeleteTask {
     pub id: String,
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchMode {
+    /// Every operation runs in one transaction, which is rolled back by the first failure
+    #[default]
+    AllOrNothing,
+    /// Failed operations are reported per item without affecting the others
+    BestEffort,
+}
+
+/// Single operation of a batch, tagged by `op`
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BatchOperation {
+    Create(CreateTask),
+    Update { id: Uuid, changes: PatchTask },
+    Delete { id: Uuid },
+    SetCondition { id: Uuid, condition: String },
+}
+
+impl Validate for BatchOperation {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        match self {
+            Self::Create(task) => task.validate(),
+            Self::Update { changes, .. } => changes.validate(),
+            Self::Delete { .. } => Ok(()),
+            Self::SetCondition { condition, .. } => {
+                validate_task_cond_str(condition).map_err(|e| {
+                    let mut errors = ValidationErrors::new();
+                    errors.add("condition", e);
+                    errors
+                })
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct BatchRequest {
+    #[serde(default)]
+    pub mode: BatchMode,
+    #[validate(length(
+        min = 1,
+        max = 100,
+        message = "Batches must contain between 1 and 100 operations"
+    ))]
+    pub operations: Vec<BatchOperation>,
+}
+
+/// Outcome of a single operation, `task` is left out for deletes & failed operations
+/// The task is as the operation left it, but its tags are loaded once the whole batch has run, so
+/// a task changed by several operations carries its final tags in each of their results
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchResult {
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task: Option<TaskWithTags>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ProblemDetails>,
+}
+
+impl BatchResult {
+    pub fn succeeded(status: StatusCode, task: Option<TaskWithTags>) -> Self {
+        Self {
+            status: status.as_u16(),
+            task,
+            error: None,
+        }
+    }
+
+    pub fn failed(error: &AppError) -> Self {
+        Self {
+            status: error.status_code().as_u16(),
+            task: None,
+            error: Some(error.problem(None)),
+        }
+    }
+}
+
+/// Results in the order of the requested operations
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchResponse {
+    pub results: Vec<BatchResult>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskSortField {
+    #[default]
+    CreatedAt,
+    UpdatedAt,
+    Title,
+    Condition,
+}
+
+impl FromStr for TaskSortField {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, AppError> {
+        match s.trim().to_lowercase().as_str() {
+            "created_at" => Ok(Self::CreatedAt),
+            "updated_at" => Ok(Self::UpdatedAt),
+            "title" => Ok(Self::Title),
+            "condition" => Ok(Self::Condition),
+            _ => Err(AppError::BadRequest("Invalid sort field".into())),
+        }
+    }
+}
+
+/// Listing views by due date, `today` & `this_week` are computed in the requested time zone
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DueView {
+    /// Due before now & not done yet
+    Overdue,
+    Today,
+    /// Monday to Sunday
+    ThisWeek,
+}
+
+impl DueView {
+    /// `date_trunc` field of the calendar period covered by the view, overdue tasks have none
+    pub fn period(&self) -> Option<&'static str> {
+        match self {
+            Self::Overdue => None,
+            Self::Today => Some("day"),
+            Self::ThisWeek => Some("week"),
+        }
+    }
+}
+
+impl FromStr for DueView {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, AppError> {
+        match s.trim().to_lowercase().as_str() {
+            "overdue" => Ok(Self::Overdue),
+            "today" => Ok(Self::Today),
+            "this_week" => Ok(Self::ThisWeek),
+            _ => Err(AppError::BadRequest("Invalid due view".into())),
+        }
+    }
+}
+
+/// Bounds of the current day or week in a time zone, the end is exclusive
+#[derive(Debug, QueryableByName)]
+pub struct DueWindow {
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    pub starts_at: DateTime<Utc>,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    pub ends_at: DateTime<Utc>,
+}
+
+/// Tasks are listed in this order with the ID as the tiebreaker
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskSort {
+    pub field: TaskSortField,
+    pub descending: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Validate)]
+#[validate(schema(function = "validate_date_ranges"))]
+pub struct TaskListQuery {
+    #[validate(range(min = 1, message = "Limit must be at least 1"))]
+    pub limit: Option<i64>,
+    /// `next_cursor` of the previous page, the first page is returned without one
+    pub cursor: Option<String>,
+    /// Comma separated list of conditions, e.g. `undone,active`
+    #[validate(custom = "validate_task_conds_str")]
+    pub condition: Option<String>,
+    pub created_after: Option<NaiveDateTime>,
+    pub created_before: Option<NaiveDateTime>,
+    pub updated_after: Option<NaiveDateTime>,
+    pub updated_before: Option<NaiveDateTime>,
+    #[validate(length(
+        min = 1,
+        max = 60,
+        message = "Title prefix must be between 1 and 60 characters long"
+    ))]
+    pub title_prefix: Option<String>,
+    /// One of `created_at` (default), `updated_at`, `title` & `condition`
+    #[validate(custom = "validate_sort_field_str")]
+    pub sort: Option<String>,
+    /// `asc` (default) or `desc`
+    #[validate(custom = "validate_sort_order_str")]
+    pub order: Option<String>,
+    /// Comma separated list of tag IDs, tasks need any of them unless `tag_match=all`
+    #[validate(custom = "validate_tag_ids_str")]
+    pub tags: Option<String>,
+    /// `any` (default) or `all`
+    #[validate(custom = "validate_tag_match_str")]
+    pub tag_match: Option<String>,
+    /// `true` lists the tasks waiting for unfinished dependencies & `false` the ones ready to be
+    /// worked on, combined with `condition=undone,active` to leave out the done ones
+    pub blocked: Option<bool>,
+    /// One of `overdue`, `today` & `this_week`
+    #[validate(custom = "validate_due_view_str")]
+    pub due: Option<String>,
+    /// IANA time zone of the user, e.g. `Europe/Helsinki` (defaults to UTC)
+    #[validate(custom = "validate_time_zone_str")]
+    pub tz: Option<String>,
+}
+
+impl TaskListQuery {
+    pub fn page_size(&self) -> i64 {
+        self.limit.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE)
+    }
+
+    pub fn conditions(&self) -> Result<Option<Vec<TaskCondition>>, AppError> {
+        self.condition
+            .as_deref()
+            .map(|conds| conds.split(',').map(TaskCondition::from_str).collect())
+            .transpose()
+    }
+
+    pub fn sort(&self) -> Result<TaskSort, AppError> {
+        let field = match &self.sort {
+            Some(field) => TaskSortField::from_str(field)?,
+            None => TaskSortField::default(),
+        };
+        let descending = match self.order.as_deref().map(str::trim) {
+            None => false,
+            Some(order) if order.eq_ignore_ascii_case("asc") => false,
+            Some(order) if order.eq_ignore_ascii_case("desc") => true,
+            Some(_) => return Err(AppError::BadRequest("Invalid sort order".into())),
+        };
+
+        Ok(TaskSort { field, descending })
+    }
+
+    pub fn tag_ids(&self) -> Result<Option<Vec<Uuid>>, AppError> {
+        self.tags
+            .as_deref()
+            .map(|ids| {
+                ids.split(',')
+                    .map(|id| Uuid::parse_str(id.trim()).map_err(AppError::Uuid))
+                    .collect()
+            })
+            .transpose()
+    }
+
+    pub fn due_view(&self) -> Result<Option<DueView>, AppError> {
+        self.due.as_deref().map(DueView::from_str).transpose()
+    }
+
+    pub fn time_zone(&self) -> &str {
+        self.tz.as_deref().map(str::trim).unwrap_or("UTC")
+    }
+
+    pub fn match_all_tags(&self) -> Result<bool, AppError> {
+        match self.tag_match.as_deref().map(str::trim) {
+            None => Ok(false),
+            Some(mode) if mode.eq_ignore_ascii_case("any") => Ok(false),
+            Some(mode) if mode.eq_ignore_ascii_case("all") => Ok(true),
+            Some(_) => Err(AppError::BadRequest("Invalid tag match".into())),
+        }
+    }
+}
+
+/// Keyset position of the last task of a page, carries every sortable value so that pages can be
+/// continued in any order. Handed out base64 encoded, so clients treat it as opaque
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskCursor {
+    pub sort: TaskSort,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub title: String,
+    pub condition: TaskCondition,
+    pub id: Uuid,
+}
+
+impl TaskCursor {
+    pub fn after(task: &Task, sort: TaskSort) -> Self {
+        Self {
+            sort,
+            created_at: task.created_at,
+            updated_at: task.updated_at,
+            title: task.title.clone(),
+            condition: task.condition,
+            id: task.id,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        // Serializing plain strings, timestamps & a UUID can't fail
+        let json = serde_json::to_vec(self).unwrap_or_default();
+        encode_engine(json, &URL_SAFE_NO_PAD)
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self, AppError> {
+        decode_engine(cursor, &URL_SAFE_NO_PAD)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .ok_or_else(|| AppError::BadRequest("Invalid cursor".into()))
+    }
+}
+
+#[derive(Debug)]
+pub struct TaskPage {
+    pub tasks: Vec<TaskWithTags>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct SearchQuery {
+    #[serde(deserialize_with = "trimmed")]
+    #[validate(length(
+        min = 1,
+        max = 200,
+        message = "Search query must be between 1 and 200 characters long"
+    ))]
+    pub q: String,
+    #[validate(range(min = 1, message = "Limit must be at least 1"))]
+    pub limit: Option<i64>,
+}
+
+impl SearchQuery {
+    pub fn page_size(&self) -> i64 {
+        self.limit.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE)
+    }
+}
+
+/// Row of the search query, the task's tags are added to it in `TaskSearchHit`
+#[derive(Debug, QueryableByName)]
+pub struct TaskSearchRow {
+    #[diesel(embed)]
+    pub task: Task,
+    #[diesel(sql_type = diesel::sql_types::Float)]
+    pub rank: f32,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub title_highlight: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub snippet: String,
+}
+
+/// Matched task with its rank & the matching terms wrapped in `<mark>` tags, the highlights are
+/// otherwise HTML escaped
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TaskSearchHit {
+    #[serde(flatten)]
+    pub task: TaskWithTags,
+    pub rank: f32,
+    pub title_highlight: String,
+    pub snippet: String,
+}
+
 fn validate_uuid_str(uuid_str: &str) -> Result<(), ValidationError> {
     match Uuid::parse_str(uuid_str) {
         Ok(_) => Ok(()),
@@ -125,6 +827,82 @@ fn validate_task_cond_str(cond_str: &str) -> Result<(), ValidationError> {
     }
 }
 
+fn validate_task_priority_str(priority_str: &str) -> Result<(), ValidationError> {
+    match TaskPriority::from_str(priority_str) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(ValidationError::new("Invalid task priority")),
+    }
+}
+
+fn validate_include_str(include_str: &str) -> Result<(), ValidationError> {
+    match include_str.trim().eq_ignore_ascii_case("children") {
+        true => Ok(()),
+        false => Err(ValidationError::new("Invalid include")),
+    }
+}
+
+fn validate_due_view_str(view_str: &str) -> Result<(), ValidationError> {
+    match DueView::from_str(view_str) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(ValidationError::new("Invalid due view")),
+    }
+}
+
+fn validate_time_zone_str(tz_str: &str) -> Result<(), ValidationError> {
+    match tz_str.trim().parse::<Tz>() {
+        Ok(_) => Ok(()),
+        Err(_) => Err(ValidationError::new("Unknown time zone")),
+    }
+}
+
+fn validate_task_conds_str(conds_str: &str) -> Result<(), ValidationError> {
+    conds_str.split(',').try_for_each(validate_task_cond_str)
+}
+
+fn validate_sort_field_str(field_str: &str) -> Result<(), ValidationError> {
+    match TaskSortField::from_str(field_str) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(ValidationError::new("Invalid sort field")),
+    }
+}
+
+fn validate_sort_order_str(order_str: &str) -> Result<(), ValidationError> {
+    match ["asc", "desc"].contains(&order_str.trim().to_lowercase().as_str()) {
+        true => Ok(()),
+        false => Err(ValidationError::new("Invalid sort order")),
+    }
+}
+
+fn validate_tag_ids_str(ids_str: &str) -> Result<(), ValidationError> {
+    match ids_str.split(',').count() <= MAX_TASK_TAGS {
+        true => ids_str
+            .split(',')
+            .try_for_each(|id| validate_uuid_str(id.trim())),
+        false => Err(ValidationError::new("Too many tags")),
+    }
+}
+
+fn validate_tag_match_str(mode_str: &str) -> Result<(), ValidationError> {
+    match ["any", "all"].contains(&mode_str.trim().to_lowercase().as_str()) {
+        true => Ok(()),
+        false => Err(ValidationError::new("Invalid tag match")),
+    }
+}
+
+fn validate_date_ranges(query: &TaskListQuery) -> Result<(), ValidationError> {
+    let ranges = [
+        (query.created_after, query.created_before),
+        (query.updated_after, query.updated_before),
+    ];
+    match ranges.iter().all(|range| match range {
+        (Some(after), Some(before)) => after < before,
+        _ => true,
+    }) {
+        true => Ok(()),
+        false => Err(ValidationError::new("Empty date range")),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -135,6 +913,140 @@ mod tests {
         assert!(validate_uuid_str(invalid_uuid).is_err());
     }
 
+    #[test]
+    fn test_task_cursor() {
+        let created_at = NaiveDateTime::from_timestamp_opt(1_668_000_000, 123_456_000).unwrap();
+        let cursor = TaskCursor {
+            sort: TaskSort {
+                field: TaskSortField::Title,
+                descending: true,
+            },
+            created_at,
+            updated_at: created_at,
+            title: "Task title".into(),
+            condition: TaskCondition::Active,
+            id: Uuid::new_v4(),
+        };
+        assert_eq!(TaskCursor::decode(&cursor.encode()).unwrap(), cursor);
+        assert!(TaskCursor::decode("not a cursor").is_err());
+        assert!(TaskCursor::decode(&encode_engine("{}", &URL_SAFE_NO_PAD)).is_err());
+    }
+
+    #[test]
+    fn test_list_query_validation() {
+        let query = TaskListQuery {
+            condition: Some("undone,done".into()),
+            sort: Some("updated_at".into()),
+            order: Some("DESC".into()),
+            tags: Some(format!("{}, {}", Uuid::nil(), Uuid::nil())),
+            tag_match: Some("All".into()),
+            ..Default::default()
+        };
+        assert!(query.validate().is_ok());
+        assert_eq!(
+            query.conditions().unwrap(),
+            Some(vec![TaskCondition::Undone, TaskCondition::Done])
+        );
+        assert_eq!(
+            query.sort().unwrap(),
+            TaskSort {
+                field: TaskSortField::UpdatedAt,
+                descending: true
+            }
+        );
+        assert_eq!(query.tag_ids().unwrap(), Some(vec![Uuid::nil(); 2]));
+        assert!(query.match_all_tags().unwrap());
+
+        let created_at = NaiveDateTime::from_timestamp_opt(1_668_000_000, 0);
+        let invalid = [
+            TaskListQuery {
+                condition: Some("undone,down".into()),
+                ..Default::default()
+            },
+            TaskListQuery {
+                sort: Some("body".into()),
+                ..Default::default()
+            },
+            TaskListQuery {
+                order: Some("up".into()),
+                ..Default::default()
+            },
+            TaskListQuery {
+                tags: Some("not-a-tag".into()),
+                ..Default::default()
+            },
+            TaskListQuery {
+                tag_match: Some("some".into()),
+                ..Default::default()
+            },
+            TaskListQuery {
+                due: Some("tomorrow".into()),
+                ..Default::default()
+            },
+            TaskListQuery {
+                created_after: created_at,
+                created_before: created_at,
+                ..Default::default()
+            },
+        ];
+        for query in invalid {
+            assert!(query.validate().is_err(), "{:?}", query);
+        }
+    }
+
+    #[test]
+    fn test_tag_limit() {
+        let tags = |count| -> Vec<Uuid> { (0..count).map(|_| Uuid::new_v4()).collect() };
+        let task =
+            |count| serde_json::json!({"title": "Task", "body": "Body", "tags": tags(count)});
+        let create: CreateTask = serde_json::from_value(task(MAX_TASK_TAGS)).unwrap();
+        assert!(create.validate().is_ok());
+        let create: CreateTask = serde_json::from_value(task(MAX_TASK_TAGS + 1)).unwrap();
+        assert!(create.validate().is_err());
+        let patch: PatchTask = serde_json::from_value(task(MAX_TASK_TAGS + 1)).unwrap();
+        assert!(patch.validate().is_err());
+    }
+
+    #[test]
+    fn test_merge_patch() {
+        let patch: PatchTask = serde_json::from_str(r#"{"condition": "done"}"#).unwrap();
+        assert!(patch.validate().is_ok());
+        let changes = TaskChanges::try_from(patch).unwrap();
+        assert_eq!(changes.condition, Some(TaskCondition::Done));
+        assert!(changes.title.is_none() && changes.body.is_none());
+        assert!(TaskChanges::default().is_empty());
+
+        let patch: PatchTask =
+            serde_json::from_str(r#"{"due_at": null, "priority": "high"}"#).unwrap();
+        assert!(patch.validate().is_ok());
+        let changes = TaskChanges::try_from(patch).unwrap();
+        assert_eq!(changes.due_at, Some(None));
+        assert_eq!(changes.priority, Some(Some(TaskPriority::High)));
+
+        let patch: PatchTask =
+            serde_json::from_str(r#"{"parent_id": null, "auto_complete": true}"#).unwrap();
+        let changes = TaskChanges::try_from(patch).unwrap();
+        assert_eq!(changes.parent_id, Some(None));
+        assert_eq!(changes.auto_complete, Some(true));
+
+        for invalid in [r#"{"title": ""}"#, r#"{"priority": "urgent"}"#] {
+            let patch: PatchTask = serde_json::from_str(invalid).unwrap();
+            assert!(patch.validate().is_err(), "{}", invalid);
+        }
+        for invalid in [
+            r#"{"title": null}"#,
+            r#"{"condition": null}"#,
+            r#"{"auto_complete": null}"#,
+            r#"{"owner_id": "someone-else"}"#,
+        ] {
+            assert!(
+                serde_json::from_str::<PatchTask>(invalid).is_err(),
+                "{}",
+                invalid
+            );
+        }
+    }
+
     #[test]
     fn test_task_cond_validation() {
         let valid_task_cond = "active";
@@ -0,0 +1,97 @@
+use crate::models::schema::users;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationError};
+
+// Accounts of the built-in local identity provider, passwords are stored as argon2 hashes
+// (the offline development mode mints tokens without accounts)
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = users)]
+pub struct NewUser<'a> {
+    pub username: &'a str,
+    pub password_hash: &'a str,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Queryable, Serialize, Deserialize)]
+pub struct User {
+    pub id: uuid::Uuid, // Used as the `sub` of issued tokens & thus as the owner of tasks
+    pub username: String,
+    #[serde(skip)]
+    pub password_hash: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct Credentials {
+    #[validate(
+        length(
+            min = 3,
+            max = 40,
+            message = "Username must be between 3 and 40 characters long"
+        ),
+        custom = "validate_username"
+    )]
+    pub username: String,
+    #[validate(length(
+        min = 8,
+        max = 128,
+        message = "Password must be between 8 and 128 characters long"
+    ))]
+    pub password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct ChangePassword {
+    pub current_password: String,
+    #[validate(length(
+        min = 8,
+        max = 128,
+        message = "Password must be between 8 and 128 characters long"
+    ))]
+    pub new_password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccessToken {
+    pub access_token: String,
+    pub token_type: String,
+    pub expires_in: u64,
+}
+
+/// Token minted in the offline development mode, scopes default to full access to tasks
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct DevTokenRequest {
+    #[validate(length(min = 1, message = "Subject must not be empty"))]
+    pub sub: String,
+    pub username: Option<String>,
+    #[serde(default)]
+    pub groups: Vec<String>,
+    pub scopes: Option<Vec<String>>,
+}
+
+fn validate_username(username: &str) -> Result<(), ValidationError> {
+    match username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+    {
+        true => Ok(()),
+        false => Err(ValidationError::new("Invalid username")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_username_validation() {
+        assert!(validate_username("alice.smith-2").is_ok());
+        assert!(validate_username("alice smith").is_err());
+        assert!(validate_username("alice@example.com").is_err());
+    }
+}
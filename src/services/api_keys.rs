@@ -0,0 +1,155 @@
+use crate::{
+    database::connection::Pool,
+    errors::app_error::AppError,
+    middlewares::policy::{Policy, GROUP_ADMIN, SCOPE_TASKS_WRITE},
+    models::{
+        api_key::*,
+        principal::AuthenticatedUser,
+        schema::api_keys::{self, dsl::*},
+    },
+};
+use actix_web::web;
+use chrono::{Duration, Local};
+use diesel::{prelude::*, result::Error as DieselError};
+use openssl::{rand::rand_bytes, sha::sha256};
+use uuid::Uuid;
+
+// Minting, listing & revoking API keys as well as resolving a presented key to its owner
+
+/// Prefix of every API key, lets the Authorization middleware tell keys & JWTs apart
+pub const API_KEY_PREFIX: &str = "zn_";
+const DISPLAYED_PREFIX_LEN: usize = API_KEY_PREFIX.len() + 8;
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// The secrets carry 256 bits of entropy, so a fast unsalted hash is sufficient
+pub fn hash_secret(secret: &str) -> String {
+    to_hex(&sha256(secret.as_bytes()))
+}
+
+fn generate_secret() -> Result<String, AppError> {
+    let mut bytes = [0u8; 32];
+    rand_bytes(&mut bytes).map_err(|e| AppError::MissingConfig(e.to_string()))?;
+
+    Ok(API_KEY_PREFIX.to_owned() + &to_hex(&bytes))
+}
+
+// Keys may only be managed with a JWT, otherwise a leaked read-only key could mint writable ones
+fn require_token_auth(user: &AuthenticatedUser) -> Result<(), AppError> {
+    match user.api_key_id {
+        Some(_) => Err(AppError::Forbidden(
+            "API keys can't be used to manage API keys".into(),
+        )),
+        None => Ok(()),
+    }
+}
+
+pub fn create(
+    pool: web::Data<Pool>,
+    key: CreateApiKey,
+    user: AuthenticatedUser,
+) -> Result<CreatedApiKey, AppError> {
+    require_token_auth(&user)?;
+    if !key.read_only {
+        Policy::scope(SCOPE_TASKS_WRITE)
+            .or_group(GROUP_ADMIN)
+            .check(&user)?;
+    }
+    let mut conn = pool.get().map_err(AppError::DieselPool)?;
+    let cur_time = Local::now().naive_local();
+    let secret = generate_secret()?;
+
+    let new_key = NewApiKey {
+        owner_id: &user.sub,
+        name: &key.name,
+        prefix: &secret[..DISPLAYED_PREFIX_LEN],
+        key_hash: &hash_secret(&secret),
+        read_only: key.read_only,
+        expires_at: key
+            .expires_in_days
+            .map(|days| cur_time + Duration::days(days)),
+        created_at: cur_time,
+    };
+    let res = diesel::insert_into(api_keys::table)
+        .values(new_key)
+        .get_result(&mut conn)
+        .map_err(AppError::DieselResult)?;
+
+    Ok(CreatedApiKey { key: res, secret })
+}
+
+pub fn get_all(pool: web::Data<Pool>, user: AuthenticatedUser) -> Result<Vec<ApiKey>, AppError> {
+    require_token_auth(&user)?;
+    let mut conn = pool.get().map_err(AppError::DieselPool)?;
+    let keys = api_keys
+        .filter(api_keys::owner_id.eq(user.sub))
+        .order(api_keys::created_at.asc())
+        .get_results::<ApiKey>(&mut conn)
+        .map_err(AppError::DieselResult)?;
+
+    Ok(keys)
+}
+
+pub fn revoke(
+    pool: web::Data<Pool>,
+    key_uuid_str: String,
+    user: AuthenticatedUser,
+) -> Result<usize, AppError> {
+    require_token_auth(&user)?;
+    let mut conn = pool.get().map_err(AppError::DieselPool)?;
+
+    let res = diesel::delete(
+        api_keys::table
+            .filter(
+                api_keys::id.eq(Uuid::parse_str(key_uuid_str.as_str()).map_err(AppError::Uuid)?),
+            )
+            .filter(api_keys::owner_id.eq(user.sub)),
+    )
+    .execute(&mut conn)
+    .map_err(AppError::DieselResult)?;
+
+    Ok(res)
+}
+
+/// Resolves a presented key to its owner, recording when the key was last used
+pub fn authenticate(pool: web::Data<Pool>, secret: &str) -> Result<AuthenticatedUser, AppError> {
+    let mut conn = pool.get().map_err(AppError::DieselPool)?;
+    let cur_time = Local::now().naive_local();
+
+    let key = diesel::update(
+        api_keys::table
+            .filter(api_keys::key_hash.eq(hash_secret(secret)))
+            .filter(
+                api_keys::expires_at
+                    .is_null()
+                    .or(api_keys::expires_at.gt(cur_time)),
+            ),
+    )
+    .set(api_keys::last_used_at.eq(cur_time))
+    .get_result::<ApiKey>(&mut conn)
+    .map_err(|e| match e {
+        DieselError::NotFound => AppError::AuthNotFound("Invalid or expired API key".into()),
+        e => AppError::DieselResult(e),
+    })?;
+
+    Ok(AuthenticatedUser::from_api_key(&key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_secrets() {
+        let secret = generate_secret().unwrap();
+        assert!(secret.starts_with(API_KEY_PREFIX));
+        assert_eq!(secret.len(), API_KEY_PREFIX.len() + 64);
+        assert_ne!(secret, generate_secret().unwrap());
+
+        let hash = hash_secret(&secret);
+        assert_eq!(hash.len(), 64);
+        assert!(!hash.contains(&secret[API_KEY_PREFIX.len()..]));
+    }
+}
@@ -0,0 +1,112 @@
+use crate::{errors::app_error::AppError, middlewares::auth::CognitoConfig};
+use oauth2::{
+    basic::{BasicClient, BasicTokenResponse},
+    reqwest::async_http_client,
+    AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken, PkceCodeChallenge,
+    PkceCodeVerifier, RedirectUrl, RefreshToken, Scope, TokenUrl,
+};
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+// Server-side OAuth2 authorization code flow with PKCE against the configured IdP (Cognito)
+
+const LOGIN_TTL: Duration = Duration::from_secs(600);
+
+/// A login in progress, optionally approving a device authorization once it completes
+pub struct PendingLogin {
+    pub verifier: PkceCodeVerifier,
+    pub device_code: Option<String>,
+}
+
+/// PKCE verifiers of logins in progress, keyed by their (single use) CSRF state
+#[derive(Default)]
+pub struct PendingLogins {
+    entries: Mutex<HashMap<String, (PendingLogin, Instant)>>,
+}
+
+impl PendingLogins {
+    pub fn insert(&self, state: &CsrfToken, login: PendingLogin) {
+        let mut entries = self.entries.lock().unwrap();
+        // Abandoned logins are dropped lazily whenever a new one starts
+        entries.retain(|_, (_, created)| created.elapsed() < LOGIN_TTL);
+        entries.insert(state.secret().clone(), (login, Instant::now()));
+    }
+
+    pub fn take(&self, state: &str) -> Result<PendingLogin, AppError> {
+        match self.entries.lock().unwrap().remove(state) {
+            Some((login, created)) if created.elapsed() < LOGIN_TTL => Ok(login),
+            _ => Err(AppError::OAuth2State(
+                "Unknown or expired login state".into(),
+            )),
+        }
+    }
+}
+
+pub fn oauth_client(config: &CognitoConfig) -> Result<BasicClient, AppError> {
+    let client = BasicClient::new(
+        ClientId::new(config.client_id.clone()),
+        Some(ClientSecret::new(config.client_secret.clone())),
+        AuthUrl::new(config.auth_url.clone()).map_err(AppError::Oauth2Parse)?,
+        Some(TokenUrl::new(config.token_url.clone()).map_err(AppError::Oauth2Parse)?),
+    )
+    .set_redirect_uri(
+        RedirectUrl::new(config.redirect_url.clone()).map_err(AppError::Oauth2Parse)?,
+    );
+
+    Ok(client)
+}
+
+/// Returns the IdP's authorization URL the user agent should be redirected to
+pub fn login_url(
+    config: &CognitoConfig,
+    pending: &PendingLogins,
+    device_code: Option<String>,
+) -> Result<String, AppError> {
+    let client = oauth_client(config)?;
+    let (challenge, verifier) = PkceCodeChallenge::new_random_sha256();
+    let (url, state) = client
+        .authorize_url(CsrfToken::new_random)
+        .add_scopes(config.scopes.iter().cloned().map(Scope::new))
+        .set_pkce_challenge(challenge)
+        .url();
+    pending.insert(
+        &state,
+        PendingLogin {
+            verifier,
+            device_code,
+        },
+    );
+
+    Ok(url.to_string())
+}
+
+pub async fn exchange_code(
+    config: &CognitoConfig,
+    code: String,
+    verifier: PkceCodeVerifier,
+) -> Result<BasicTokenResponse, AppError> {
+    let token = oauth_client(config)?
+        .exchange_code(AuthorizationCode::new(code))
+        .set_pkce_verifier(verifier)
+        .request_async(async_http_client)
+        .await
+        .map_err(AppError::OAuth2Token)?;
+
+    Ok(token)
+}
+
+pub async fn refresh(
+    config: &CognitoConfig,
+    refresh_token: String,
+) -> Result<BasicTokenResponse, AppError> {
+    let token = oauth_client(config)?
+        .exchange_refresh_token(&RefreshToken::new(refresh_token))
+        .request_async(async_http_client)
+        .await
+        .map_err(AppError::OAuth2Token)?;
+
+    Ok(token)
+}
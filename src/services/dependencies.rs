@@ -0,0 +1,174 @@
+use crate::{
+    database::connection::Pool,
+    errors::app_error::AppError,
+    models::{
+        dependency::*,
+        principal::AuthenticatedUser,
+        schema::{task_dependencies, tasks},
+        task::{CycleCheck, Task, TaskCondition, TaskWithTags},
+    },
+    services::tags::with_tags,
+};
+use actix_web::web;
+use diesel::{
+    prelude::*,
+    result::{DatabaseErrorKind, Error as DieselError},
+    sql_types::{Text, Uuid as SqlUuid},
+};
+use uuid::Uuid;
+
+// Dependencies between the user's tasks. A task is blocked while any of the tasks it depends on
+// isn't done, so it can't be started or finished. Dependencies in the trash don't block
+
+fn task_not_found() -> AppError {
+    AppError::NotFound("Task not found".into())
+}
+
+fn find_task(
+    conn: &mut PgConnection,
+    task_id: Uuid,
+    user: &AuthenticatedUser,
+) -> Result<Option<Task>, AppError> {
+    tasks::table
+        .filter(tasks::id.eq(task_id))
+        .filter(tasks::owner_id.eq(&user.sub))
+        .filter(tasks::deleted_at.is_null())
+        .first::<Task>(conn)
+        .optional()
+        .map_err(AppError::DieselResult)
+}
+
+/// Tasks the task depends on in the order the dependencies were added
+pub fn get_all(
+    pool: web::Data<Pool>,
+    task_id: Uuid,
+    user: AuthenticatedUser,
+) -> Result<Vec<TaskWithTags>, AppError> {
+    let mut conn = pool.get().map_err(AppError::DieselPool)?;
+    let task = find_task(&mut conn, task_id, &user)?.ok_or_else(task_not_found)?;
+
+    let dependencies = task_dependencies::table
+        .inner_join(tasks::table.on(tasks::id.eq(task_dependencies::depends_on_id)))
+        .filter(task_dependencies::task_id.eq(task.id))
+        .filter(tasks::deleted_at.is_null())
+        .order((task_dependencies::created_at, tasks::id))
+        .select(tasks::all_columns)
+        .get_results::<Task>(&mut conn)
+        .map_err(AppError::DieselResult)?;
+
+    with_tags(&mut conn, dependencies)
+}
+
+// Dependencies of the same user are added one at a time, otherwise two concurrent additions could
+// each pass the check & together create a cycle
+const LOCK_DEPENDENCIES_QUERY: &str =
+    "SELECT pg_advisory_xact_lock(hashtext('task-dependencies:' || $1))";
+
+// Walks the dependencies of the new dependency, reaching the task means a cycle
+const CYCLE_QUERY: &str = "
+    WITH RECURSIVE upstream AS (
+        SELECT depends_on_id AS id FROM task_dependencies WHERE task_id = $2
+        UNION
+        SELECT task_dependencies.depends_on_id FROM task_dependencies
+        JOIN upstream ON task_dependencies.task_id = upstream.id
+    )
+    SELECT $1 = $2 OR EXISTS (SELECT 1 FROM upstream WHERE id = $1) AS creates_cycle";
+
+pub fn add(
+    pool: web::Data<Pool>,
+    task_id: Uuid,
+    dependency: AddDependency,
+    user: AuthenticatedUser,
+) -> Result<TaskDependency, AppError> {
+    let mut conn = pool.get().map_err(AppError::DieselPool)?;
+
+    conn.transaction(|conn| {
+        diesel::sql_query(LOCK_DEPENDENCIES_QUERY)
+            .bind::<Text, _>(&user.sub)
+            .execute(conn)
+            .map_err(AppError::DieselResult)?;
+        let task = find_task(conn, task_id, &user)?.ok_or_else(task_not_found)?;
+        let depends_on = find_task(conn, dependency.depends_on_id, &user)?
+            .ok_or_else(|| AppError::UnprocessableEntity("Dependency task not found".into()))?;
+
+        let check = diesel::sql_query(CYCLE_QUERY)
+            .bind::<SqlUuid, _>(task.id)
+            .bind::<SqlUuid, _>(depends_on.id)
+            .get_result::<CycleCheck>(conn)
+            .map_err(AppError::DieselResult)?;
+        if check.creates_cycle {
+            return Err(AppError::UnprocessableEntity(
+                "Dependency would create a cycle".into(),
+            ));
+        }
+
+        let new_dependency = NewTaskDependency {
+            task_id: task.id,
+            depends_on_id: depends_on.id,
+        };
+        diesel::insert_into(task_dependencies::table)
+            .values(new_dependency)
+            .get_result(conn)
+            .map_err(|e| match e {
+                DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                    AppError::Conflict("Task already depends on the task".into())
+                }
+                e => AppError::DieselResult(e),
+            })
+    })
+}
+
+pub fn remove(
+    pool: web::Data<Pool>,
+    task_id: Uuid,
+    depends_on_id: Uuid,
+    user: AuthenticatedUser,
+) -> Result<usize, AppError> {
+    let mut conn = pool.get().map_err(AppError::DieselPool)?;
+    let task = find_task(&mut conn, task_id, &user)?.ok_or_else(task_not_found)?;
+
+    let removed = diesel::delete(
+        task_dependencies::table
+            .filter(task_dependencies::task_id.eq(task.id))
+            .filter(task_dependencies::depends_on_id.eq(depends_on_id)),
+    )
+    .execute(&mut conn)
+    .map_err(AppError::DieselResult)?;
+
+    match removed {
+        0 => Err(AppError::NotFound("Dependency not found".into())),
+        _ => Ok(removed),
+    }
+}
+
+/// Number of the task's dependencies that aren't done yet, they're share locked for the rest of the
+/// transaction so they can't be reopened before the task's change is committed
+pub fn unfinished(conn: &mut PgConnection, task_id: Uuid) -> Result<usize, AppError> {
+    let depends_on = task_dependencies::table
+        .filter(task_dependencies::task_id.eq(task_id))
+        .select(task_dependencies::depends_on_id);
+
+    tasks::table
+        .filter(tasks::id.eq_any(depends_on))
+        .filter(tasks::deleted_at.is_null())
+        .filter(tasks::condition.ne(TaskCondition::Done))
+        .select(tasks::id)
+        .for_share()
+        .load::<Uuid>(conn)
+        .map(|blocking| blocking.len())
+        .map_err(AppError::DieselResult)
+}
+
+/// Rejects starting or finishing the task while it's blocked
+pub fn check_unblocked(conn: &mut PgConnection, task_id: Uuid) -> Result<(), AppError> {
+    match unfinished(conn, task_id)? {
+        0 => Ok(()),
+        1 => Err(AppError::TaskBlocked(
+            "Task is blocked by 1 unfinished dependency".into(),
+        )),
+        count => Err(AppError::TaskBlocked(format!(
+            "Task is blocked by {} unfinished dependencies",
+            count
+        ))),
+    }
+}
@@ -0,0 +1,224 @@
+use crate::{
+    errors::app_error::AppError, middlewares::auth::CognitoConfig,
+    models::auth::DeviceAuthorization,
+};
+use oauth2::{
+    basic::{BasicErrorResponseType, BasicTokenResponse},
+    devicecode::DeviceCodeErrorResponseType,
+    CsrfToken, DeviceCode, EndUserVerificationUrl, UserCode,
+};
+use openssl::rand::rand_bytes;
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+// OAuth2 device authorization grant (RFC 8628) brokered on top of the PKCE login:
+// the device polls while its user logs in with the user code on a device that has a browser
+
+pub const DEVICE_CODE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+const DEVICE_CODE_TTL: Duration = Duration::from_secs(600);
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// Consonants only, so user codes can't spell words & aren't confused with digits
+const USER_CODE_CHARSET: &[u8] = b"BCDFGHJKLMNPQRSTVWXZ";
+const USER_CODE_LEN: usize = 8;
+
+enum DeviceStatus {
+    Pending,
+    Approved(Box<BasicTokenResponse>),
+    Denied,
+}
+
+struct DeviceGrant {
+    user_code: String,
+    status: DeviceStatus,
+    created: Instant,
+    last_poll: Option<Instant>,
+}
+
+/// Device authorizations in progress, keyed by their device code
+pub struct PendingDevices {
+    interval: Duration,
+    grants: Mutex<HashMap<String, DeviceGrant>>,
+}
+
+impl Default for PendingDevices {
+    fn default() -> Self {
+        Self::with_interval(POLL_INTERVAL)
+    }
+}
+
+fn format_user_code(chars: &str) -> String {
+    format!(
+        "{}-{}",
+        &chars[..USER_CODE_LEN / 2],
+        &chars[USER_CODE_LEN / 2..]
+    )
+}
+
+fn generate_user_code() -> Result<String, AppError> {
+    let charset_len = USER_CODE_CHARSET.len();
+    // Bytes past the largest multiple of the charset's length are skipped to avoid a modulo bias
+    let limit = 256 / charset_len * charset_len;
+    let mut chars = String::with_capacity(USER_CODE_LEN);
+    let mut bytes = [0u8; 16];
+    while chars.len() < USER_CODE_LEN {
+        rand_bytes(&mut bytes).map_err(|e| AppError::MissingConfig(e.to_string()))?;
+        let missing = USER_CODE_LEN - chars.len();
+        chars.extend(
+            bytes
+                .iter()
+                .map(|&b| b as usize)
+                .filter(|&b| b < limit)
+                .map(|b| USER_CODE_CHARSET[b % charset_len] as char)
+                .take(missing),
+        );
+    }
+
+    Ok(format_user_code(&chars))
+}
+
+/// Accepts user codes regardless of case, spacing & dashes
+fn normalize_user_code(input: &str) -> Option<String> {
+    let chars: String = input
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    (chars.len() == USER_CODE_LEN).then(|| format_user_code(&chars))
+}
+
+impl PendingDevices {
+    pub fn with_interval(interval: Duration) -> Self {
+        Self {
+            interval,
+            grants: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn start(&self, config: &CognitoConfig) -> Result<DeviceAuthorization, AppError> {
+        let verification_uri = EndUserVerificationUrl::new(config.device_verification_url.clone())
+            .map_err(AppError::Oauth2Parse)?;
+        let device_code = CsrfToken::new_random().secret().clone();
+
+        let mut grants = self.grants.lock().unwrap();
+        // Abandoned authorizations are dropped lazily whenever a new one starts
+        grants.retain(|_, grant| grant.created.elapsed() < DEVICE_CODE_TTL);
+        let user_code = loop {
+            let code = generate_user_code()?;
+            if !grants.values().any(|grant| grant.user_code == code) {
+                break code;
+            }
+        };
+        grants.insert(
+            device_code.clone(),
+            DeviceGrant {
+                user_code: user_code.clone(),
+                status: DeviceStatus::Pending,
+                created: Instant::now(),
+                last_poll: None,
+            },
+        );
+
+        let mut complete = verification_uri.url().clone();
+        complete
+            .query_pairs_mut()
+            .append_pair("user_code", &user_code);
+
+        Ok(DeviceAuthorization {
+            device_code: DeviceCode::new(device_code),
+            user_code: UserCode::new(user_code),
+            verification_uri,
+            verification_uri_complete: complete.to_string(),
+            expires_in: DEVICE_CODE_TTL.as_secs(),
+            interval: self.interval.as_secs(),
+        })
+    }
+
+    /// Resolves the user code entered on the verification page to its pending device code
+    pub fn device_code(&self, user_code: &str) -> Result<String, AppError> {
+        let unknown = || AppError::OAuth2State("Unknown or expired user code".into());
+        let user_code = normalize_user_code(user_code).ok_or_else(unknown)?;
+
+        self.grants
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(_, grant)| {
+                grant.user_code == user_code
+                    && matches!(grant.status, DeviceStatus::Pending)
+                    && grant.created.elapsed() < DEVICE_CODE_TTL
+            })
+            .map(|(device_code, _)| device_code.clone())
+            .ok_or_else(unknown)
+    }
+
+    fn set_status(&self, device_code: &str, status: DeviceStatus) {
+        if let Some(grant) = self.grants.lock().unwrap().get_mut(device_code) {
+            grant.status = status;
+        }
+    }
+
+    pub fn approve(&self, device_code: &str, token: BasicTokenResponse) {
+        self.set_status(device_code, DeviceStatus::Approved(Box::new(token)));
+    }
+
+    pub fn deny(&self, device_code: &str) {
+        self.set_status(device_code, DeviceStatus::Denied);
+    }
+
+    /// Hands out the tokens once the user has approved the device, the device code is single use
+    pub fn poll(
+        &self,
+        device_code: &str,
+    ) -> Result<BasicTokenResponse, DeviceCodeErrorResponseType> {
+        let mut grants = self.grants.lock().unwrap();
+        let grant = grants
+            .get_mut(device_code)
+            .ok_or(DeviceCodeErrorResponseType::Basic(
+                BasicErrorResponseType::InvalidGrant,
+            ))?;
+
+        if grant.created.elapsed() >= DEVICE_CODE_TTL {
+            grants.remove(device_code);
+            return Err(DeviceCodeErrorResponseType::ExpiredToken);
+        }
+        let too_fast = grant
+            .last_poll
+            .is_some_and(|last_poll| last_poll.elapsed() < self.interval);
+        grant.last_poll = Some(Instant::now());
+        if too_fast {
+            return Err(DeviceCodeErrorResponseType::SlowDown);
+        }
+
+        match grant.status {
+            DeviceStatus::Pending => Err(DeviceCodeErrorResponseType::AuthorizationPending),
+            _ => match grants.remove(device_code).map(|grant| grant.status) {
+                Some(DeviceStatus::Approved(token)) => Ok(*token),
+                _ => Err(DeviceCodeErrorResponseType::AccessDenied),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_codes() {
+        let code = generate_user_code().unwrap();
+        assert_eq!(code.len(), USER_CODE_LEN + 1);
+        assert_eq!(&code[4..5], "-");
+        assert!(code
+            .bytes()
+            .filter(|&b| b != b'-')
+            .all(|b| USER_CODE_CHARSET.contains(&b)));
+        assert_ne!(code, generate_user_code().unwrap());
+
+        assert_eq!(normalize_user_code(" bcdf ghjk"), Some("BCDF-GHJK".into()));
+        assert_eq!(normalize_user_code("BCDF-GHJK"), Some("BCDF-GHJK".into()));
+        assert_eq!(normalize_user_code("BCDF-GHJ"), None);
+    }
+}
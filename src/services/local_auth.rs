@@ -0,0 +1,154 @@
+use crate::{
+    database::connection::Pool,
+    errors::app_error::AppError,
+    middlewares::policy::{SCOPE_TASKS_READ, SCOPE_TASKS_WRITE},
+    models::{
+        principal::AuthenticatedUser,
+        schema::users::{self, dsl::*},
+        user::*,
+    },
+    utils::local_issuer::LocalIssuer,
+};
+use actix_web::web;
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use chrono::Local;
+use diesel::{
+    prelude::*,
+    result::{DatabaseErrorKind, Error as DieselError},
+};
+use openssl::rand::rand_bytes;
+use uuid::Uuid;
+
+// Registration, password login & password changes of the built-in local identity provider
+
+fn hash_password(password: &str) -> Result<String, AppError> {
+    let mut salt = [0u8; 16];
+    rand_bytes(&mut salt).map_err(|e| AppError::MissingConfig(e.to_string()))?;
+    let salt = SaltString::b64_encode(&salt).map_err(AppError::PasswordHash)?;
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(AppError::PasswordHash)?;
+
+    Ok(hash.to_string())
+}
+
+fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash)
+        .and_then(|hash| Argon2::default().verify_password(password.as_bytes(), &hash))
+        .is_ok()
+}
+
+pub fn register(pool: web::Data<Pool>, credentials: Credentials) -> Result<User, AppError> {
+    let mut conn = pool.get().map_err(AppError::DieselPool)?;
+    let cur_time = Local::now().naive_local();
+    let name = credentials.username.to_lowercase();
+
+    let new_user = NewUser {
+        username: &name,
+        password_hash: &hash_password(&credentials.password)?,
+        created_at: cur_time,
+        updated_at: cur_time,
+    };
+    let res = diesel::insert_into(users::table)
+        .values(new_user)
+        .get_result(&mut conn)
+        .map_err(|e| match e {
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                AppError::Conflict("Username is already taken".into())
+            }
+            e => AppError::DieselResult(e),
+        })?;
+
+    Ok(res)
+}
+
+pub fn login(
+    pool: web::Data<Pool>,
+    issuer: web::Data<LocalIssuer>,
+    credentials: Credentials,
+) -> Result<AccessToken, AppError> {
+    let mut conn = pool.get().map_err(AppError::DieselPool)?;
+    let user = users
+        .filter(users::username.eq(credentials.username.to_lowercase()))
+        .first::<User>(&mut conn)
+        .optional()
+        .map_err(AppError::DieselResult)?;
+
+    let user = match user {
+        Some(user) if verify_password(&credentials.password, &user.password_hash) => user,
+        Some(_) => return Err(invalid_credentials()),
+        None => {
+            // Hash anyway so unknown usernames can't be told apart by the response time
+            hash_password(&credentials.password)?;
+            return Err(invalid_credentials());
+        }
+    };
+    let token = issuer.issue(
+        &user.id.to_string(),
+        &user.username,
+        &[SCOPE_TASKS_READ, SCOPE_TASKS_WRITE],
+    )?;
+
+    Ok(AccessToken {
+        access_token: token,
+        token_type: "Bearer".into(),
+        expires_in: issuer.token_ttl(),
+    })
+}
+
+fn invalid_credentials() -> AppError {
+    AppError::AuthNotFound("Invalid username or password".into())
+}
+
+pub fn change_password(
+    pool: web::Data<Pool>,
+    passwords: ChangePassword,
+    user: AuthenticatedUser,
+) -> Result<(), AppError> {
+    if user.api_key_id.is_some() {
+        return Err(AppError::Forbidden(
+            "API keys can't be used to change passwords".into(),
+        ));
+    }
+    let mut conn = pool.get().map_err(AppError::DieselPool)?;
+    let not_local = || AppError::Forbidden("Not a local account".into());
+    let user_id = Uuid::parse_str(&user.sub).map_err(|_| not_local())?;
+
+    let account = users
+        .filter(users::id.eq(user_id))
+        .first::<User>(&mut conn)
+        .optional()
+        .map_err(AppError::DieselResult)?
+        .ok_or_else(not_local)?;
+    if !verify_password(&passwords.current_password, &account.password_hash) {
+        return Err(AppError::Forbidden("Current password is incorrect".into()));
+    }
+
+    diesel::update(users::table)
+        .filter(users::id.eq(user_id))
+        .set((
+            users::password_hash.eq(hash_password(&passwords.new_password)?),
+            users::updated_at.eq(Local::now().naive_local()),
+        ))
+        .execute(&mut conn)
+        .map_err(AppError::DieselResult)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_password_hashing() {
+        let hash = hash_password("correct horse").unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify_password("correct horse", &hash));
+        assert!(!verify_password("wrong horse", &hash));
+        assert_ne!(hash, hash_password("correct horse").unwrap());
+    }
+}
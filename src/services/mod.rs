@@ -1 +1,10 @@
+pub mod api_keys;
+pub mod auth;
+pub mod dependencies;
+pub mod device;
+pub mod local_auth;
+pub mod reminders;
+pub mod revocations;
+pub mod tags;
 pub mod tasks;
+pub mod trash;
@@ -0,0 +1,475 @@
+use crate::{
+    database::connection::Pool,
+    errors::app_error::AppError,
+    models::{
+        principal::AuthenticatedUser,
+        reminder::*,
+        schema::{reminder_deliveries, reminders, tasks},
+        task::Task,
+    },
+    utils::notifier::Notifiers,
+};
+use actix_web::{
+    rt::{self, time},
+    web,
+};
+use chrono::{DateTime, Utc};
+use diesel::{
+    prelude::*,
+    sql_types::{BigInt, Integer, Nullable, Timestamptz, Uuid as SqlUuid},
+};
+use log::{debug, warn};
+use std::{env, sync::Arc, time::Duration};
+use uuid::Uuid;
+
+// Reminders of the user's tasks & the scheduler delivering them. Due reminders are claimed with a
+// lease, so several servers never deliver the same one at once. Every channel that accepts a
+// reminder is recorded right away & skipped by retries, the reminder is marked as fired once all of
+// them have. Only a crash between a channel accepting the reminder & the record redelivers it to
+// that channel, with the same idempotency key
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(30);
+/// Renewed before every channel, so it only has to outlast a single delivery
+const DEFAULT_LEASE_SECS: i32 = 300;
+const CLAIM_BATCH_SIZE: i64 = 100;
+/// Failed deliveries are retried after 2, 4, 8 & 16 minutes before the reminder is given up
+const MAX_ATTEMPTS: i32 = 5;
+
+fn reminder_not_found() -> AppError {
+    AppError::NotFound("Reminder not found".into())
+}
+
+// Reminders of trashed tasks are as unreachable as the tasks themselves
+fn find_task(
+    conn: &mut PgConnection,
+    task_id: Uuid,
+    user: &AuthenticatedUser,
+) -> Result<Task, AppError> {
+    tasks::table
+        .filter(tasks::id.eq(task_id))
+        .filter(tasks::owner_id.eq(&user.sub))
+        .filter(tasks::deleted_at.is_null())
+        .first::<Task>(conn)
+        .optional()
+        .map_err(AppError::DieselResult)?
+        .ok_or_else(|| AppError::NotFound("Task not found".into()))
+}
+
+pub fn get_all(
+    pool: web::Data<Pool>,
+    task_id: Uuid,
+    user: AuthenticatedUser,
+) -> Result<Vec<Reminder>, AppError> {
+    let mut conn = pool.get().map_err(AppError::DieselPool)?;
+    find_task(&mut conn, task_id, &user)?;
+
+    reminders::table
+        .filter(reminders::task_id.eq(task_id))
+        .order((reminders::created_at, reminders::id))
+        .get_results::<Reminder>(&mut conn)
+        .map_err(AppError::DieselResult)
+}
+
+pub fn create(
+    pool: web::Data<Pool>,
+    task_id: Uuid,
+    reminder: CreateReminder,
+    user: AuthenticatedUser,
+) -> Result<Reminder, AppError> {
+    let mut conn = pool.get().map_err(AppError::DieselPool)?;
+    let task = find_task(&mut conn, task_id, &user)?;
+
+    let remind_at = match reminder.minutes_before_due {
+        Some(minutes) => {
+            let due_at = task.due_at.ok_or_else(|| {
+                AppError::UnprocessableEntity("Task doesn't have a due date".into())
+            })?;
+            Some(due_at - chrono::Duration::minutes(minutes.into()))
+        }
+        None => reminder.remind_at,
+    };
+    let new_reminder = NewReminder {
+        task_id: task.id,
+        owner_id: &user.sub,
+        remind_at,
+        minutes_before_due: reminder.minutes_before_due,
+        email: reminder.email.as_deref(),
+        created_at: Utc::now(),
+    };
+    diesel::insert_into(reminders::table)
+        .values(new_reminder)
+        .get_result(&mut conn)
+        .map_err(AppError::DieselResult)
+}
+
+pub fn delete(
+    pool: web::Data<Pool>,
+    reminder_id: Uuid,
+    user: AuthenticatedUser,
+) -> Result<usize, AppError> {
+    let mut conn = pool.get().map_err(AppError::DieselPool)?;
+
+    let deleted = diesel::delete(
+        reminders::table
+            .filter(reminders::id.eq(reminder_id))
+            .filter(reminders::owner_id.eq(user.sub)),
+    )
+    .execute(&mut conn)
+    .map_err(AppError::DieselResult)?;
+
+    match deleted {
+        0 => Err(reminder_not_found()),
+        _ => Ok(deleted),
+    }
+}
+
+/// Fires the reminder again after the given minutes, even if it has already fired or been
+/// dismissed. Snoozed reminders no longer follow the due date
+pub fn snooze(
+    pool: web::Data<Pool>,
+    reminder_id: Uuid,
+    snooze: SnoozeReminder,
+    user: AuthenticatedUser,
+) -> Result<Reminder, AppError> {
+    let mut conn = pool.get().map_err(AppError::DieselPool)?;
+
+    diesel::update(reminders::table)
+        .filter(reminders::id.eq(reminder_id))
+        .filter(reminders::owner_id.eq(user.sub))
+        .set((
+            reminders::remind_at.eq(Utc::now() + chrono::Duration::minutes(snooze.minutes)),
+            reminders::minutes_before_due.eq(None::<i32>),
+            reminders::fired_at.eq(None::<DateTime<Utc>>),
+            reminders::dismissed_at.eq(None::<DateTime<Utc>>),
+            reminders::claimed_until.eq(None::<DateTime<Utc>>),
+            reminders::attempts.eq(0),
+        ))
+        .get_result(&mut conn)
+        .optional()
+        .map_err(AppError::DieselResult)?
+        .ok_or_else(reminder_not_found)
+}
+
+/// Keeps the reminder from firing until it's snoozed
+pub fn dismiss(
+    pool: web::Data<Pool>,
+    reminder_id: Uuid,
+    user: AuthenticatedUser,
+) -> Result<Reminder, AppError> {
+    let mut conn = pool.get().map_err(AppError::DieselPool)?;
+
+    diesel::update(reminders::table)
+        .filter(reminders::id.eq(reminder_id))
+        .filter(reminders::owner_id.eq(user.sub))
+        .set(reminders::dismissed_at.eq(Utc::now()))
+        .get_result(&mut conn)
+        .optional()
+        .map_err(AppError::DieselResult)?
+        .ok_or_else(reminder_not_found)
+}
+
+// A NULL due date leaves the reminders without a time until the task gets a new one
+const RESCHEDULE_QUERY: &str = "
+    UPDATE reminders
+    SET remind_at = $2 - make_interval(mins => minutes_before_due),
+        fired_at = NULL, claimed_until = NULL, attempts = 0
+    WHERE task_id = $1 AND minutes_before_due IS NOT NULL AND dismissed_at IS NULL";
+
+/// Moves the reminders relative to the due date along with it & re-arms the fired ones
+pub fn reschedule(
+    conn: &mut PgConnection,
+    task_id: Uuid,
+    due_at: Option<DateTime<Utc>>,
+) -> Result<usize, AppError> {
+    diesel::sql_query(RESCHEDULE_QUERY)
+        .bind::<SqlUuid, _>(task_id)
+        .bind::<Nullable<Timestamptz>, _>(due_at)
+        .execute(conn)
+        .map_err(AppError::DieselResult)
+}
+
+// Reminders of done & trashed tasks are held back
+const CLAIM_QUERY: &str = "
+    UPDATE reminders
+    SET claimed_until = now() + $1 * interval '1 second', attempts = attempts + 1
+    WHERE id IN (
+        SELECT reminders.id FROM reminders JOIN tasks ON tasks.id = reminders.task_id
+        WHERE remind_at <= now() AND fired_at IS NULL AND dismissed_at IS NULL
+            AND (claimed_until IS NULL OR claimed_until <= now())
+            AND tasks.deleted_at IS NULL AND tasks.condition <> 'done'
+        ORDER BY remind_at
+        LIMIT $2
+        FOR UPDATE OF reminders SKIP LOCKED)
+    RETURNING *";
+
+// Only succeeds while the lease is still the scheduler's own, reclaiming, snoozing, rescheduling &
+// dismissing the reminder all change it
+const RENEW_QUERY: &str = "
+    UPDATE reminders
+    SET claimed_until = now() + $4 * interval '1 second'
+    WHERE id = $1 AND remind_at = $2 AND claimed_until = $3
+        AND fired_at IS NULL AND dismissed_at IS NULL
+    RETURNING *";
+
+// The reminder is retried once the backoff has passed, the last attempt gives it up
+const RETRY_QUERY: &str = "
+    UPDATE reminders
+    SET claimed_until = now() + power(2, attempts) * interval '1 minute',
+        fired_at = CASE WHEN attempts >= $4 THEN now() END
+    WHERE id = $1 AND remind_at = $2 AND claimed_until = $3 AND fired_at IS NULL";
+
+// Claimed reminder, the lease identifies the claim until it's renewed
+#[derive(Debug, Clone)]
+struct Claim {
+    notification: Notification,
+    lease: DateTime<Utc>,
+}
+
+// Renewed lease & the channels that have already accepted the reminder
+struct Renewal {
+    lease: DateTime<Utc>,
+    delivered: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ReminderScheduler {
+    interval: Duration,
+    lease_secs: i32,
+}
+
+impl Default for ReminderScheduler {
+    fn default() -> Self {
+        Self::new(DEFAULT_INTERVAL)
+    }
+}
+
+impl ReminderScheduler {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            lease_secs: DEFAULT_LEASE_SECS,
+        }
+    }
+
+    pub fn from_env() -> Result<Self, AppError> {
+        let interval =
+            env::var("REMINDER_INTERVAL").map_or(Ok(DEFAULT_INTERVAL), |secs| {
+                match secs.parse::<u64>() {
+                    Ok(secs) if secs > 0 => Ok(Duration::from_secs(secs)),
+                    _ => Err(AppError::MissingConfig(
+                        "REMINDER_INTERVAL must be a positive number of seconds".into(),
+                    )),
+                }
+            })?;
+
+        Ok(Self::new(interval))
+    }
+
+    fn claim(&self, pool: &Pool) -> Result<Vec<Claim>, AppError> {
+        let mut conn = pool.get().map_err(AppError::DieselPool)?;
+        let claimed = diesel::sql_query(CLAIM_QUERY)
+            .bind::<Integer, _>(self.lease_secs)
+            .bind::<BigInt, _>(CLAIM_BATCH_SIZE)
+            .load::<Reminder>(&mut conn)
+            .map_err(AppError::DieselResult)?;
+        let claimed_ids: Vec<Uuid> = claimed.iter().map(|reminder| reminder.id).collect();
+
+        Ok(reminders::table
+            .inner_join(tasks::table)
+            .filter(reminders::id.eq_any(claimed_ids))
+            .order(reminders::remind_at)
+            .load::<(Reminder, Task)>(&mut conn)
+            .map_err(AppError::DieselResult)?
+            .into_iter()
+            .filter_map(|(reminder, task)| {
+                Some(Claim {
+                    notification: Notification {
+                        reminder_id: reminder.id,
+                        task_id: task.id,
+                        owner_id: reminder.owner_id,
+                        title: task.title,
+                        due_at: task.due_at,
+                        remind_at: reminder.remind_at?,
+                        email: reminder.email,
+                    },
+                    lease: reminder.claimed_until?,
+                })
+            })
+            .collect())
+    }
+
+    // `None` once the lease is lost
+    fn renew(&self, pool: &Pool, claim: &Claim) -> Result<Option<Renewal>, AppError> {
+        let mut conn = pool.get().map_err(AppError::DieselPool)?;
+        let reminder_id = claim.notification.reminder_id;
+        let remind_at = claim.notification.remind_at;
+
+        conn.transaction(|conn| {
+            let lease = diesel::sql_query(RENEW_QUERY)
+                .bind::<SqlUuid, _>(reminder_id)
+                .bind::<Timestamptz, _>(remind_at)
+                .bind::<Timestamptz, _>(claim.lease)
+                .bind::<Integer, _>(self.lease_secs)
+                .get_result::<Reminder>(conn)
+                .optional()
+                .map_err(AppError::DieselResult)?
+                .and_then(|reminder| reminder.claimed_until);
+            let lease = match lease {
+                Some(lease) => lease,
+                None => return Ok(None),
+            };
+
+            let delivered = reminder_deliveries::table
+                .filter(reminder_deliveries::reminder_id.eq(reminder_id))
+                .filter(reminder_deliveries::remind_at.eq(remind_at))
+                .select(reminder_deliveries::channel)
+                .load::<String>(conn)
+                .map_err(AppError::DieselResult)?;
+
+            Ok(Some(Renewal { lease, delivered }))
+        })
+    }
+
+    fn record(pool: &Pool, notification: &Notification, channel: &str) -> Result<usize, AppError> {
+        let mut conn = pool.get().map_err(AppError::DieselPool)?;
+        let delivery = NewReminderDelivery {
+            reminder_id: notification.reminder_id,
+            remind_at: notification.remind_at,
+            channel,
+        };
+
+        diesel::insert_into(reminder_deliveries::table)
+            .values(delivery)
+            .on_conflict_do_nothing()
+            .execute(&mut conn)
+            .map_err(AppError::DieselResult)
+    }
+
+    // A lost lease leaves the reminder to whoever holds it now
+    fn complete(pool: &Pool, claim: &Claim, delivered: bool) -> Result<usize, AppError> {
+        let mut conn = pool.get().map_err(AppError::DieselPool)?;
+        let notification = &claim.notification;
+        let claimed = reminders::table
+            .filter(reminders::id.eq(notification.reminder_id))
+            .filter(reminders::remind_at.eq(notification.remind_at))
+            .filter(reminders::claimed_until.eq(claim.lease))
+            .filter(reminders::fired_at.is_null());
+
+        match delivered {
+            true => diesel::update(claimed)
+                .set((
+                    reminders::fired_at.eq(Utc::now()),
+                    reminders::claimed_until.eq(None::<DateTime<Utc>>),
+                ))
+                .execute(&mut conn),
+            false => diesel::sql_query(RETRY_QUERY)
+                .bind::<SqlUuid, _>(notification.reminder_id)
+                .bind::<Timestamptz, _>(notification.remind_at)
+                .bind::<Timestamptz, _>(claim.lease)
+                .bind::<Integer, _>(MAX_ATTEMPTS)
+                .execute(&mut conn),
+        }
+        .map_err(AppError::DieselResult)
+    }
+
+    // Goes through the channels one by one & renews the lease before each of them, so a slow
+    // batch can't outrun it. Returns whether every channel has accepted the reminder
+    async fn deliver(
+        &self,
+        pool: &Pool,
+        notifiers: &Notifiers,
+        mut claim: Claim,
+    ) -> Result<bool, AppError> {
+        let mut failed = false;
+        for notifier in notifiers.channels() {
+            let (scheduler, renew_pool, renew_claim) = (*self, pool.clone(), claim.clone());
+            let renewed = web::block(move || scheduler.renew(&renew_pool, &renew_claim))
+                .await
+                .map_err(AppError::WebBlocking)??;
+            let delivered = match renewed {
+                Some(renewal) => {
+                    claim.lease = renewal.lease;
+                    renewal.delivered
+                }
+                None => return Ok(false),
+            };
+            let channel = notifier.channel().to_owned();
+            if delivered.contains(&channel) {
+                continue;
+            }
+
+            if let Err(e) = notifier.notify(&claim.notification).await {
+                warn!(target: "errors_file", "Failed to deliver reminder {} via {}: {}", claim.notification.reminder_id, channel, e);
+                failed = true;
+                continue;
+            }
+            let (record_pool, notification) = (pool.clone(), claim.notification.clone());
+            web::block(move || Self::record(&record_pool, &notification, &channel))
+                .await
+                .map_err(AppError::WebBlocking)??;
+        }
+
+        let complete_pool = pool.clone();
+        let completed = web::block(move || Self::complete(&complete_pool, &claim, !failed))
+            .await
+            .map_err(AppError::WebBlocking)??;
+
+        Ok(!failed && completed > 0)
+    }
+
+    /// Delivers the due reminders, returning how many were delivered
+    pub async fn run(&self, pool: &Pool, notifiers: &Notifiers) -> Result<usize, AppError> {
+        let scheduler = *self;
+        let claim_pool = pool.clone();
+        let claims = web::block(move || scheduler.claim(&claim_pool))
+            .await
+            .map_err(AppError::WebBlocking)??;
+
+        let mut delivered = 0;
+        for claim in claims {
+            delivered += self.deliver(pool, notifiers, claim).await? as usize;
+        }
+
+        Ok(delivered)
+    }
+
+    /// Spawns a task on the current runtime that delivers due reminders every interval, reminders
+    /// that came due while the server was down are delivered on the first tick
+    pub fn spawn(self, pool: Pool, notifiers: Arc<Notifiers>) {
+        rt::spawn(async move {
+            let mut interval = time::interval(self.interval);
+            loop {
+                interval.tick().await;
+                match self.run(&pool, &notifiers).await {
+                    Ok(delivered) => debug!("Delivered {} reminders", delivered),
+                    Err(e) => warn!(target: "errors_file", "Failed to deliver reminders: {}", e),
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scheduler_config() {
+        env::set_var("REMINDER_INTERVAL", "5");
+        let scheduler = ReminderScheduler::from_env().unwrap();
+        assert_eq!(scheduler.interval, Duration::from_secs(5));
+
+        for interval in ["0", "-5", "soon"] {
+            env::set_var("REMINDER_INTERVAL", interval);
+            assert!(
+                matches!(
+                    ReminderScheduler::from_env(),
+                    Err(AppError::MissingConfig(_))
+                ),
+                "{} seconds",
+                interval
+            );
+        }
+        env::remove_var("REMINDER_INTERVAL");
+    }
+}
@@ -0,0 +1,251 @@
+use crate::{
+    database::connection::Pool,
+    errors::app_error::AppError,
+    models::{
+        auth::*,
+        principal::AuthenticatedUser,
+        schema::{revoked_tokens, revoked_users},
+    },
+};
+use actix_web::web;
+use chrono::{DateTime, Duration as ChronoDuration, Local, NaiveDateTime, Timelike, Utc};
+use diesel::{pg::upsert::excluded, prelude::*};
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+// Revocation of single tokens (by `jti`) & of every token of a user issued before a point in time
+// Lookups are cached briefly, so other instances notice a revocation within the cache TTL
+// Revoked `jti`s are kept for the longest token lifetime, tokens valid for longer are rejected
+
+const DEFAULT_TTL: Duration = Duration::from_secs(30);
+/// How long revoked `jti`s are kept by default when the token's expiry isn't known
+/// (Cognito access tokens are valid for at most a day)
+const DEFAULT_TOKEN_LIFETIME_HOURS: i64 = 24;
+
+struct TtlMap<K, V> {
+    entries: Mutex<HashMap<K, (V, Instant)>>,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> TtlMap<K, V> {
+    fn new() -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn get_or_load(
+        &self,
+        key: &K,
+        ttl: Duration,
+        load: impl FnOnce() -> Result<V, AppError>,
+    ) -> Result<V, AppError> {
+        if let Some((value, cached)) = self.entries.lock().unwrap().get(key) {
+            if cached.elapsed() < ttl {
+                return Ok(value.clone());
+            }
+        }
+        // The lock isn't held during the query, concurrent misses may both load the same key
+        let value = load()?;
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (_, cached)| cached.elapsed() < ttl);
+        entries.insert(key.clone(), (value.clone(), Instant::now()));
+
+        Ok(value)
+    }
+
+    fn invalidate(&self, key: &K) {
+        self.entries.lock().unwrap().remove(key);
+    }
+}
+
+/// Registered in app data, the Authorization middleware checks every verified JWT against it
+pub struct RevocationCache {
+    ttl: Duration,
+    token_lifetime: ChronoDuration,
+    tokens: TtlMap<String, bool>,
+    users: TtlMap<String, Option<NaiveDateTime>>,
+}
+
+impl Default for RevocationCache {
+    fn default() -> Self {
+        Self::with_ttl(DEFAULT_TTL)
+    }
+}
+
+fn to_local(time: DateTime<Utc>) -> NaiveDateTime {
+    time.with_timezone(&Local).naive_local()
+}
+
+impl RevocationCache {
+    pub fn with_ttl(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            token_lifetime: ChronoDuration::hours(DEFAULT_TOKEN_LIFETIME_HOURS),
+            tokens: TtlMap::new(),
+            users: TtlMap::new(),
+        }
+    }
+
+    /// Keeps revoked `jti`s for at least the lifetime of an issuer's tokens, e.g. the local
+    /// issuer's `LOCAL_AUTH_TOKEN_TTL`
+    pub fn with_token_lifetime(mut self, lifetime: ChronoDuration) -> Self {
+        self.token_lifetime = self.token_lifetime.max(lifetime);
+        self
+    }
+
+    // Revocations by `jti` are purged once the token could have expired
+    fn revocation_expiry(&self) -> NaiveDateTime {
+        Local::now().naive_local() + self.token_lifetime
+    }
+
+    pub fn is_revoked(&self, pool: &Pool, user: &AuthenticatedUser) -> Result<bool, AppError> {
+        let issued_before = self.users.get_or_load(&user.sub, self.ttl, || {
+            let mut conn = pool.get().map_err(AppError::DieselPool)?;
+            revoked_users::table
+                .find(&user.sub)
+                .select(revoked_users::issued_before)
+                .first::<NaiveDateTime>(&mut conn)
+                .optional()
+                .map_err(AppError::DieselResult)
+        })?;
+        if let Some(issued_before) = issued_before {
+            // Tokens without `iat` can't be placed in time & are treated as revoked
+            match user.issued_at {
+                Some(iat) if to_local(iat) > issued_before => (),
+                _ => return Ok(true),
+            }
+        }
+
+        match &user.token_id {
+            // A revocation by `jti` wouldn't be kept long enough to cover the token
+            Some(_) if user.expires_at > Some(Utc::now() + self.token_lifetime) => Ok(true),
+            Some(jti) => self.tokens.get_or_load(jti, self.ttl, || {
+                let mut conn = pool.get().map_err(AppError::DieselPool)?;
+                diesel::select(diesel::dsl::exists(revoked_tokens::table.find(jti)))
+                    .get_result(&mut conn)
+                    .map_err(AppError::DieselResult)
+            }),
+            None => Ok(false),
+        }
+    }
+}
+
+fn revoke_token(
+    conn: &mut PgConnection,
+    cache: &RevocationCache,
+    owner: &str,
+    jti: &str,
+    expires_at: NaiveDateTime,
+) -> Result<(), AppError> {
+    let cur_time = Local::now().naive_local();
+    // Revocations of tokens that have expired by now are no longer needed
+    diesel::delete(revoked_tokens::table.filter(revoked_tokens::expires_at.lt(cur_time)))
+        .execute(conn)
+        .map_err(AppError::DieselResult)?;
+    diesel::insert_into(revoked_tokens::table)
+        .values(NewRevokedToken {
+            jti,
+            owner_id: owner,
+            expires_at,
+            created_at: cur_time,
+        })
+        .on_conflict_do_nothing()
+        .execute(conn)
+        .map_err(AppError::DieselResult)?;
+    cache.tokens.invalidate(&jti.to_owned());
+
+    Ok(())
+}
+
+fn revoke_user(
+    conn: &mut PgConnection,
+    cache: &RevocationCache,
+    owner: &str,
+) -> Result<(), AppError> {
+    // `iat` has a precision of seconds, so tokens issued within the current second are included
+    let cur_time = Local::now().naive_local();
+    let issued_before = cur_time.with_nanosecond(0).unwrap_or(cur_time);
+    diesel::insert_into(revoked_users::table)
+        .values(NewRevokedUser {
+            owner_id: owner,
+            issued_before,
+        })
+        .on_conflict(revoked_users::owner_id)
+        .do_update()
+        .set(revoked_users::issued_before.eq(excluded(revoked_users::issued_before)))
+        .execute(conn)
+        .map_err(AppError::DieselResult)?;
+    cache.users.invalidate(&owner.to_owned());
+
+    Ok(())
+}
+
+/// Revokes the presented token, or with `all` every token of the user issued so far
+pub fn logout(
+    pool: web::Data<Pool>,
+    cache: web::Data<RevocationCache>,
+    user: AuthenticatedUser,
+    all: bool,
+) -> Result<(), AppError> {
+    if user.api_key_id.is_some() {
+        return Err(AppError::Forbidden(
+            "API keys are revoked through /api/keys".into(),
+        ));
+    }
+    let mut conn = pool.get().map_err(AppError::DieselPool)?;
+
+    match (&user.token_id, all) {
+        (Some(jti), false) => {
+            let expires_at = user
+                .expires_at
+                .map(to_local)
+                .unwrap_or_else(|| cache.revocation_expiry());
+            revoke_token(&mut conn, &cache, &user.sub, jti, expires_at)
+        }
+        // Without a `jti` the token can only be revoked together with the user's other tokens
+        _ => revoke_user(&mut conn, &cache, &user.sub),
+    }
+}
+
+pub fn revoke(
+    pool: web::Data<Pool>,
+    cache: web::Data<RevocationCache>,
+    req: RevokeTokens,
+) -> Result<(), AppError> {
+    let mut conn = pool.get().map_err(AppError::DieselPool)?;
+
+    match &req.jti {
+        Some(jti) => {
+            let expires_at = cache.revocation_expiry();
+            revoke_token(&mut conn, &cache, &req.sub, jti, expires_at)
+        }
+        None => revoke_user(&mut conn, &cache, &req.sub),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ttl_map_caches_until_expiry() {
+        let map = TtlMap::<String, bool>::new();
+        let key = "jti".to_owned();
+        let ttl = Duration::from_millis(50);
+
+        assert!(!map.get_or_load(&key, ttl, || Ok(false)).unwrap());
+        assert!(
+            !map.get_or_load(&key, ttl, || Ok(true)).unwrap(),
+            "Cached value should be used within the TTL"
+        );
+        std::thread::sleep(ttl);
+        assert!(map.get_or_load(&key, ttl, || Ok(true)).unwrap());
+
+        map.invalidate(&key);
+        assert!(!map.get_or_load(&key, ttl, || Ok(false)).unwrap());
+    }
+}
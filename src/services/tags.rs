@@ -0,0 +1,201 @@
+use crate::{
+    database::connection::Pool,
+    errors::app_error::AppError,
+    models::{
+        principal::AuthenticatedUser,
+        schema::{tags, task_tags, tasks},
+        tag::*,
+        task::{Task, TaskWithTags},
+    },
+};
+use actix_web::web;
+use chrono::Local;
+use diesel::prelude::*;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+// Tags of the user & their links to tasks, task responses embed the tags through `with_tags`
+
+fn tag_not_found() -> AppError {
+    AppError::NotFound("Tag not found".into())
+}
+
+pub fn get_all(pool: web::Data<Pool>, user: AuthenticatedUser) -> Result<Vec<Tag>, AppError> {
+    let mut conn = pool.get().map_err(AppError::DieselPool)?;
+
+    tags::table
+        .filter(tags::owner_id.eq(user.sub))
+        .order((tags::name, tags::id))
+        .get_results::<Tag>(&mut conn)
+        .map_err(AppError::DieselResult)
+}
+
+pub fn get(pool: web::Data<Pool>, tag_id: Uuid, user: AuthenticatedUser) -> Result<Tag, AppError> {
+    let mut conn = pool.get().map_err(AppError::DieselPool)?;
+
+    tags::table
+        .filter(tags::id.eq(tag_id))
+        .filter(tags::owner_id.eq(user.sub))
+        .first::<Tag>(&mut conn)
+        .optional()
+        .map_err(AppError::DieselResult)?
+        .ok_or_else(tag_not_found)
+}
+
+pub fn create(
+    pool: web::Data<Pool>,
+    tag: EditTag,
+    user: AuthenticatedUser,
+) -> Result<Tag, AppError> {
+    let mut conn = pool.get().map_err(AppError::DieselPool)?;
+
+    let new_tag = NewTag {
+        owner_id: &user.sub,
+        name: &tag.name,
+        color: tag.color.as_deref(),
+        created_at: Local::now().naive_local(),
+    };
+    diesel::insert_into(tags::table)
+        .values(new_tag)
+        .get_result(&mut conn)
+        .map_err(AppError::DieselResult)
+}
+
+// Tasks embed their tags, so renaming or deleting a tag changes their representation & version
+fn bump_tagged_tasks(conn: &mut PgConnection, tag_id: Uuid) -> Result<usize, AppError> {
+    let tagged = task_tags::table
+        .filter(task_tags::tag_id.eq(tag_id))
+        .select(task_tags::task_id);
+
+    diesel::update(tasks::table.filter(tasks::id.eq_any(tagged)))
+        .set(tasks::version.eq(tasks::version + 1))
+        .execute(conn)
+        .map_err(AppError::DieselResult)
+}
+
+pub fn replace(
+    pool: web::Data<Pool>,
+    tag_id: Uuid,
+    tag: EditTag,
+    user: AuthenticatedUser,
+) -> Result<Tag, AppError> {
+    let mut conn = pool.get().map_err(AppError::DieselPool)?;
+
+    conn.transaction(|conn| {
+        let tag = diesel::update(tags::table)
+            .filter(tags::id.eq(tag_id))
+            .filter(tags::owner_id.eq(&user.sub))
+            .set((tags::name.eq(&tag.name), tags::color.eq(&tag.color)))
+            .get_result::<Tag>(conn)
+            .optional()
+            .map_err(AppError::DieselResult)?
+            .ok_or_else(tag_not_found)?;
+        bump_tagged_tasks(conn, tag.id)?;
+
+        Ok(tag)
+    })
+}
+
+/// Deletes the tag, detaching it from every task
+pub fn delete(
+    pool: web::Data<Pool>,
+    tag_id: Uuid,
+    user: AuthenticatedUser,
+) -> Result<usize, AppError> {
+    let mut conn = pool.get().map_err(AppError::DieselPool)?;
+
+    conn.transaction(|conn| {
+        let tag_id = tags::table
+            .filter(tags::id.eq(tag_id))
+            .filter(tags::owner_id.eq(&user.sub))
+            .select(tags::id)
+            .for_update()
+            .first::<Uuid>(conn)
+            .optional()
+            .map_err(AppError::DieselResult)?
+            .ok_or_else(tag_not_found)?;
+        bump_tagged_tasks(conn, tag_id)?;
+
+        diesel::delete(tags::table.filter(tags::id.eq(tag_id)))
+            .execute(conn)
+            .map_err(AppError::DieselResult)
+    })
+}
+
+/// Replaces the tags attached to the task, every tag has to belong to the user
+pub fn set_task_tags(
+    conn: &mut PgConnection,
+    task_id: Uuid,
+    tag_ids: &[Uuid],
+    user: &AuthenticatedUser,
+) -> Result<(), AppError> {
+    let mut tag_ids = tag_ids.to_vec();
+    tag_ids.sort();
+    tag_ids.dedup();
+
+    let owned = tags::table
+        .filter(tags::id.eq_any(&tag_ids))
+        .filter(tags::owner_id.eq(&user.sub))
+        .count()
+        .get_result::<i64>(conn)
+        .map_err(AppError::DieselResult)?;
+    if owned as usize != tag_ids.len() {
+        return Err(AppError::UnprocessableEntity("Tag not found".into()));
+    }
+
+    diesel::delete(task_tags::table.filter(task_tags::task_id.eq(task_id)))
+        .execute(conn)
+        .map_err(AppError::DieselResult)?;
+    let links: Vec<NewTaskTag> = tag_ids
+        .into_iter()
+        .map(|tag_id| NewTaskTag { task_id, tag_id })
+        .collect();
+    diesel::insert_into(task_tags::table)
+        .values(links)
+        .execute(conn)
+        .map_err(AppError::DieselResult)?;
+
+    Ok(())
+}
+
+/// Embeds the tags of the tasks, loaded with a single query for all of them
+pub fn with_tags(
+    conn: &mut PgConnection,
+    tasks_vec: Vec<Task>,
+) -> Result<Vec<TaskWithTags>, AppError> {
+    let task_ids: Vec<Uuid> = tasks_vec.iter().map(|task| task.id).collect();
+    let mut tags_by_task: HashMap<Uuid, Vec<Tag>> = HashMap::new();
+    task_tags::table
+        .inner_join(tags::table)
+        .filter(task_tags::task_id.eq_any(task_ids))
+        .order((tags::name, tags::id))
+        .select((task_tags::task_id, tags::all_columns))
+        .load::<(Uuid, Tag)>(conn)
+        .map_err(AppError::DieselResult)?
+        .into_iter()
+        .for_each(|(task_id, tag)| tags_by_task.entry(task_id).or_default().push(tag));
+
+    Ok(tasks_vec
+        .into_iter()
+        .map(|task| TaskWithTags {
+            // A task can be listed more than once, e.g. when a batch updates it twice
+            tags: tags_by_task.get(&task.id).cloned().unwrap_or_default(),
+            task,
+        })
+        .collect())
+}
+
+pub fn with_task_tags(conn: &mut PgConnection, task: Task) -> Result<TaskWithTags, AppError> {
+    let tags_vec = task_tags::table
+        .inner_join(tags::table)
+        .filter(task_tags::task_id.eq(task.id))
+        .order((tags::name, tags::id))
+        .select(tags::all_columns)
+        .load::<Tag>(conn)
+        .map_err(AppError::DieselResult)?;
+
+    Ok(TaskWithTags {
+        task,
+        tags: tags_vec,
+    })
+}
@@ -2,115 +2,742 @@ use crate::{
     database::connection::Pool,
     errors::app_error::AppError,
     models::{
-        schema::tasks::{self, dsl::*},
+        principal::AuthenticatedUser,
+        schema::{
+            task_dependencies, task_tags,
+            tasks::{self, dsl::*},
+        },
         task::*,
     },
+    services::{
+        dependencies, reminders,
+        tags::{set_task_tags, with_tags, with_task_tags},
+    },
+};
+use actix_web::{http::StatusCode, web};
+use chrono::{Local, Utc};
+use diesel::{
+    prelude::*,
+    sql_types::{BigInt, Text, Timestamp, Uuid as SqlUuid},
 };
-use actix_http::header::HeaderMap;
-use actix_web::web;
-use chrono::Local;
-use diesel::ExpressionMethods;
-use diesel::{query_dsl::methods::FilterDsl, RunQueryDsl};
-use jwt::{Header, RegisteredClaims, Token};
-use std::str::FromStr;
+use std::{collections::HashMap, str::FromStr};
 use uuid::Uuid;
+use validator::Validate;
+
+// Orders a boxed query by a column with the ID as the tiebreaker
+macro_rules! order_by {
+    ($query:expr, $column:expr, $descending:expr) => {
+        match $descending {
+            false => $query.order(($column.asc(), tasks::id.asc())),
+            true => $query.order(($column.desc(), tasks::id.desc())),
+        }
+    };
+}
+
+// Continues a boxed query after the cursor's position in the ordering of `order_by!`
+macro_rules! after_cursor {
+    ($query:expr, $column:expr, $value:expr, $cursor:expr) => {
+        match $cursor.sort.descending {
+            false => $query.filter(
+                $column
+                    .gt($value)
+                    .or($column.eq($value).and(tasks::id.gt($cursor.id))),
+            ),
+            true => $query.filter(
+                $column
+                    .lt($value)
+                    .or($column.eq($value).and(tasks::id.lt($cursor.id))),
+            ),
+        }
+    };
+}
+
+/// Escapes LIKE wildcards, so the prefix is matched literally
+fn like_prefix(prefix: &str) -> String {
+    let escaped = prefix
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+
+    escaped + "%"
+}
+
+// Midnight in the time zone, so the day or week follows its DST changes
+// The time zone has already been checked by `TaskListQuery`'s validation
+const DUE_WINDOW_QUERY: &str = "
+    SELECT date_trunc($2, now() AT TIME ZONE $1) AT TIME ZONE $1 AS starts_at,
+        (date_trunc($2, now() AT TIME ZONE $1) + ('1 ' || $2)::interval) AT TIME ZONE $1
+            AS ends_at";
+
+fn due_window(conn: &mut PgConnection, period: &str, tz: &str) -> Result<DueWindow, AppError> {
+    diesel::sql_query(DUE_WINDOW_QUERY)
+        .bind::<Text, _>(tz)
+        .bind::<Text, _>(period)
+        .get_result::<DueWindow>(conn)
+        .map_err(AppError::DieselResult)
+}
 
-fn extract_sub(headers: HeaderMap) -> Result<String, AppError> {
-    let bearer = headers
-        .get("Authorization")
-        .ok_or("Authorization header empty".to_owned())
-        .map_err(AppError::AuthNotFound)?;
-    let access_token = bearer.to_str().unwrap().split(" ").collect::<Vec<&str>>()[1];
-
-    /* Skipping signature verification is in this case acceptable
-    as the middleware does it before any endpoint handler is invoked */
-    let unverified: Token<Header, RegisteredClaims, _> =
-        Token::parse_unverified(access_token).map_err(AppError::JwtParse)?;
-    let sub = unverified
-        .claims()
-        .subject
-        .as_ref()
-        .ok_or("No subject claim found in JWT".to_owned())
-        .map_err(AppError::JwtMissingClaim)?
-        .clone();
-
-    Ok(sub)
-}
-
-pub fn get_all(pool: web::Data<Pool>, headers: HeaderMap) -> Result<Vec<Task>, AppError> {
+pub fn get_all(
+    pool: web::Data<Pool>,
+    list: TaskListQuery,
+    user: AuthenticatedUser,
+) -> Result<TaskPage, AppError> {
     let mut conn = pool.get().map_err(AppError::DieselPool)?;
-    let token_sub = extract_sub(headers)?;
-    let tasks_vec = tasks
-        .filter(tasks::owner_id.eq(token_sub))
-        .get_results::<Task>(&mut conn)?;
+    let page_size = list.page_size();
+    let sort = list.sort()?;
+    let mut query = tasks
+        .filter(tasks::owner_id.eq(user.sub))
+        .filter(tasks::deleted_at.is_null())
+        // One extra row tells whether there's a next page
+        .limit(page_size + 1)
+        .into_boxed();
+
+    if let Some(conds) = list.conditions()? {
+        query = query.filter(tasks::condition.eq_any(conds));
+    }
+    if let Some(after) = list.created_after {
+        query = query.filter(tasks::created_at.ge(after));
+    }
+    if let Some(before) = list.created_before {
+        query = query.filter(tasks::created_at.lt(before));
+    }
+    if let Some(after) = list.updated_after {
+        query = query.filter(tasks::updated_at.ge(after));
+    }
+    if let Some(before) = list.updated_before {
+        query = query.filter(tasks::updated_at.lt(before));
+    }
+    if let Some(prefix) = &list.title_prefix {
+        query = query.filter(tasks::title.ilike(like_prefix(prefix)));
+    }
+    if let Some(view) = list.due_view()? {
+        query = match view.period() {
+            None => query
+                .filter(tasks::due_at.lt(Utc::now()))
+                .filter(tasks::condition.ne(TaskCondition::Done)),
+            Some(period) => {
+                let window = due_window(&mut conn, period, list.time_zone())?;
+                query
+                    .filter(tasks::due_at.ge(window.starts_at))
+                    .filter(tasks::due_at.lt(window.ends_at))
+            }
+        };
+    }
+    if let Some(blocked) = list.blocked {
+        let blockers = diesel::alias!(tasks as blockers);
+        let blocked_ids = task_dependencies::table
+            .inner_join(
+                blockers.on(blockers
+                    .field(tasks::id)
+                    .eq(task_dependencies::depends_on_id)),
+            )
+            .filter(blockers.field(tasks::deleted_at).is_null())
+            .filter(blockers.field(tasks::condition).ne(TaskCondition::Done))
+            .select(task_dependencies::task_id);
+        query = match blocked {
+            true => query.filter(tasks::id.eq_any(blocked_ids)),
+            false => query.filter(tasks::id.ne_all(blocked_ids)),
+        };
+    }
+    if let Some(tag_ids) = list.tag_ids()? {
+        let tagged = |tag_ids| {
+            task_tags::table
+                .filter(task_tags::tag_id.eq_any(tag_ids))
+                .select(task_tags::task_id)
+        };
+        query = match list.match_all_tags()? {
+            false => query.filter(tasks::id.eq_any(tagged(tag_ids))),
+            true => tag_ids.into_iter().fold(query, |query, tag_id| {
+                query.filter(tasks::id.eq_any(tagged(vec![tag_id])))
+            }),
+        };
+    }
+
+    query = match sort.field {
+        TaskSortField::CreatedAt => order_by!(query, tasks::created_at, sort.descending),
+        TaskSortField::UpdatedAt => order_by!(query, tasks::updated_at, sort.descending),
+        TaskSortField::Title => order_by!(query, tasks::title, sort.descending),
+        TaskSortField::Condition => order_by!(query, tasks::condition, sort.descending),
+    };
+    if let Some(cursor) = &list.cursor {
+        let after = TaskCursor::decode(cursor)?;
+        if after.sort != sort {
+            return Err(AppError::BadRequest(
+                "Cursor doesn't match the requested sort order".into(),
+            ));
+        }
+        query = match sort.field {
+            TaskSortField::CreatedAt => {
+                after_cursor!(query, tasks::created_at, after.created_at, after)
+            }
+            TaskSortField::UpdatedAt => {
+                after_cursor!(query, tasks::updated_at, after.updated_at, after)
+            }
+            TaskSortField::Title => after_cursor!(query, tasks::title, after.title.clone(), after),
+            TaskSortField::Condition => {
+                after_cursor!(query, tasks::condition, after.condition, after)
+            }
+        };
+    }
+
+    let mut tasks_vec = query
+        .get_results::<Task>(&mut conn)
+        .map_err(AppError::DieselResult)?;
+    let next_cursor = match tasks_vec.len() as i64 > page_size {
+        true => {
+            tasks_vec.truncate(page_size as usize);
+            tasks_vec
+                .last()
+                .map(|last| TaskCursor::after(last, sort).encode())
+        }
+        false => None,
+    };
 
-    Ok(tasks_vec)
+    Ok(TaskPage {
+        tasks: with_tags(&mut conn, tasks_vec)?,
+        next_cursor,
+    })
 }
 
-pub fn create(
+// Full-text matches are ranked first, misspelled terms still match through trigram similarity
+// Title & body are HTML escaped before the matches are marked, so highlights are safe to render
+const SEARCH_QUERY: &str = "
+    SELECT id, owner_id, title, body, condition, created_at, updated_at, version, deleted_at,
+        due_at, priority, parent_id, auto_complete,
+        ts_rank(search_vector, query) + word_similarity($2, title) AS rank,
+        ts_headline('english', escaped_title, query,
+            'HighlightAll=true, StartSel=<mark>, StopSel=</mark>') AS title_highlight,
+        ts_headline('english', escaped_body, query,
+            'MaxFragments=2, MaxWords=20, MinWords=5, StartSel=<mark>, StopSel=</mark>') AS snippet
+    FROM tasks, websearch_to_tsquery('english', $2) query,
+        LATERAL (SELECT
+            replace(replace(replace(replace(replace(title,
+                '&', '&amp;'), '<', '&lt;'), '>', '&gt;'), '\"', '&quot;'), '''', '&#39;')
+                AS escaped_title,
+            replace(replace(replace(replace(replace(body,
+                '&', '&amp;'), '<', '&lt;'), '>', '&gt;'), '\"', '&quot;'), '''', '&#39;')
+                AS escaped_body) escaped
+    WHERE owner_id = $1 AND deleted_at IS NULL
+        AND (search_vector @@ query OR $2 <% title OR $2 <% body)
+    ORDER BY rank DESC, id
+    LIMIT $3";
+
+pub fn search(
     pool: web::Data<Pool>,
-    task: CreateTask,
-    headers: HeaderMap,
-) -> Result<Task, AppError> {
+    search: SearchQuery,
+    user: AuthenticatedUser,
+) -> Result<Vec<TaskSearchHit>, AppError> {
     let mut conn = pool.get().map_err(AppError::DieselPool)?;
+    let (tasks_vec, rows): (Vec<Task>, Vec<_>) = diesel::sql_query(SEARCH_QUERY)
+        .bind::<Text, _>(&user.sub)
+        .bind::<Text, _>(&search.q)
+        .bind::<BigInt, _>(search.page_size())
+        .load::<TaskSearchRow>(&mut conn)
+        .map_err(AppError::DieselResult)?
+        .into_iter()
+        .map(|row| (row.task, (row.rank, row.title_highlight, row.snippet)))
+        .unzip();
+
+    Ok(with_tags(&mut conn, tasks_vec)?
+        .into_iter()
+        .zip(rows)
+        .map(|(task, (rank, title_highlight, snippet))| TaskSearchHit {
+            task,
+            rank,
+            title_highlight,
+            snippet,
+        })
+        .collect())
+}
+
+// The parent is share locked, so it can't be moved to the trash before its new subtask is added
+fn find_parent(
+    conn: &mut PgConnection,
+    parent: Uuid,
+    user: &AuthenticatedUser,
+) -> Result<Task, AppError> {
+    tasks
+        .filter(tasks::id.eq(parent))
+        .filter(tasks::owner_id.eq(&user.sub))
+        .filter(tasks::deleted_at.is_null())
+        .for_share()
+        .first::<Task>(conn)
+        .optional()
+        .map_err(AppError::DieselResult)?
+        .ok_or_else(|| AppError::UnprocessableEntity("Parent task not found".into()))
+}
+
+// Moves of the same user are serialized, otherwise two concurrent moves could each pass the
+// check & together create a cycle
+const LOCK_MOVES_QUERY: &str = "SELECT pg_advisory_xact_lock(hashtext('task-moves:' || $1))";
+
+// Walks up from the new parent, the task being among its ancestors means a cycle
+const CYCLE_QUERY: &str = "
+    WITH RECURSIVE ancestors AS (
+        SELECT id, parent_id FROM tasks WHERE id = $2
+        UNION
+        SELECT tasks.id, tasks.parent_id FROM tasks JOIN ancestors ON tasks.id = ancestors.parent_id
+    )
+    SELECT EXISTS (SELECT 1 FROM ancestors WHERE id = $1) AS creates_cycle";
+
+fn check_move(
+    conn: &mut PgConnection,
+    task_id: Uuid,
+    parent: Uuid,
+    user: &AuthenticatedUser,
+) -> Result<(), AppError> {
+    diesel::sql_query(LOCK_MOVES_QUERY)
+        .bind::<Text, _>(&user.sub)
+        .execute(conn)
+        .map_err(AppError::DieselResult)?;
+    find_parent(conn, parent, user)?;
+    let check = diesel::sql_query(CYCLE_QUERY)
+        .bind::<SqlUuid, _>(task_id)
+        .bind::<SqlUuid, _>(parent)
+        .get_result::<CycleCheck>(conn)
+        .map_err(AppError::DieselResult)?;
+
+    match check.creates_cycle {
+        true => Err(AppError::UnprocessableEntity(
+            "Task can't be moved under itself or its subtasks".into(),
+        )),
+        false => Ok(()),
+    }
+}
+
+/// Marks the parent done once all of its remaining subtasks are, if it's set to auto-complete,
+/// & continues up the tree as far as the parents complete
+fn complete_parents(conn: &mut PgConnection, parent: Option<Uuid>) -> Result<(), AppError> {
+    let mut next = parent;
+    while let Some(parent) = next {
+        // Locked first, so concurrently completed siblings see each other's changes
+        let parent = tasks
+            .filter(tasks::id.eq(parent))
+            .filter(tasks::deleted_at.is_null())
+            .for_update()
+            .first::<Task>(conn)
+            .optional()
+            .map_err(AppError::DieselResult)?;
+        let parent = match parent {
+            Some(parent) if parent.auto_complete && parent.condition != TaskCondition::Done => {
+                parent
+            }
+            _ => return Ok(()),
+        };
+        let conds = tasks
+            .filter(tasks::parent_id.eq(parent.id))
+            .filter(tasks::deleted_at.is_null())
+            .select(tasks::condition)
+            .load::<TaskCondition>(conn)
+            .map_err(AppError::DieselResult)?;
+        if conds.is_empty() || conds.iter().any(|cond| *cond != TaskCondition::Done) {
+            return Ok(());
+        }
+        // Blocked parents are left for their owners to finish once they're unblocked
+        if dependencies::unfinished(conn, parent.id)? > 0 {
+            return Ok(());
+        }
+
+        diesel::update(tasks::table.filter(tasks::id.eq(parent.id)))
+            .set((
+                tasks::condition.eq(TaskCondition::Done),
+                tasks::updated_at.eq(Local::now().naive_local()),
+                tasks::version.eq(tasks::version + 1),
+            ))
+            .execute(conn)
+            .map_err(AppError::DieselResult)?;
+        next = parent.parent_id;
+    }
+
+    Ok(())
+}
+
+fn insert(
+    conn: &mut PgConnection,
+    task: &CreateTask,
+    user: &AuthenticatedUser,
+) -> Result<Task, AppError> {
+    if let Some(parent) = task.parent_id {
+        find_parent(conn, parent, user)?;
+    }
     let cur_time = Local::now().naive_local();
     let task_cond = TaskCondition::default();
-    let token_sub = extract_sub(headers)?;
 
     let new_task = NewTask {
         title: &task.title,
-        owner_id: &token_sub,
+        owner_id: &user.sub,
         body: &task.body,
         condition: task_cond,
         created_at: cur_time,
         updated_at: cur_time,
+        due_at: task.due_at,
+        priority: task
+            .priority
+            .as_deref()
+            .map(TaskPriority::from_str)
+            .transpose()?,
+        parent_id: task.parent_id,
+        auto_complete: task.auto_complete,
     };
-    let res = diesel::insert_into(tasks::table)
+    let new_task = diesel::insert_into(tasks::table)
         .values(new_task)
-        .get_result(&mut conn)
+        .get_result::<Task>(conn)
         .map_err(AppError::DieselResult)?;
+    if !task.tags.is_empty() {
+        set_task_tags(conn, new_task.id, &task.tags, user)?;
+    }
 
-    Ok(res)
+    Ok(new_task)
 }
 
-pub fn update(
+pub fn create(
     pool: web::Data<Pool>,
-    task: UpdateTask,
-    headers: HeaderMap,
-) -> Result<Task, AppError> {
+    task: CreateTask,
+    user: AuthenticatedUser,
+) -> Result<TaskWithTags, AppError> {
     let mut conn = pool.get().map_err(AppError::DieselPool)?;
-    let token_sub = extract_sub(headers)?;
 
-    let res = diesel::update(tasks::table)
-        .filter(tasks::id.eq(Uuid::parse_str(task.id.as_str()).map_err(AppError::Uuid)?))
-        .filter(tasks::owner_id.eq(token_sub))
+    conn.transaction(|conn| {
+        let task = insert(conn, &task, &user)?;
+        with_task_tags(conn, task)
+    })
+}
+
+fn task_not_found() -> AppError {
+    AppError::NotFound("Task not found".into())
+}
+
+pub fn get(
+    pool: web::Data<Pool>,
+    task_id: Uuid,
+    user: AuthenticatedUser,
+) -> Result<TaskWithTags, AppError> {
+    let mut conn = pool.get().map_err(AppError::DieselPool)?;
+
+    // Other users' tasks are reported as missing, so their IDs can't be probed
+    let task = tasks
+        .filter(tasks::id.eq(task_id))
+        .filter(tasks::owner_id.eq(user.sub))
+        .filter(tasks::deleted_at.is_null())
+        .first::<Task>(&mut conn)
+        .optional()
+        .map_err(AppError::DieselResult)?
+        .ok_or_else(task_not_found)?;
+
+    with_task_tags(&mut conn, task)
+}
+
+// Subtasks of trashed tasks are trashed along with them, so the whole subtree is live
+const SUBTREE_QUERY: &str = "
+    WITH RECURSIVE subtree AS (
+        SELECT * FROM tasks WHERE parent_id = $1 AND deleted_at IS NULL
+        UNION ALL
+        SELECT tasks.* FROM tasks JOIN subtree ON tasks.parent_id = subtree.id
+        WHERE tasks.deleted_at IS NULL
+    )
+    SELECT * FROM subtree ORDER BY created_at, id";
+
+fn into_tree(task: TaskWithTags, children: &mut HashMap<Uuid, Vec<TaskWithTags>>) -> TaskTree {
+    let subtasks = children.remove(&task.task.id).unwrap_or_default();
+
+    TaskTree {
+        task,
+        children: subtasks
+            .into_iter()
+            .map(|child| into_tree(child, children))
+            .collect(),
+    }
+}
+
+/// The task with its subtasks, their subtasks & so on
+pub fn get_tree(
+    pool: web::Data<Pool>,
+    task_id: Uuid,
+    user: AuthenticatedUser,
+) -> Result<TaskTree, AppError> {
+    let mut conn = pool.get().map_err(AppError::DieselPool)?;
+
+    // Read in one snapshot, so the tree is consistent
+    conn.build_transaction().repeatable_read().run(|conn| {
+        let task = tasks
+            .filter(tasks::id.eq(task_id))
+            .filter(tasks::owner_id.eq(&user.sub))
+            .filter(tasks::deleted_at.is_null())
+            .first::<Task>(conn)
+            .optional()
+            .map_err(AppError::DieselResult)?
+            .ok_or_else(task_not_found)?;
+        let subtree = diesel::sql_query(SUBTREE_QUERY)
+            .bind::<SqlUuid, _>(task.id)
+            .load::<Task>(conn)
+            .map_err(AppError::DieselResult)?;
+
+        let mut children: HashMap<Uuid, Vec<TaskWithTags>> = HashMap::new();
+        for child in with_tags(conn, subtree)? {
+            if let Some(parent) = child.task.parent_id {
+                children.entry(parent).or_default().push(child);
+            }
+        }
+        Ok(into_tree(with_task_tags(conn, task)?, &mut children))
+    })
+}
+
+/// Direct subtasks of the task in the order they were created
+pub fn get_children(
+    pool: web::Data<Pool>,
+    task_id: Uuid,
+    user: AuthenticatedUser,
+) -> Result<Vec<TaskWithTags>, AppError> {
+    let mut conn = pool.get().map_err(AppError::DieselPool)?;
+
+    let parent = tasks
+        .filter(tasks::id.eq(task_id))
+        .filter(tasks::owner_id.eq(&user.sub))
+        .filter(tasks::deleted_at.is_null())
+        .first::<Task>(&mut conn)
+        .optional()
+        .map_err(AppError::DieselResult)?
+        .ok_or_else(task_not_found)?;
+    let children = tasks
+        .filter(tasks::parent_id.eq(parent.id))
+        .filter(tasks::deleted_at.is_null())
+        .order((tasks::created_at, tasks::id))
+        .get_results::<Task>(&mut conn)
+        .map_err(AppError::DieselResult)?;
+
+    with_tags(&mut conn, children)
+}
+
+// Locks the user's task (unless it's in the trash) for the rest of the transaction & checks it
+// against the precondition
+fn lock_task(
+    conn: &mut PgConnection,
+    task_id: Uuid,
+    precondition: &TaskPrecondition,
+    user: &AuthenticatedUser,
+) -> Result<Option<Task>, AppError> {
+    let task = tasks
+        .filter(tasks::id.eq(task_id))
+        .filter(tasks::owner_id.eq(&user.sub))
+        .filter(tasks::deleted_at.is_null())
+        .for_update()
+        .first::<Task>(conn)
+        .optional()
+        .map_err(AppError::DieselResult)?;
+
+    match task {
+        Some(task) if !precondition.matches(&task) => Err(AppError::PreconditionFailed(
+            "Task has been modified since it was fetched".into(),
+        )),
+        task => Ok(task),
+    }
+}
+
+// Both run within the caller's transaction, so the locked task can't change in between
+fn apply_changes(
+    conn: &mut PgConnection,
+    task_id: Uuid,
+    changes: TaskChanges,
+    precondition: &TaskPrecondition,
+    user: &AuthenticatedUser,
+) -> Result<Task, AppError> {
+    let task = lock_task(conn, task_id, precondition, user)?.ok_or_else(task_not_found)?;
+    // An empty merge patch leaves the task, its updated_at & version as is
+    if changes.is_empty() {
+        return Ok(task);
+    }
+
+    // Blocked tasks can still be reopened, but not started or finished
+    if let Some(cond) = changes.condition {
+        if cond != task.condition && cond != TaskCondition::Undone {
+            dependencies::check_unblocked(conn, task.id)?;
+        }
+    }
+    if let Some(tag_ids) = &changes.tags {
+        set_task_tags(conn, task.id, tag_ids, user)?;
+    }
+    if let Some(Some(parent)) = changes.parent_id {
+        if task.parent_id != Some(parent) {
+            check_move(conn, task.id, parent, user)?;
+        }
+    }
+    let updated = diesel::update(tasks::table.filter(tasks::id.eq(task.id)))
         .set((
-            tasks::title.eq(task.title),
-            tasks::body.eq(task.body),
-            tasks::condition.eq(TaskCondition::from_str(&task.condition)?),
+            changes.title.map(|new_title| tasks::title.eq(new_title)),
+            changes.body.map(|new_body| tasks::body.eq(new_body)),
+            changes.condition.map(|cond| tasks::condition.eq(cond)),
+            changes.due_at.map(|due| tasks::due_at.eq(due)),
+            changes.priority.map(|prio| tasks::priority.eq(prio)),
+            changes.parent_id.map(|parent| tasks::parent_id.eq(parent)),
+            changes
+                .auto_complete
+                .map(|auto| tasks::auto_complete.eq(auto)),
             tasks::updated_at.eq(Local::now().naive_local()),
+            tasks::version.eq(tasks::version + 1),
         ))
-        .get_result(&mut conn)
+        .get_result::<Task>(conn)
         .map_err(AppError::DieselResult)?;
+    if updated.due_at != task.due_at {
+        reminders::reschedule(conn, updated.id, updated.due_at)?;
+    }
+    // Both the new parent & the one the task was moved from may now have only done subtasks
+    let moved = updated.parent_id != task.parent_id;
+    if updated.condition == TaskCondition::Done && (task.condition != TaskCondition::Done || moved)
+    {
+        complete_parents(conn, updated.parent_id)?;
+    }
+    if moved {
+        complete_parents(conn, task.parent_id)?;
+    }
+
+    Ok(updated)
+}
+
+// Subtasks get the same deletion time, which tells them apart from the ones trashed earlier when
+// the task is restored
+const TRASH_SUBTREE_QUERY: &str = "
+    WITH RECURSIVE subtree AS (
+        SELECT id FROM tasks WHERE id = $1
+        UNION ALL
+        SELECT tasks.id FROM tasks JOIN subtree ON tasks.parent_id = subtree.id
+        WHERE tasks.deleted_at IS NULL
+    )
+    UPDATE tasks SET deleted_at = $2, version = version + 1
+    WHERE id IN (SELECT id FROM subtree)";
+
+fn move_to_trash(
+    conn: &mut PgConnection,
+    task_id: Uuid,
+    precondition: &TaskPrecondition,
+    user: &AuthenticatedUser,
+) -> Result<usize, AppError> {
+    let task = match lock_task(conn, task_id, precondition, user)? {
+        Some(task) => task,
+        None => return Ok(0),
+    };
+    let trashed = diesel::sql_query(TRASH_SUBTREE_QUERY)
+        .bind::<SqlUuid, _>(task.id)
+        .bind::<Timestamp, _>(Local::now().naive_local())
+        .execute(conn)
+        .map_err(AppError::DieselResult)?;
+    complete_parents(conn, task.parent_id)?;
+
+    Ok(trashed)
+}
+
+pub fn update(
+    pool: web::Data<Pool>,
+    task_id: Uuid,
+    changes: TaskChanges,
+    precondition: TaskPrecondition,
+    user: AuthenticatedUser,
+) -> Result<TaskWithTags, AppError> {
+    let mut conn = pool.get().map_err(AppError::DieselPool)?;
 
-    Ok(res)
+    conn.transaction(|conn| {
+        let task = apply_changes(conn, task_id, changes, &precondition, &user)?;
+        with_task_tags(conn, task)
+    })
 }
 
+/// Moves the task with its subtasks to the trash, they can be restored until they're purged
 pub fn delete(
     pool: web::Data<Pool>,
-    task_uuid_str: String,
-    headers: HeaderMap,
+    task_id: Uuid,
+    precondition: TaskPrecondition,
+    user: AuthenticatedUser,
 ) -> Result<usize, AppError> {
     let mut conn = pool.get().map_err(AppError::DieselPool)?;
-    let token_sub = extract_sub(headers)?;
 
-    let res = diesel::delete(
-        tasks::table
-            .filter(tasks::id.eq(Uuid::parse_str(task_uuid_str.as_str()).map_err(AppError::Uuid)?))
-            .filter(tasks::owner_id.eq(token_sub)),
-    )
-    .execute(&mut conn)
-    .map_err(AppError::DieselResult)?;
+    conn.transaction(|conn| move_to_trash(conn, task_id, &precondition, &user))
+}
+
+// Status & task of a successful operation, the tags of all returned tasks are loaded at once
+// after the batch
+fn apply_operation(
+    conn: &mut PgConnection,
+    operation: BatchOperation,
+    user: &AuthenticatedUser,
+) -> Result<(StatusCode, Option<Task>), AppError> {
+    operation.validate().map_err(AppError::Validator)?;
+    let (task_id, changes) = match operation {
+        BatchOperation::Create(task) => {
+            let task = insert(conn, &task, user)?;
+            return Ok((StatusCode::CREATED, Some(task)));
+        }
+        BatchOperation::Delete { id: task_id } => {
+            return match move_to_trash(conn, task_id, &TaskPrecondition::Any, user)? {
+                0 => Err(task_not_found()),
+                _ => Ok((StatusCode::NO_CONTENT, None)),
+            };
+        }
+        BatchOperation::Update {
+            id: task_id,
+            changes,
+        } => (task_id, TaskChanges::try_from(changes)?),
+        BatchOperation::SetCondition {
+            id: task_id,
+            condition: cond,
+        } => (
+            task_id,
+            TaskChanges {
+                condition: Some(TaskCondition::from_str(&cond)?),
+                ..Default::default()
+            },
+        ),
+    };
+
+    let task = apply_changes(conn, task_id, changes, &TaskPrecondition::Any, user)?;
+    Ok((StatusCode::OK, Some(task)))
+}
+
+/// Runs the operations in order within a single transaction
+pub fn batch(
+    pool: web::Data<Pool>,
+    batch: BatchRequest,
+    user: AuthenticatedUser,
+) -> Result<Vec<BatchResult>, AppError> {
+    let mut conn = pool.get().map_err(AppError::DieselPool)?;
+    let operations = batch.operations.into_iter();
+
+    conn.transaction(|conn| {
+        let outcomes = match batch.mode {
+            BatchMode::AllOrNothing => operations
+                .enumerate()
+                .map(|(index, operation)| {
+                    apply_operation(conn, operation, &user)
+                        .map(Ok)
+                        .map_err(|e| AppError::BatchOperation(index, Box::new(e)))
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            // Nested transactions are savepoints, so only the failed operation is rolled back
+            BatchMode::BestEffort => operations
+                .map(|operation| conn.transaction(|conn| apply_operation(conn, operation, &user)))
+                .collect(),
+        };
+
+        let mut returned = Vec::new();
+        let outcomes: Vec<_> = outcomes
+            .into_iter()
+            .map(|outcome| {
+                outcome.map(|(status, task)| {
+                    let has_task = task.is_some();
+                    returned.extend(task);
+                    (status, has_task)
+                })
+            })
+            .collect();
+        // Tags are loaded once for the whole batch, see `BatchResult`
+        let mut tagged = with_tags(conn, returned)?.into_iter();
 
-    Ok(res)
+        Ok(outcomes
+            .into_iter()
+            .map(|outcome| match outcome {
+                Ok((status, has_task)) => {
+                    BatchResult::succeeded(status, has_task.then(|| tagged.next()).flatten())
+                }
+                Err(e) => BatchResult::failed(&e),
+            })
+            .collect())
+    })
 }
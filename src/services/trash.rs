@@ -0,0 +1,215 @@
+use crate::{
+    database::connection::Pool,
+    errors::app_error::AppError,
+    models::{
+        principal::AuthenticatedUser,
+        schema::tasks::{self, dsl::*},
+        task::{Task, TaskWithTags},
+    },
+    services::tags::{with_tags, with_task_tags},
+};
+use actix_web::{
+    rt::{self, time},
+    web,
+};
+use chrono::Local;
+use diesel::{prelude::*, sql_types::Uuid as SqlUuid};
+use log::{debug, warn};
+use std::{env, time::Duration};
+use uuid::Uuid;
+
+// Deleted tasks are kept in the trash, from where their owners can restore them until they're
+// purged for good after the retention period. Purging a task deletes its subtasks too
+
+const DEFAULT_RETENTION_DAYS: i64 = 30;
+const DEFAULT_PURGE_INTERVAL: Duration = Duration::from_secs(3600);
+
+pub fn get_all(
+    pool: web::Data<Pool>,
+    user: AuthenticatedUser,
+) -> Result<Vec<TaskWithTags>, AppError> {
+    let mut conn = pool.get().map_err(AppError::DieselPool)?;
+
+    let trashed = tasks
+        .filter(tasks::owner_id.eq(user.sub))
+        .filter(tasks::deleted_at.is_not_null())
+        .order((tasks::deleted_at.desc(), tasks::id))
+        .get_results::<Task>(&mut conn)
+        .map_err(AppError::DieselResult)?;
+
+    with_tags(&mut conn, trashed)
+}
+
+// Subtasks trashed along with the task share its deletion time, ones trashed before stay there
+const RESTORE_SUBTREE_QUERY: &str = "
+    WITH RECURSIVE subtree AS (
+        SELECT id, deleted_at FROM tasks WHERE id = $1
+        UNION ALL
+        SELECT tasks.id, tasks.deleted_at FROM tasks JOIN subtree ON tasks.parent_id = subtree.id
+        WHERE tasks.deleted_at = subtree.deleted_at
+    )
+    UPDATE tasks SET deleted_at = NULL, version = version + 1
+    WHERE id IN (SELECT id FROM subtree)";
+
+/// Restores the task with the subtasks trashed along with it, a subtask whose parent is still in
+/// the trash is restored to the top level
+pub fn restore(
+    pool: web::Data<Pool>,
+    task_id: Uuid,
+    user: AuthenticatedUser,
+) -> Result<TaskWithTags, AppError> {
+    let mut conn = pool.get().map_err(AppError::DieselPool)?;
+
+    conn.transaction(|conn| {
+        let task = tasks
+            .filter(tasks::id.eq(task_id))
+            .filter(tasks::owner_id.eq(user.sub))
+            .filter(tasks::deleted_at.is_not_null())
+            .for_update()
+            .first::<Task>(conn)
+            .optional()
+            .map_err(AppError::DieselResult)?
+            .ok_or_else(|| AppError::NotFound("Task not found in the trash".into()))?;
+        diesel::sql_query(RESTORE_SUBTREE_QUERY)
+            .bind::<SqlUuid, _>(task.id)
+            .execute(conn)
+            .map_err(AppError::DieselResult)?;
+
+        let parent_trashed = match task.parent_id {
+            Some(parent) => diesel::select(diesel::dsl::exists(
+                tasks
+                    .filter(tasks::id.eq(parent))
+                    .filter(tasks::deleted_at.is_not_null()),
+            ))
+            .get_result::<bool>(conn)
+            .map_err(AppError::DieselResult)?,
+            None => false,
+        };
+        let restored = tasks::table.filter(tasks::id.eq(task.id));
+        let task = match parent_trashed {
+            true => diesel::update(restored)
+                .set(tasks::parent_id.eq(None::<Uuid>))
+                .get_result::<Task>(conn),
+            false => restored.first::<Task>(conn),
+        }
+        .map_err(AppError::DieselResult)?;
+
+        with_task_tags(conn, task)
+    })
+}
+
+/// Permanently deletes every task in the user's trash
+pub fn empty(pool: web::Data<Pool>, user: AuthenticatedUser) -> Result<usize, AppError> {
+    let mut conn = pool.get().map_err(AppError::DieselPool)?;
+
+    diesel::delete(
+        tasks::table
+            .filter(tasks::owner_id.eq(user.sub))
+            .filter(tasks::deleted_at.is_not_null()),
+    )
+    .execute(&mut conn)
+    .map_err(AppError::DieselResult)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TrashPurge {
+    retention: chrono::Duration,
+    interval: Duration,
+}
+
+impl Default for TrashPurge {
+    fn default() -> Self {
+        Self::new(
+            chrono::Duration::days(DEFAULT_RETENTION_DAYS),
+            DEFAULT_PURGE_INTERVAL,
+        )
+    }
+}
+
+impl TrashPurge {
+    pub fn new(retention: chrono::Duration, interval: Duration) -> Self {
+        Self {
+            retention,
+            interval,
+        }
+    }
+
+    /// Reads the retention period from `TRASH_RETENTION_DAYS` (0 purges on every run) & the purge
+    /// interval from `TRASH_PURGE_INTERVAL`
+    pub fn from_env() -> Result<Self, AppError> {
+        let retention =
+            env::var("TRASH_RETENTION_DAYS").map_or(Ok(DEFAULT_RETENTION_DAYS), |days| {
+                days.parse::<u16>().map(i64::from).map_err(|_| {
+                    AppError::MissingConfig(
+                        "TRASH_RETENTION_DAYS must be a number of days up to 65535".into(),
+                    )
+                })
+            })?;
+        let interval = env::var("TRASH_PURGE_INTERVAL").map_or(
+            Ok(DEFAULT_PURGE_INTERVAL),
+            |secs| match secs.parse::<u64>() {
+                Ok(secs) if secs > 0 => Ok(Duration::from_secs(secs)),
+                _ => Err(AppError::MissingConfig(
+                    "TRASH_PURGE_INTERVAL must be a positive number of seconds".into(),
+                )),
+            },
+        )?;
+
+        Ok(Self::new(chrono::Duration::days(retention), interval))
+    }
+
+    /// Permanently deletes the tasks of all users that have been in the trash for longer than
+    /// the retention period
+    pub fn purge(&self, pool: &Pool) -> Result<usize, AppError> {
+        let mut conn = pool.get().map_err(AppError::DieselPool)?;
+        let deleted_before = Local::now().naive_local() - self.retention;
+
+        diesel::delete(tasks::table.filter(tasks::deleted_at.lt(deleted_before)))
+            .execute(&mut conn)
+            .map_err(AppError::DieselResult)
+    }
+
+    /// Spawns a task on the current runtime that purges the trash every interval (first tick
+    /// purges immediately)
+    pub fn spawn(self, pool: Pool) {
+        rt::spawn(async move {
+            let mut interval = time::interval(self.interval);
+            loop {
+                interval.tick().await;
+                let pool = pool.clone();
+                match web::block(move || self.purge(&pool)).await {
+                    Ok(Ok(purged)) => debug!("Purged {} tasks from the trash", purged),
+                    Ok(Err(e)) => warn!(target: "errors_file", "Failed to purge the trash: {}", e),
+                    Err(e) => warn!(target: "errors_file", "Failed to purge the trash: {}", e),
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_purge_config() {
+        env::set_var("TRASH_RETENTION_DAYS", "0");
+        env::set_var("TRASH_PURGE_INTERVAL", "60");
+        let purge = TrashPurge::from_env().unwrap();
+        assert_eq!(purge.retention, chrono::Duration::zero());
+        assert_eq!(purge.interval, Duration::from_secs(60));
+
+        for (retention, interval) in [("-1", "60"), ("thirty", "60"), ("30", "0"), ("30", "-60")] {
+            env::set_var("TRASH_RETENTION_DAYS", retention);
+            env::set_var("TRASH_PURGE_INTERVAL", interval);
+            assert!(
+                matches!(TrashPurge::from_env(), Err(AppError::MissingConfig(_))),
+                "{} days every {} seconds",
+                retention,
+                interval
+            );
+        }
+        env::remove_var("TRASH_RETENTION_DAYS");
+        env::remove_var("TRASH_PURGE_INTERVAL");
+    }
+}
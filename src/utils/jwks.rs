@@ -0,0 +1,184 @@
+use crate::errors::app_error::AppError;
+use actix_web::rt::{self, time};
+use jsonwebtokens::{raw::decode_header_only, Algorithm, AlgorithmID, Verifier};
+use log::{debug, warn};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+// Shared cache of the identity provider's JWKS key set, stored in app data and refreshed
+// periodically in the background & on demand whenever a token with an unknown `kid` appears
+
+const DEFAULT_TTL: Duration = Duration::from_secs(3600);
+const DEFAULT_MIN_FETCH_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Deserialize)]
+struct Jwk {
+    kid: String,
+    kty: String,
+    alg: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Default)]
+struct KeyState {
+    algorithms: HashMap<String, Arc<Algorithm>>,
+    last_attempt: Option<Instant>,
+}
+
+pub struct JwksCache {
+    jwks_url: String,
+    ttl: Duration,
+    min_fetch_interval: Duration,
+    client: reqwest::Client,
+    state: RwLock<KeyState>,
+}
+
+impl JwksCache {
+    pub fn new(jwks_url: impl Into<String>) -> Self {
+        Self {
+            jwks_url: jwks_url.into(),
+            ttl: DEFAULT_TTL,
+            min_fetch_interval: DEFAULT_MIN_FETCH_INTERVAL,
+            client: reqwest::Client::new(),
+            state: RwLock::new(KeyState::default()),
+        }
+    }
+
+    /// Interval between background refreshes of the whole key set
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Minimum time between two fetches triggered by unknown key IDs (throttles bogus tokens)
+    pub fn with_min_fetch_interval(mut self, interval: Duration) -> Self {
+        self.min_fetch_interval = interval;
+        self
+    }
+
+    pub fn jwks_url(&self) -> &str {
+        &self.jwks_url
+    }
+
+    pub fn key_ids(&self) -> Vec<String> {
+        // Poisoning would imply a panic while holding the lock, which none of the writers can do
+        let state = self.state.read().unwrap();
+        state.algorithms.keys().cloned().collect()
+    }
+
+    /// Fetches the remote key set and replaces the cached keys. On failure the last-known-good
+    /// keys are left untouched so that verification keeps working while the IdP is unavailable.
+    pub async fn refresh(&self) -> Result<(), AppError> {
+        self.state.write().unwrap().last_attempt = Some(Instant::now());
+
+        let jwks = match self.fetch().await {
+            Ok(jwks) => jwks,
+            Err(e) => {
+                warn!(target: "errors_file", "Failed to refresh JWKS from {}: {}", self.jwks_url, e);
+                return Err(e);
+            }
+        };
+
+        let mut algorithms = HashMap::new();
+        for key in jwks.keys {
+            // Only RSA signing keys are supported (RS256 is what Cognito & most OIDC IdPs use)
+            if key.kty != "RSA" || key.alg.as_deref().unwrap_or("RS256") != "RS256" {
+                continue;
+            }
+            let (Some(n), Some(e)) = (key.n.as_deref(), key.e.as_deref()) else {
+                continue;
+            };
+            let mut algorithm = Algorithm::new_rsa_n_e_b64_verifier(AlgorithmID::RS256, n, e)
+                .map_err(AppError::JwtGeneric)?;
+            algorithm.set_kid(&key.kid);
+            algorithms.insert(key.kid, Arc::new(algorithm));
+        }
+
+        debug!(
+            "Fetched {} signing key(s) from {}",
+            algorithms.len(),
+            self.jwks_url
+        );
+        self.state.write().unwrap().algorithms = algorithms;
+
+        Ok(())
+    }
+
+    async fn fetch(&self) -> Result<JwkSet, AppError> {
+        self.client
+            .get(&self.jwks_url)
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(AppError::JwksFetch)?
+            .json::<JwkSet>()
+            .await
+            .map_err(AppError::JwksFetch)
+    }
+
+    /// Returns the verification key for `kid`, refetching the key set once if it isn't cached
+    pub async fn algorithm(&self, kid: &str) -> Result<Arc<Algorithm>, AppError> {
+        let last_attempt = {
+            let state = self.state.read().unwrap();
+            if let Some(algorithm) = state.algorithms.get(kid) {
+                return Ok(algorithm.clone());
+            }
+            state.last_attempt
+        };
+
+        let throttled = last_attempt.is_some_and(|t| t.elapsed() < self.min_fetch_interval);
+        if throttled {
+            return Err(AppError::JwtUnknownKey(kid.into()));
+        }
+        // A failed refetch still leaves the previous keys in place, the kid is just unknown
+        let _ = self.refresh().await;
+
+        self.state
+            .read()
+            .unwrap()
+            .algorithms
+            .get(kid)
+            .cloned()
+            .ok_or_else(|| AppError::JwtUnknownKey(kid.into()))
+    }
+
+    /// Verifies the token with the key referenced by its `kid` header & the given claim verifier
+    pub async fn verify(&self, token: &str, verifier: &Verifier) -> Result<Value, AppError> {
+        let header = decode_header_only(token).map_err(AppError::JwtGeneric)?;
+        let kid = header
+            .get("kid")
+            .and_then(Value::as_str)
+            .ok_or(AppError::JwtMissingClaim(
+                "No key ID found in JWT header".into(),
+            ))?;
+        let algorithm = self.algorithm(kid).await?;
+
+        verifier
+            .verify(token, &algorithm)
+            .map_err(AppError::JwtGeneric)
+    }
+
+    /// Spawns a task on the current runtime that refreshes the key set every TTL (first tick
+    /// fetches immediately)
+    pub fn spawn_refresh(cache: Arc<Self>) {
+        rt::spawn(async move {
+            let mut interval = time::interval(cache.ttl);
+            loop {
+                interval.tick().await;
+                // Errors are logged by refresh(), the old keys keep being served
+                let _ = cache.refresh().await;
+            }
+        });
+    }
+}
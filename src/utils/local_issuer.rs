@@ -0,0 +1,240 @@
+use crate::{
+    errors::app_error::AppError,
+    utils::verifier::{verifier_builder, IssuerSettings, TokenVerifier},
+};
+use async_trait::async_trait;
+use base64::{
+    alphabet::URL_SAFE,
+    encode_engine,
+    engine::fast_portable::{FastPortable, NO_PAD},
+};
+use jsonwebtokens::{encode, Algorithm, AlgorithmID, Verifier};
+use openssl::{pkey::Private, rsa::Rsa, sha::sha256};
+use serde_json::{json, Value};
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    os::unix::fs::OpenOptionsExt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use uuid::Uuid;
+
+// Built-in identity provider for self-hosting without Cognito, issues RS256 access tokens
+// signed with a locally managed key & verifies them as one of the `TrustedIssuers`
+// The same issuer backs the offline development mode with a key that only lives in memory
+
+const URL_SAFE_NO_PAD: FastPortable = FastPortable::from(&URL_SAFE, NO_PAD);
+pub const DEV_ISSUER: &str = "zeronote-dev";
+const DEV_TOKEN_TTL: u64 = 3600;
+
+fn env_flag(name: &str) -> bool {
+    env::var(name).is_ok_and(|v| v.eq_ignore_ascii_case("true"))
+}
+
+/// Offline development mode, enabled by setting `DEV_AUTH=true`
+/// Anyone reaching the server can mint tokens for arbitrary subjects & groups in this mode
+pub fn dev_auth_enabled() -> bool {
+    env_flag("DEV_AUTH")
+}
+
+#[derive(Debug, Clone)]
+pub struct LocalAuthConfig {
+    pub issuer: String,
+    /// PEM encoded RSA private key, generated on the first start if missing
+    pub key_file: String,
+    /// Lifetime of issued access tokens in seconds
+    pub token_ttl: u64,
+}
+
+impl LocalAuthConfig {
+    /// Enabled by setting `LOCAL_AUTH=true`
+    pub fn from_env() -> Option<Self> {
+        if !env_flag("LOCAL_AUTH") {
+            return None;
+        }
+
+        Some(Self {
+            issuer: env::var("LOCAL_AUTH_ISSUER").unwrap_or_else(|_| "zeronote".into()),
+            key_file: env::var("LOCAL_AUTH_KEY_FILE")
+                .unwrap_or_else(|_| "local_auth_key.pem".into()),
+            token_ttl: env::var("LOCAL_AUTH_TOKEN_TTL").map_or(3600, |ttl| {
+                ttl.parse()
+                    .expect("LOCAL_AUTH_TOKEN_TTL must be a number of seconds")
+            }),
+        })
+    }
+}
+
+fn key_error(e: impl std::fmt::Display) -> AppError {
+    AppError::MissingConfig(format!("Invalid local signing key: {}", e))
+}
+
+fn load_or_generate_key(path: &str) -> Result<Rsa<Private>, AppError> {
+    match fs::read(path) {
+        Ok(pem) => Rsa::private_key_from_pem(&pem).map_err(key_error),
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            let rsa = Rsa::generate(2048).map_err(key_error)?;
+            let pem = rsa.private_key_to_pem().map_err(key_error)?;
+            OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(path)
+                .and_then(|mut file| file.write_all(&pem))
+                .map_err(key_error)?;
+
+            Ok(rsa)
+        }
+        Err(e) => Err(key_error(e)),
+    }
+}
+
+pub struct LocalIssuer {
+    issuer: String,
+    token_ttl: u64,
+    signer: Algorithm,
+    verify_key: Algorithm,
+    verifier: Verifier,
+    jwk: Value,
+}
+
+impl LocalIssuer {
+    pub fn new(config: &LocalAuthConfig) -> Result<Self, AppError> {
+        let rsa = load_or_generate_key(&config.key_file)?;
+
+        Self::from_rsa(&config.issuer, config.token_ttl, &rsa)
+    }
+
+    /// Issuer of the offline development mode, its tokens become invalid on restart
+    pub fn dev() -> Result<Self, AppError> {
+        let rsa = Rsa::generate(2048).map_err(key_error)?;
+
+        Self::from_rsa(DEV_ISSUER, DEV_TOKEN_TTL, &rsa)
+    }
+
+    pub fn from_rsa(issuer: &str, token_ttl: u64, rsa: &Rsa<Private>) -> Result<Self, AppError> {
+        let n = encode_engine(rsa.n().to_vec(), &URL_SAFE_NO_PAD);
+        let e = encode_engine(rsa.e().to_vec(), &URL_SAFE_NO_PAD);
+        // The key ID is derived from the public key, so it stays stable across restarts
+        let public_der = rsa.public_key_to_der().map_err(key_error)?;
+        let kid = encode_engine(&sha256(&public_der)[..12], &URL_SAFE_NO_PAD);
+
+        let pem = rsa.private_key_to_pem().map_err(key_error)?;
+        let mut signer = Algorithm::new_rsa_pem_signer(AlgorithmID::RS256, &pem)
+            .map_err(AppError::JwtGeneric)?;
+        signer.set_kid(kid.clone());
+        let mut verify_key = Algorithm::new_rsa_n_e_b64_verifier(AlgorithmID::RS256, &n, &e)
+            .map_err(AppError::JwtGeneric)?;
+        verify_key.set_kid(kid.clone());
+
+        let mut builder = verifier_builder(issuer, &IssuerSettings::default());
+        builder.string_equals("token_use", "access");
+
+        Ok(Self {
+            issuer: issuer.into(),
+            token_ttl,
+            signer,
+            verify_key,
+            verifier: builder.build().map_err(AppError::JwtGeneric)?,
+            jwk: json!({"kid": kid, "kty": "RSA", "alg": "RS256", "use": "sig", "n": n, "e": e}),
+        })
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    pub fn token_ttl(&self) -> u64 {
+        self.token_ttl
+    }
+
+    /// Signs an access token for a local user, shaped like Cognito's access tokens
+    pub fn issue(&self, sub: &str, username: &str, scopes: &[&str]) -> Result<String, AppError> {
+        self.issue_with_groups(sub, Some(username), &[], scopes)
+    }
+
+    pub fn issue_with_groups(
+        &self,
+        sub: &str,
+        username: Option<&str>,
+        groups: &[&str],
+        scopes: &[&str],
+    ) -> Result<String, AppError> {
+        let iat = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| AppError::MissingConfig(e.to_string()))?
+            .as_secs();
+        let mut claims = json!({
+            "iss": self.issuer,
+            "sub": sub,
+            "scope": scopes.join(" "),
+            "token_use": "access",
+            "jti": Uuid::new_v4().to_string(),
+            "iat": iat,
+            "exp": iat + self.token_ttl,
+        });
+        if let Some(username) = username {
+            claims["username"] = json!(username);
+        }
+        if !groups.is_empty() {
+            claims["cognito:groups"] = json!(groups);
+        }
+        let header = json!({"alg": self.signer.name(), "kid": self.signer.kid()});
+
+        encode(&header, &claims, &self.signer).map_err(AppError::JwtGeneric)
+    }
+
+    /// Public key set, so other services can verify the issued tokens as well
+    pub fn jwks(&self) -> Value {
+        json!({ "keys": [self.jwk] })
+    }
+}
+
+#[async_trait(?Send)]
+impl TokenVerifier for LocalIssuer {
+    async fn verify(&self, token: &str) -> Result<Value, AppError> {
+        self.verifier
+            .verify(token, &self.verify_key)
+            .map_err(AppError::JwtGeneric)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_issued_tokens_verify() {
+        let rsa = Rsa::generate(2048).unwrap();
+        let issuer = LocalIssuer::from_rsa("zeronote", 60, &rsa).unwrap();
+        let token = issuer.issue("user-1", "alice", &["tasks:read"]).unwrap();
+
+        let claims = issuer.verify(&token).await.unwrap();
+        assert_eq!(claims["sub"], "user-1");
+        assert_eq!(claims["username"], "alice");
+        assert_eq!(claims["scope"], "tasks:read");
+
+        let other = LocalIssuer::from_rsa("zeronote", 60, &Rsa::generate(2048).unwrap()).unwrap();
+        assert!(
+            other.verify(&token).await.is_err(),
+            "Tokens signed by another key must be rejected"
+        );
+    }
+
+    #[test]
+    fn test_key_file_is_reused() {
+        let path = env::temp_dir().join(format!("zeronote-key-{}.pem", std::process::id()));
+        let path = path.to_str().unwrap();
+        let config = LocalAuthConfig {
+            issuer: "zeronote".into(),
+            key_file: path.into(),
+            token_ttl: 60,
+        };
+
+        let first = LocalIssuer::new(&config).unwrap();
+        let second = LocalIssuer::new(&config).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(first.jwks(), second.jwks());
+    }
+}
@@ -1,2 +1,6 @@
+pub mod jwks;
+pub mod local_issuer;
 pub mod log;
+pub mod notifier;
 pub mod ssl_builder;
+pub mod verifier;
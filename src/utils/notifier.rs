@@ -0,0 +1,325 @@
+use crate::{errors::app_error::AppError, models::reminder::Notification};
+use actix_web::web;
+use async_trait::async_trait;
+use std::{
+    env,
+    io::{BufRead, BufReader, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::Arc,
+    time::Duration,
+};
+
+// Delivery of reminders abstracted from the channel. The reminder scheduler delivers each
+// notification to every channel in `Notifiers` & records the ones that accepted it by their names
+
+/// Bounds every connect, read & write of a delivery
+pub const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+#[async_trait(?Send)]
+pub trait Notifier: Send + Sync {
+    /// Name the deliveries through the channel are recorded under, unique within `Notifiers`
+    fn channel(&self) -> &str;
+
+    async fn notify(&self, notification: &Notification) -> Result<(), AppError>;
+}
+
+/// POSTs notifications as JSON, redeliveries carry the same `Idempotency-Key` header
+pub struct WebhookNotifier {
+    url: String,
+    client: reqwest::Client,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl Notifier for WebhookNotifier {
+    fn channel(&self) -> &str {
+        "webhook"
+    }
+
+    async fn notify(&self, notification: &Notification) -> Result<(), AppError> {
+        let res = self
+            .client
+            .post(&self.url)
+            .timeout(DELIVERY_TIMEOUT)
+            .header(IDEMPOTENCY_KEY_HEADER, notification.idempotency_key())
+            .json(notification)
+            .send()
+            .await
+            .map_err(|e| AppError::Notify(format!("Webhook request failed: {}", e)))?;
+
+        match res.status().is_success() {
+            true => Ok(()),
+            false => Err(AppError::Notify(format!(
+                "Webhook responded with {}",
+                res.status()
+            ))),
+        }
+    }
+}
+
+/// Sends plain text emails through an SMTP relay without authentication or TLS, meant for a
+/// relay on the local network. Notifications without an allowed address are skipped
+#[derive(Clone)]
+pub struct SmtpNotifier {
+    host: String,
+    port: u16,
+    from: String,
+    recipients: EmailRecipients,
+}
+
+/// Domains reminders may be emailed to, so the relay can't be used to mail arbitrary addresses
+#[derive(Debug, Clone, Default)]
+pub struct EmailRecipients {
+    domains: Vec<String>,
+}
+
+impl EmailRecipients {
+    pub fn new<S: AsRef<str>>(domains: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            domains: domains
+                .into_iter()
+                .map(|domain| domain.as_ref().trim().to_lowercase())
+                .filter(|domain| !domain.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Comma separated `SMTP_ALLOWED_DOMAINS`, no address is allowed without it
+    pub fn from_env() -> Self {
+        Self::new(
+            env::var("SMTP_ALLOWED_DOMAINS")
+                .unwrap_or_default()
+                .split(','),
+        )
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.domains.is_empty()
+    }
+
+    /// Subdomains have to be listed separately
+    pub fn allows(&self, email: &str) -> bool {
+        email
+            .rsplit_once('@')
+            .map(|(_, domain)| domain.to_lowercase())
+            .is_some_and(|domain| self.domains.contains(&domain))
+    }
+}
+
+// Header values can't contain line breaks, otherwise the title could inject headers
+fn header_value(value: &str) -> String {
+    value.replace(['\r', '\n'], " ")
+}
+
+// Lines starting with a dot are escaped by doubling it (RFC 5321 4.5.2)
+fn smtp_message(from: &str, to: &str, notification: &Notification) -> String {
+    let due = notification.due_at.map_or("No due date".into(), |due| {
+        format!("Due at {}", due.to_rfc3339())
+    });
+    let body = format!("{}\r\n{}\r\n", notification.title, due);
+    let body: Vec<String> = body
+        .lines()
+        .map(|line| match line.starts_with('.') {
+            true => format!(".{}", line),
+            false => line.to_owned(),
+        })
+        .collect();
+
+    format!(
+        "From: <{}>\r\nTo: <{}>\r\nSubject: Reminder: {}\r\nMessage-ID: <{}@zeronote>\r\n\
+         Content-Type: text/plain; charset=utf-8\r\n\r\n{}\r\n.\r\n",
+        from,
+        to,
+        header_value(&notification.title),
+        notification.idempotency_key().replace(':', "."),
+        body.join("\r\n"),
+    )
+}
+
+impl SmtpNotifier {
+    pub fn new(
+        host: impl Into<String>,
+        port: u16,
+        from: impl Into<String>,
+        recipients: EmailRecipients,
+    ) -> Self {
+        Self {
+            host: host.into(),
+            port,
+            from: from.into(),
+            recipients,
+        }
+    }
+
+    fn send(&self, to: &str, message: &str) -> Result<(), AppError> {
+        let smtp_error = |e: std::io::Error| AppError::Notify(format!("SMTP failed: {}", e));
+        let addr = (self.host.as_str(), self.port)
+            .to_socket_addrs()
+            .map_err(smtp_error)?
+            .next()
+            .ok_or_else(|| AppError::Notify(format!("Unknown SMTP host {}", self.host)))?;
+        let mut stream = TcpStream::connect_timeout(&addr, DELIVERY_TIMEOUT).map_err(smtp_error)?;
+        stream
+            .set_read_timeout(Some(DELIVERY_TIMEOUT))
+            .map_err(smtp_error)?;
+        stream
+            .set_write_timeout(Some(DELIVERY_TIMEOUT))
+            .map_err(smtp_error)?;
+        let mut reader = BufReader::new(stream.try_clone().map_err(smtp_error)?);
+
+        // Replies can span several lines, the last one has a space after the code
+        let mut expect = |code: &str| -> Result<(), AppError> {
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).map_err(smtp_error)?;
+                if !line.starts_with(code) {
+                    return Err(AppError::Notify(format!(
+                        "Unexpected SMTP reply: {}",
+                        line.trim_end()
+                    )));
+                }
+                if line.as_bytes().get(3) != Some(&b'-') {
+                    return Ok(());
+                }
+            }
+        };
+        let send = |stream: &mut TcpStream, command: &str| {
+            stream.write_all(command.as_bytes()).map_err(smtp_error)
+        };
+
+        expect("220")?;
+        send(&mut stream, "EHLO zeronote\r\n")?;
+        expect("250")?;
+        send(&mut stream, &format!("MAIL FROM:<{}>\r\n", self.from))?;
+        expect("250")?;
+        send(&mut stream, &format!("RCPT TO:<{}>\r\n", to))?;
+        expect("250")?;
+        send(&mut stream, "DATA\r\n")?;
+        expect("354")?;
+        send(&mut stream, message)?;
+        expect("250")?;
+        send(&mut stream, "QUIT\r\n")
+    }
+}
+
+#[async_trait(?Send)]
+impl Notifier for SmtpNotifier {
+    fn channel(&self) -> &str {
+        "smtp"
+    }
+
+    async fn notify(&self, notification: &Notification) -> Result<(), AppError> {
+        // Reminders created before their domain was removed from the allowlist are skipped too
+        let to = match &notification.email {
+            Some(email) if self.recipients.allows(email) => header_value(email),
+            _ => return Ok(()),
+        };
+        let message = smtp_message(&self.from, &to, notification);
+        let relay = self.clone();
+
+        web::block(move || relay.send(&to, &message))
+            .await
+            .map_err(AppError::WebBlocking)?
+    }
+}
+
+/// Every configured channel, a notification is delivered once all of them have accepted it. The
+/// scheduler records each channel that has, so it isn't sent the notification again when another
+/// one fails
+#[derive(Default, Clone)]
+pub struct Notifiers {
+    notifiers: Vec<Arc<dyn Notifier>>,
+}
+
+impl Notifiers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, notifier: Arc<dyn Notifier>) -> Self {
+        self.notifiers.push(notifier);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.notifiers.is_empty()
+    }
+
+    pub fn channels(&self) -> impl Iterator<Item = &dyn Notifier> {
+        self.notifiers.iter().map(|notifier| notifier.as_ref())
+    }
+
+    /// Webhooks are enabled by `NOTIFY_WEBHOOK_URL` & emails by `SMTP_HOST` (with `SMTP_FROM`,
+    /// `SMTP_ALLOWED_DOMAINS` & an optional `SMTP_PORT`)
+    pub fn from_env() -> Result<Self, AppError> {
+        let mut notifiers = Self::new();
+
+        if let Ok(url) = env::var("NOTIFY_WEBHOOK_URL") {
+            notifiers = notifiers.with(Arc::new(WebhookNotifier::new(url)));
+        }
+        if let Ok(host) = env::var("SMTP_HOST") {
+            let from = env::var("SMTP_FROM")
+                .map_err(|_| AppError::MissingConfig("SMTP_FROM must be set".into()))?;
+            let port = env::var("SMTP_PORT").map_or(Ok(25), |port| {
+                port.parse()
+                    .map_err(|_| AppError::MissingConfig("SMTP_PORT must be a port".into()))
+            })?;
+            let recipients = EmailRecipients::from_env();
+            if recipients.is_empty() {
+                return Err(AppError::MissingConfig(
+                    "SMTP_ALLOWED_DOMAINS must be set".into(),
+                ));
+            }
+            notifiers = notifiers.with(Arc::new(SmtpNotifier::new(host, port, from, recipients)));
+        }
+
+        Ok(notifiers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_smtp_message() {
+        let notification = Notification {
+            reminder_id: Uuid::nil(),
+            task_id: Uuid::nil(),
+            owner_id: "user".into(),
+            title: ".hidden\r\nBcc: someone@example.com".into(),
+            due_at: None,
+            remind_at: Utc::now(),
+            email: None,
+        };
+        let message = smtp_message("from@example.com", "to@example.com", &notification);
+
+        assert!(message.contains("Subject: Reminder: .hidden  Bcc: someone@example.com\r\n"));
+        assert!(message.contains("\r\n\r\n..hidden\r\nBcc: someone@example.com\r\n"));
+        assert!(message.ends_with("No due date\r\n.\r\n"));
+    }
+
+    #[test]
+    fn test_email_recipients() {
+        let recipients = EmailRecipients::new([" Example.com", "", "mail.example.org"]);
+
+        assert!(recipients.allows("someone@example.com"));
+        assert!(recipients.allows("someone@EXAMPLE.COM"));
+        assert!(recipients.allows("someone@mail.example.org"));
+        assert!(!recipients.allows("someone@sub.example.com"));
+        assert!(!recipients.allows("someone@example.com.evil.test"));
+        assert!(!recipients.allows("example.com"));
+        assert!(!EmailRecipients::default().allows("someone@example.com"));
+    }
+}
@@ -0,0 +1,220 @@
+use crate::{
+    errors::app_error::AppError, middlewares::auth::CognitoConfig, utils::jwks::JwksCache,
+};
+use async_trait::async_trait;
+use jsonwebtokens::{raw::decode_only, Verifier, VerifierBuilder};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{collections::HashMap, sync::Arc};
+
+// Token verification abstracted from the identity provider, the Authorization middleware only
+// depends on `TokenVerifier` & `TrustedIssuers` dispatches each token by its (unverified) `iss`
+
+#[async_trait(?Send)]
+pub trait TokenVerifier: Send + Sync {
+    /// Verifies the token's signature & registered claims, returning all of its claims
+    async fn verify(&self, token: &str) -> Result<Value, AppError>;
+}
+
+/// Per-issuer validation settings. An empty audience list skips the audience check.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct IssuerSettings {
+    #[serde(default)]
+    pub audiences: Vec<String>,
+    /// Allowed clock skew in seconds for `exp`, `nbf` & `iat`
+    #[serde(default)]
+    pub leeway: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OidcIssuerConfig {
+    pub issuer: String,
+    #[serde(flatten)]
+    pub settings: IssuerSettings,
+}
+
+impl OidcIssuerConfig {
+    /// Parses `OIDC_ISSUERS`, a JSON array like `[{"issuer": "...", "audiences": [...], "leeway": 30}]`
+    pub fn from_env() -> Result<Vec<Self>, AppError> {
+        match std::env::var("OIDC_ISSUERS") {
+            Ok(raw) => serde_json::from_str(&raw)
+                .map_err(|e| AppError::MissingConfig(format!("Invalid OIDC_ISSUERS: {}", e))),
+            Err(_) => Ok(Vec::new()),
+        }
+    }
+}
+
+pub(crate) fn verifier_builder(issuer: &str, settings: &IssuerSettings) -> VerifierBuilder {
+    let mut builder = Verifier::create();
+    builder
+        .issuer(issuer)
+        .leeway(settings.leeway)
+        // jsonwebtokens only validates `exp` when present, tokens without an expiry are rejected
+        .claim_callback("exp", Value::is_u64);
+
+    builder
+}
+
+fn audience_claim(builder: &mut VerifierBuilder, claim: &str, audiences: &[String]) {
+    if audiences.is_empty() {
+        return;
+    }
+    let audiences = audiences.to_vec();
+    // The claim may be either a single string or an array of strings
+    builder.claim_callback(claim, move |value| match value {
+        Value::String(aud) => audiences.contains(aud),
+        Value::Array(auds) => auds
+            .iter()
+            .filter_map(Value::as_str)
+            .any(|aud| audiences.iter().any(|a| a == aud)),
+        _ => false,
+    });
+}
+
+/// Verifies AWS Cognito access tokens, which carry the app client in `client_id` instead of `aud`
+pub struct CognitoVerifier {
+    issuer: String,
+    keys: Arc<JwksCache>,
+    verifier: Verifier,
+}
+
+impl CognitoVerifier {
+    pub fn new(
+        config: &CognitoConfig,
+        keys: Arc<JwksCache>,
+        mut settings: IssuerSettings,
+    ) -> Result<Self, AppError> {
+        if settings.audiences.is_empty() {
+            settings.audiences.push(config.client_id.clone());
+        }
+        let mut builder = verifier_builder(&config.issuer, &settings);
+        builder.string_equals("token_use", "access");
+        audience_claim(&mut builder, "client_id", &settings.audiences);
+
+        Ok(Self {
+            issuer: config.issuer.clone(),
+            keys,
+            verifier: builder.build().map_err(AppError::JwtGeneric)?,
+        })
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+}
+
+#[async_trait(?Send)]
+impl TokenVerifier for CognitoVerifier {
+    async fn verify(&self, token: &str) -> Result<Value, AppError> {
+        self.keys.verify(token, &self.verifier).await
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DiscoveryDocument {
+    issuer: String,
+    jwks_uri: String,
+}
+
+/// Verifies tokens of any OpenID Connect provider (e.g. Keycloak) configured through discovery
+pub struct OidcVerifier {
+    issuer: String,
+    keys: Arc<JwksCache>,
+    verifier: Verifier,
+}
+
+impl OidcVerifier {
+    /// Fetches `<issuer>/.well-known/openid-configuration` to locate the provider's JWKS
+    pub async fn discover(issuer: &str, settings: IssuerSettings) -> Result<Self, AppError> {
+        let discovery_url =
+            issuer.trim_end_matches('/').to_owned() + "/.well-known/openid-configuration";
+        let document: DiscoveryDocument = reqwest::get(&discovery_url)
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(AppError::JwksFetch)?
+            .json()
+            .await
+            .map_err(AppError::JwksFetch)?;
+        if document.issuer != issuer {
+            return Err(AppError::MissingConfig(format!(
+                "Discovered issuer {} doesn't match the configured issuer {}",
+                document.issuer, issuer
+            )));
+        }
+
+        Self::new(
+            issuer,
+            Arc::new(JwksCache::new(document.jwks_uri)),
+            settings,
+        )
+    }
+
+    pub fn new(
+        issuer: &str,
+        keys: Arc<JwksCache>,
+        settings: IssuerSettings,
+    ) -> Result<Self, AppError> {
+        let mut builder = verifier_builder(issuer, &settings);
+        audience_claim(&mut builder, "aud", &settings.audiences);
+
+        Ok(Self {
+            issuer: issuer.into(),
+            keys,
+            verifier: builder.build().map_err(AppError::JwtGeneric)?,
+        })
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    pub fn keys(&self) -> Arc<JwksCache> {
+        self.keys.clone()
+    }
+}
+
+#[async_trait(?Send)]
+impl TokenVerifier for OidcVerifier {
+    async fn verify(&self, token: &str) -> Result<Value, AppError> {
+        self.keys.verify(token, &self.verifier).await
+    }
+}
+
+/// Accepts tokens from several issuers at once, each verified by its own `TokenVerifier`
+#[derive(Default)]
+pub struct TrustedIssuers {
+    verifiers: HashMap<String, Arc<dyn TokenVerifier>>,
+}
+
+impl TrustedIssuers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, issuer: impl Into<String>, verifier: Arc<dyn TokenVerifier>) -> Self {
+        self.verifiers.insert(issuer.into(), verifier);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.verifiers.is_empty()
+    }
+}
+
+#[async_trait(?Send)]
+impl TokenVerifier for TrustedIssuers {
+    async fn verify(&self, token: &str) -> Result<Value, AppError> {
+        /* Reading `iss` before verification only selects the verifier, which then checks
+        the signature & the issuer itself */
+        let unverified = decode_only(token).map_err(AppError::JwtGeneric)?;
+        let issuer = unverified.claims.get("iss").and_then(Value::as_str).ok_or(
+            AppError::JwtMissingClaim("No issuer claim found in JWT".into()),
+        )?;
+        let verifier = self
+            .verifiers
+            .get(issuer)
+            .ok_or_else(|| AppError::JwtUntrustedIssuer(issuer.into()))?;
+
+        verifier.verify(token).await
+    }
+}
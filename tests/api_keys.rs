@@ -0,0 +1,214 @@
+mod common;
+
+use actix_http::{Request, StatusCode};
+use actix_web::{
+    dev::{Service, ServiceResponse},
+    test, web, App, Error,
+};
+use common::{create_pool, Context, IdpStandIn, SigningKey};
+use diesel::{ExpressionMethods, RunQueryDsl};
+use serde_json::{json, Value};
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use zeronote::{
+    database::connection::Pool,
+    handlers::{api_keys::*, tasks::*},
+    middlewares::auth,
+    models::{api_key::CreatedApiKey, schema::api_keys},
+    utils::verifier::{IssuerSettings, OidcVerifier, TokenVerifier},
+};
+
+// Integration tests for minting, using & revoking API keys
+// Keys are managed with JWTs signed by a local IdP stand-in & then used in place of JWTs
+
+async fn init_app(
+    idp: &IdpStandIn,
+    pool: Pool,
+) -> impl Service<Request, Response = ServiceResponse, Error = Error> {
+    let verifier: Arc<dyn TokenVerifier> = Arc::new(
+        OidcVerifier::discover(idp.issuer(), IssuerSettings::default())
+            .await
+            .unwrap(),
+    );
+    test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool))
+            .app_data(web::Data::from(verifier))
+            .service(
+                web::scope("/api")
+                    .service(get_all_tasks)
+                    .service(create_new_task)
+                    .service(create_api_key)
+                    .service(get_api_keys)
+                    .service(revoke_api_key)
+                    .wrap(auth::Authorization),
+            ),
+    )
+    .await
+}
+
+fn jwt(key: &SigningKey, idp: &IdpStandIn, sub: &str, scope: &str) -> String {
+    let exp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        + 300;
+    key.sign(&json!({"iss": idp.issuer(), "sub": sub, "exp": exp, "scope": scope}))
+}
+
+async fn call(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    req: test::TestRequest,
+    bearer: &str,
+) -> Result<ServiceResponse, StatusCode> {
+    let req = req
+        .insert_header(("Authorization", format!("Bearer {}", bearer)))
+        .to_request();
+    app.call(req)
+        .await
+        .map_err(|e| e.as_response_error().status_code())
+}
+
+async fn status(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    req: test::TestRequest,
+    bearer: &str,
+) -> StatusCode {
+    match call(app, req, bearer).await {
+        Ok(res) => res.status(),
+        Err(status) => status,
+    }
+}
+
+async fn create_key(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    bearer: &str,
+    body: Value,
+) -> CreatedApiKey {
+    let req = test::TestRequest::post().uri("/api/keys").set_json(body);
+    let res = call(app, req, bearer).await.expect("Key creation failed");
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    test::read_body_json(res).await
+}
+
+fn new_task() -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/api/new")
+        .set_json(json!({"title": "Task title", "body": "Task body"}))
+}
+
+#[actix_web::test]
+async fn test_api_key_lifecycle() {
+    let ctx = Context::new("api_key_lifecycle_test");
+    let pool = create_pool(&ctx);
+    let signing_key = SigningKey::generate("keys-key");
+    let idp = IdpStandIn::start(&[&signing_key]).await;
+    let app = init_app(&idp, pool).await;
+    let owner = jwt(&signing_key, &idp, "owner-1", "tasks:read tasks:write");
+
+    let created = create_key(&app, &owner, json!({"name": "CI"})).await;
+    assert!(created.secret.starts_with("zn_"));
+    assert!(created.secret.starts_with(&created.key.prefix));
+    assert_eq!(created.key.owner_id, "owner-1");
+
+    // Tasks created with the key belong to the same owner as with the JWT
+    assert_eq!(
+        status(&app, new_task(), &created.secret).await,
+        StatusCode::OK
+    );
+    let req = test::TestRequest::get().uri("/api/all");
+    let res = call(&app, req, &owner).await.unwrap();
+    let tasks: Vec<Value> = test::read_body_json(res).await;
+    assert_eq!(tasks.len(), 1);
+
+    let req = test::TestRequest::get().uri("/api/keys");
+    let res = call(&app, req, &owner).await.unwrap();
+    let keys: Vec<Value> = test::read_body_json(res).await;
+    assert_eq!(keys.len(), 1);
+    assert_eq!(keys[0]["name"], "CI");
+    assert!(!keys[0]["last_used_at"].is_null(), "Usage wasn't recorded");
+    assert!(
+        keys[0].get("secret").is_none() && keys[0].get("key_hash").is_none(),
+        "Listings must not expose the secret or its hash"
+    );
+
+    let uri = format!("/api/keys/{}", created.key.id);
+    let req = test::TestRequest::delete().uri(&uri);
+    let res = call(&app, req, &owner).await.unwrap();
+    assert_eq!(test::read_body(res).await, "1");
+    assert_eq!(
+        status(
+            &app,
+            test::TestRequest::get().uri("/api/all"),
+            &created.secret
+        )
+        .await,
+        StatusCode::UNAUTHORIZED,
+        "Revoked keys must be rejected"
+    );
+}
+
+#[actix_web::test]
+async fn test_api_key_restrictions() {
+    let ctx = Context::new("api_key_restrictions_test");
+    let pool = create_pool(&ctx);
+    let signing_key = SigningKey::generate("keys-key");
+    let idp = IdpStandIn::start(&[&signing_key]).await;
+    let app = init_app(&idp, pool.clone()).await;
+    let owner = jwt(&signing_key, &idp, "owner-1", "tasks:read tasks:write");
+
+    let read_only = create_key(&app, &owner, json!({"name": "Reports", "read_only": true})).await;
+    let get_all = || test::TestRequest::get().uri("/api/all");
+    assert_eq!(
+        status(&app, get_all(), &read_only.secret).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        status(&app, new_task(), &read_only.secret).await,
+        StatusCode::FORBIDDEN
+    );
+
+    let full = create_key(&app, &owner, json!({"name": "CI", "expires_in_days": 30})).await;
+    let req = test::TestRequest::post()
+        .uri("/api/keys")
+        .set_json(json!({"name": "Escalation"}));
+    assert_eq!(
+        status(&app, req, &full.secret).await,
+        StatusCode::FORBIDDEN,
+        "API keys must not be able to mint other keys"
+    );
+
+    let reader = jwt(&signing_key, &idp, "reader-1", "tasks:read");
+    let req = test::TestRequest::post()
+        .uri("/api/keys")
+        .set_json(json!({"name": "Escalation"}));
+    assert_eq!(
+        status(&app, req, &reader).await,
+        StatusCode::FORBIDDEN,
+        "Writable keys require the write scope"
+    );
+
+    let req = test::TestRequest::post()
+        .uri("/api/keys")
+        .set_json(json!({"name": "Forever", "expires_in_days": 0}));
+    assert_eq!(status(&app, req, &owner).await, StatusCode::BAD_REQUEST);
+
+    let mut conn = pool.get().unwrap();
+    diesel::update(api_keys::table)
+        .filter(api_keys::id.eq(full.key.id))
+        .set(api_keys::expires_at.eq(chrono::Local::now().naive_local()))
+        .execute(&mut conn)
+        .unwrap();
+    assert_eq!(
+        status(&app, get_all(), &full.secret).await,
+        StatusCode::UNAUTHORIZED,
+        "Expired keys must be rejected"
+    );
+    assert_eq!(
+        status(&app, get_all(), "zn_unknown").await,
+        StatusCode::UNAUTHORIZED
+    );
+}
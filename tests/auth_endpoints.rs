@@ -0,0 +1,183 @@
+mod common;
+
+use actix_http::{Request, StatusCode};
+use actix_web::{
+    dev::{Service, ServiceResponse},
+    http::header,
+    test, web, App, Error,
+};
+use base64::{
+    alphabet::URL_SAFE,
+    encode_engine,
+    engine::fast_portable::{FastPortable, NO_PAD},
+};
+use common::IdpStandIn;
+use oauth2::url::Url;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use zeronote::{
+    errors::app_error::AppError,
+    handlers::auth::*,
+    middlewares::auth::CognitoConfig,
+    services::{auth::PendingLogins, device::PendingDevices},
+};
+
+// Integration tests for the server-side OAuth2 PKCE login flow
+// The IdP's token endpoint is a local stand-in, so no real Cognito user pool is needed
+
+async fn init_app(
+    config: CognitoConfig,
+) -> impl Service<Request, Response = ServiceResponse, Error = Error> {
+    test::init_service(
+        App::new()
+            .app_data(
+                web::JsonConfig::default()
+                    .error_handler(|err, _| AppError::JsonPayLoad(err).into()),
+            )
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(PendingLogins::default()))
+            .app_data(web::Data::new(PendingDevices::default()))
+            .service(
+                web::scope("/auth")
+                    .service(login)
+                    .service(login_callback)
+                    .service(refresh_token),
+            ),
+    )
+    .await
+}
+
+async fn call(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    req: Request,
+) -> (StatusCode, ServiceResponse) {
+    let res = test::call_service(app, req).await;
+    (res.status(), res)
+}
+
+// Starts a login & returns the query parameters of the IdP redirect
+async fn start_login(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+) -> HashMap<String, String> {
+    let req = test::TestRequest::get().uri("/auth/login").to_request();
+    let (status, res) = call(app, req).await;
+    assert_eq!(
+        status,
+        StatusCode::FOUND,
+        "Login should redirect to the IdP"
+    );
+
+    let location = res
+        .headers()
+        .get(header::LOCATION)
+        .unwrap()
+        .to_str()
+        .unwrap();
+    Url::parse(location)
+        .unwrap()
+        .query_pairs()
+        .into_owned()
+        .collect()
+}
+
+#[actix_web::test]
+async fn test_login_redirects_with_pkce_challenge() {
+    let idp = IdpStandIn::start(&[]).await;
+    let app = init_app(idp.cognito_config("client-a")).await;
+
+    let params = start_login(&app).await;
+    assert_eq!(params["response_type"], "code");
+    assert_eq!(params["client_id"], "client-a");
+    assert_eq!(params["redirect_uri"], "https://localhost/auth/callback");
+    assert_eq!(params["code_challenge_method"], "S256");
+    assert!(!params["code_challenge"].is_empty());
+    assert!(!params["state"].is_empty());
+
+    let other = start_login(&app).await;
+    assert_ne!(
+        params["state"], other["state"],
+        "Login state must be random"
+    );
+}
+
+#[actix_web::test]
+async fn test_callback_exchanges_code_with_verifier() {
+    let idp = IdpStandIn::start(&[]).await;
+    let app = init_app(idp.cognito_config("client-a")).await;
+    let params = start_login(&app).await;
+
+    let uri = format!("/auth/callback?code=valid-code&state={}", params["state"]);
+    let req = test::TestRequest::get().uri(&uri).to_request();
+    let (status, res) = call(&app, req).await;
+    assert_eq!(status, StatusCode::OK, "Code exchange failed");
+    let body: Value = test::read_body_json(res).await;
+    assert_eq!(body["access_token"], "stand-in-access-token");
+    assert_eq!(body["refresh_token"], "stand-in-refresh-token");
+
+    let token_requests = idp.token_requests();
+    let verifier = &token_requests[0]["code_verifier"];
+    let challenge = encode_engine(
+        Sha256::digest(verifier.as_bytes()),
+        &FastPortable::from(&URL_SAFE, NO_PAD),
+    );
+    assert_eq!(
+        challenge, params["code_challenge"],
+        "Code verifier sent to the IdP doesn't match the challenge"
+    );
+
+    let req = test::TestRequest::get().uri(&uri).to_request();
+    let (status, _) = call(&app, req).await;
+    assert_eq!(
+        status,
+        StatusCode::UNAUTHORIZED,
+        "Login state must not be usable twice"
+    );
+}
+
+#[actix_web::test]
+async fn test_callback_rejects_invalid_state_and_errors() {
+    let idp = IdpStandIn::start(&[]).await;
+    let app = init_app(idp.cognito_config("client-a")).await;
+
+    let req = test::TestRequest::get()
+        .uri("/auth/callback?code=valid-code&state=forged")
+        .to_request();
+    let (status, _) = call(&app, req).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let params = start_login(&app).await;
+    let uri = format!(
+        "/auth/callback?error=access_denied&state={}",
+        params["state"]
+    );
+    let req = test::TestRequest::get().uri(&uri).to_request();
+    let (status, _) = call(&app, req).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert!(
+        idp.token_requests().is_empty(),
+        "No code exchange should be attempted for invalid callbacks"
+    );
+}
+
+#[actix_web::test]
+async fn test_refresh_token() {
+    let idp = IdpStandIn::start(&[]).await;
+    let app = init_app(idp.cognito_config("client-a")).await;
+
+    let req = test::TestRequest::post()
+        .uri("/auth/refresh")
+        .set_json(json!({"refresh_token": "valid-refresh"}))
+        .to_request();
+    let (status, res) = call(&app, req).await;
+    assert_eq!(status, StatusCode::OK);
+    let body: Value = test::read_body_json(res).await;
+    assert_eq!(body["access_token"], "stand-in-access-token");
+
+    let req = test::TestRequest::post()
+        .uri("/auth/refresh")
+        .set_json(json!({"refresh_token": "revoked"}))
+        .to_request();
+    let (status, _) = call(&app, req).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
@@ -1,16 +1,36 @@
-use actix_http::Request;
+#![allow(dead_code)] // Each integration test crate uses only a subset of the helpers
+
+use actix_http::{Request, StatusCode};
 use actix_web::{
     dev::{Service, ServiceResponse},
-    test, Error,
+    http::header,
+    rt, test, web, App, Error, HttpResponse, HttpServer,
+};
+use base64::{
+    alphabet::URL_SAFE,
+    encode_engine,
+    engine::fast_portable::{FastPortable, NO_PAD},
 };
-use base64::encode;
 use diesel::{sql_query, Connection, PgConnection, RunQueryDsl};
 use dotenv::dotenv;
-use hmac::{Hmac, Mac};
-use serde_json::Value;
-use sha2::Sha256;
-use std::{env, process::Command, str};
-use zeronote::database::connection::{init_pool, run_migrations, Pool};
+use jsonwebtokens::{encode as encode_jwt, Algorithm, AlgorithmID};
+use openssl::rsa::Rsa;
+use serde_json::{json, Value};
+use std::{collections::HashMap, env, sync::Arc, sync::Mutex};
+use zeronote::{
+    database::connection::{init_pool, run_migrations, Pool},
+    middlewares::{
+        auth::CognitoConfig,
+        policy::{SCOPE_TASKS_READ, SCOPE_TASKS_WRITE},
+    },
+    models::task::{Task, MERGE_PATCH_CONTENT_TYPE},
+    utils::{
+        local_issuer::LocalIssuer,
+        verifier::{TokenVerifier, TrustedIssuers},
+    },
+};
+
+const URL_SAFE_NO_PAD: FastPortable = FastPortable::from(&URL_SAFE, NO_PAD);
 
 pub struct Context {
     pub db_name: String,
@@ -35,7 +55,7 @@ impl Context {
         let query = sql_query(format!("CREATE DATABASE {};", db_name));
         query
             .execute(&mut conn)
-            .expect(format!("Couldn't create database {}", db_name).as_str());
+            .unwrap_or_else(|_| panic!("Couldn't create database {}", db_name));
 
         Self {
             db_name: db_name.to_string(),
@@ -57,7 +77,7 @@ impl Drop for Context {
         let query = sql_query(format!("DROP DATABASE {};", self.db_name));
         query
             .execute(&mut conn)
-            .expect(format!("Couldn't drop database {}", self.db_name).as_str());
+            .unwrap_or_else(|_| panic!("Couldn't drop database {}", self.db_name));
     }
 }
 
@@ -79,46 +99,34 @@ pub fn create_pool(ctx: &Context) -> Pool {
     pool
 }
 
-pub fn create_signature(username: &str, client_id: &str, client_secret: &str) -> String {
-    type HmacSha256 = Hmac<Sha256>;
-
-    let mut mac = HmacSha256::new_from_slice(client_secret.as_bytes())
-        .expect("Failed creating HMAC for AWS authentication");
-    mac.update((username.to_owned() + client_id).as_bytes());
-    let res_vec = mac.finalize().into_bytes().to_vec();
-
-    encode(&res_vec)
+/// Offline development issuer, so tests go through the real Authorization middleware with JWTs
+/// but need neither Cognito nor the AWS CLI
+pub struct DevAuth {
+    issuer: Arc<LocalIssuer>,
 }
 
-pub fn fetch_jwt() -> String {
-    let client_id = env::var("CLIENT_ID").expect("CLIENT_ID must be set for integration tests");
-    let client_secret =
-        env::var("CLIENT_SECRET").expect("CLIENT_SECRET must be set for integration tests");
-    let keyset_pool_id =
-        env::var("KEYSET_POOL_ID").expect("KEYSET_POOL_ID must be set for integration tests");
-    let username =
-        env::var("OAUTH_USERNAME").expect("OAUTH_USERNAME must be set for integration tests");
-    let password =
-        env::var("OAUTH_PASSWORD").expect("OAUTH_PASSWORD must be set for integration tests");
+impl DevAuth {
+    pub fn new() -> Self {
+        Self {
+            issuer: Arc::new(LocalIssuer::dev().unwrap()),
+        }
+    }
 
-    // Base64(HMAC_SHA256("Client Secret Key", "Username" + "Client Id"))
-    let secret_hash = create_signature(&username, &client_id, &client_secret);
+    /// App data the Authorization middleware verifies tokens with
+    pub fn verifier(&self) -> web::Data<dyn TokenVerifier> {
+        let verifier: Arc<dyn TokenVerifier> =
+            Arc::new(TrustedIssuers::new().with(self.issuer.issuer(), self.issuer.clone()));
+        web::Data::from(verifier)
+    }
 
-    // Requires AWS CLI
-    let aws_command = format!(
-        "aws cognito-idp admin-initiate-auth --user-pool-id '{}' --client-id '{}' --auth-flow 'ADMIN_USER_PASSWORD_AUTH' --auth-parameters USERNAME='{}',PASSWORD='{}',SECRET_HASH='{}' --output 'json'",
-        keyset_pool_id, client_id, username, password, secret_hash
-    );
-    let raw_stdout = Command::new("sh")
-        .arg("-c")
-        .arg(aws_command)
-        .output()
-        .unwrap()
-        .stdout;
-    let res_data: Value = serde_json::from_str(str::from_utf8(&raw_stdout).unwrap()).unwrap();
-    let access_token = res_data["AuthenticationResult"]["AccessToken"].clone();
+    pub fn bearer(&self, sub: &str, groups: &[&str]) -> String {
+        let token = self
+            .issuer
+            .issue_with_groups(sub, None, groups, &[SCOPE_TASKS_READ, SCOPE_TASKS_WRITE])
+            .unwrap();
 
-    "Bearer ".to_owned() + access_token.as_str().unwrap()
+        "Bearer ".to_owned() + &token
+    }
 }
 
 pub async fn get_endpoint_res(
@@ -167,6 +175,23 @@ pub async fn put_endpoint_res(
     res
 }
 
+pub async fn patch_endpoint_res(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    req_body: Value,
+    bearer: &str,
+    uri: &str,
+) -> ServiceResponse {
+    let req = test::TestRequest::patch()
+        .uri(uri)
+        .insert_header(("Authorization", bearer))
+        .set_json(req_body)
+        .insert_header((header::CONTENT_TYPE, MERGE_PATCH_CONTENT_TYPE))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+
+    res
+}
+
 pub async fn delete_endpoint_res(
     app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
     req_body: Value,
@@ -182,3 +207,192 @@ pub async fn delete_endpoint_res(
 
     res
 }
+
+pub async fn create(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    bearer: &str,
+    task: Value,
+) -> Task {
+    let res = post_endpoint_res(app, task, bearer, "/api/v1/tasks").await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+    test::read_body_json(res).await
+}
+
+// Titles of the tasks listed at the uri, in the order they're listed
+pub async fn titles(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    bearer: &str,
+    uri: &str,
+) -> Vec<String> {
+    let res = get_endpoint_res(app, bearer, uri).await;
+    assert_eq!(res.status(), StatusCode::OK, "{}", uri);
+    let tasks: Vec<Task> = test::read_body_json(res).await;
+    tasks.into_iter().map(|task| task.title).collect()
+}
+
+// RSA key pair used to sign test tokens that are verified against a JWKS stand-in
+
+pub struct SigningKey {
+    pub kid: String,
+    algorithm: Algorithm,
+    n: String,
+    e: String,
+}
+
+impl SigningKey {
+    pub fn generate(kid: &str) -> Self {
+        let rsa = Rsa::generate(2048).expect("Failed to generate RSA key");
+        let pem = rsa.private_key_to_pem().unwrap();
+        let mut algorithm = Algorithm::new_rsa_pem_signer(AlgorithmID::RS256, &pem).unwrap();
+        algorithm.set_kid(kid);
+
+        Self {
+            kid: kid.to_string(),
+            algorithm,
+            n: encode_engine(rsa.n().to_vec(), &URL_SAFE_NO_PAD),
+            e: encode_engine(rsa.e().to_vec(), &URL_SAFE_NO_PAD),
+        }
+    }
+
+    pub fn jwk(&self) -> Value {
+        json!({"kid": self.kid, "kty": "RSA", "alg": "RS256", "use": "sig", "n": self.n, "e": self.e})
+    }
+
+    pub fn sign(&self, claims: &Value) -> String {
+        let header = json!({"alg": "RS256", "typ": "JWT", "kid": self.kid});
+        encode_jwt(&header, claims, &self.algorithm).unwrap()
+    }
+}
+
+#[derive(Default)]
+struct StandInState {
+    issuer: String,
+    keys: Vec<Value>,
+    hits: usize,
+    failing: bool,
+    token_requests: Vec<HashMap<String, String>>,
+}
+
+// Local HTTP server impersonating the identity provider's discovery, JWKS & token endpoints
+// The issuer identifier is the stand-in's base URL
+
+pub struct IdpStandIn {
+    pub base_url: String,
+    state: web::Data<Mutex<StandInState>>,
+}
+
+async fn serve_jwks(state: web::Data<Mutex<StandInState>>) -> HttpResponse {
+    let mut state = state.lock().unwrap();
+    state.hits += 1;
+    if state.failing {
+        return HttpResponse::ServiceUnavailable().finish();
+    }
+
+    HttpResponse::Ok().json(json!({ "keys": state.keys }))
+}
+
+async fn serve_discovery(state: web::Data<Mutex<StandInState>>) -> HttpResponse {
+    let issuer = state.lock().unwrap().issuer.clone();
+
+    HttpResponse::Ok().json(json!({
+        "issuer": issuer,
+        "jwks_uri": issuer.clone() + "/.well-known/jwks.json",
+    }))
+}
+
+// Accepts the authorization code "valid-code" & the refresh token "valid-refresh"
+async fn serve_token(
+    state: web::Data<Mutex<StandInState>>,
+    form: web::Form<HashMap<String, String>>,
+) -> HttpResponse {
+    let form = form.into_inner();
+    let field = |name: &str| form.get(name).map(String::as_str);
+    let granted = match field("grant_type") {
+        Some("authorization_code") => {
+            field("code") == Some("valid-code") && field("code_verifier").is_some()
+        }
+        Some("refresh_token") => field("refresh_token") == Some("valid-refresh"),
+        _ => false,
+    };
+    state.lock().unwrap().token_requests.push(form);
+
+    if !granted {
+        return HttpResponse::BadRequest().json(json!({"error": "invalid_grant"}));
+    }
+    HttpResponse::Ok().json(json!({
+        "access_token": "stand-in-access-token",
+        "refresh_token": "stand-in-refresh-token",
+        "token_type": "Bearer",
+        "expires_in": 3600,
+    }))
+}
+
+impl IdpStandIn {
+    pub async fn start(keys: &[&SigningKey]) -> Self {
+        let state = web::Data::new(Mutex::new(StandInState {
+            keys: keys.iter().map(|k| k.jwk()).collect(),
+            ..Default::default()
+        }));
+        let app_state = state.clone();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(app_state.clone())
+                .route("/.well-known/jwks.json", web::get().to(serve_jwks))
+                .route(
+                    "/.well-known/openid-configuration",
+                    web::get().to(serve_discovery),
+                )
+                .route("/oauth2/token", web::post().to(serve_token))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .expect("Failed to bind JWKS stand-in");
+        let base_url = format!("http://{}", server.addrs()[0]);
+        state.lock().unwrap().issuer = base_url.clone();
+        // Stopped together with the test's actix system
+        rt::spawn(server.run());
+
+        Self { base_url, state }
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.base_url
+    }
+
+    pub fn jwks_url(&self) -> String {
+        self.base_url.clone() + "/.well-known/jwks.json"
+    }
+
+    /// Client configuration pointing the OAuth2 flow & the Cognito verifier at the stand-in
+    pub fn cognito_config(&self, client_id: &str) -> CognitoConfig {
+        CognitoConfig {
+            auth_url: self.base_url.clone() + "/oauth2/authorize",
+            token_url: self.base_url.clone() + "/oauth2/token",
+            client_id: client_id.into(),
+            client_secret: "secret".into(),
+            redirect_url: "https://localhost/auth/callback".into(),
+            device_verification_url: "https://localhost/auth/device".into(),
+            scopes: vec!["openid".into()],
+            keyset_region: "local".into(),
+            keyset_pool_id: "local_pool".into(),
+            issuer: self.issuer().into(),
+            jwks_url: self.jwks_url(),
+        }
+    }
+
+    pub fn token_requests(&self) -> Vec<HashMap<String, String>> {
+        self.state.lock().unwrap().token_requests.clone()
+    }
+
+    pub fn rotate(&self, keys: &[&SigningKey]) {
+        self.state.lock().unwrap().keys = keys.iter().map(|k| k.jwk()).collect();
+    }
+
+    pub fn set_failing(&self, failing: bool) {
+        self.state.lock().unwrap().failing = failing;
+    }
+
+    pub fn hits(&self) -> usize {
+        self.state.lock().unwrap().hits
+    }
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use actix_http::{Request, StatusCode};
+use actix_web::{
+    dev::{Service, ServiceResponse},
+    test, web, App, Error,
+};
+use common::{create_pool, Context};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use zeronote::{
+    errors::app_error::AppError,
+    handlers::{local_auth::*, tasks::*},
+    middlewares::auth,
+    models::user::AccessToken,
+    utils::{
+        local_issuer::LocalIssuer,
+        verifier::{TokenVerifier, TrustedIssuers},
+    },
+};
+
+// Integration tests for the offline development mode, minted tokens go through the real
+// Authorization middleware & route policies
+
+async fn init_app(
+    ctx: &Context,
+) -> impl Service<Request, Response = ServiceResponse, Error = Error> {
+    let issuer = Arc::new(LocalIssuer::dev().unwrap());
+    let verifier: Arc<dyn TokenVerifier> =
+        Arc::new(TrustedIssuers::new().with(issuer.issuer(), issuer.clone()));
+    test::init_service(
+        App::new()
+            .app_data(
+                web::JsonConfig::default()
+                    .error_handler(|err, _| AppError::JsonPayLoad(err).into()),
+            )
+            .app_data(web::Data::new(create_pool(ctx)))
+            .app_data(web::Data::from(verifier))
+            .service(
+                web::scope("/auth/dev")
+                    .app_data(web::Data::from(issuer))
+                    .service(dev_token)
+                    .service(local_jwks),
+            )
+            .service(
+                web::scope("/api")
+                    .service(get_all_tasks)
+                    .wrap(auth::Authorization),
+            ),
+    )
+    .await
+}
+
+async fn status(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    req: Request,
+) -> StatusCode {
+    match app.call(req).await {
+        Ok(res) => res.status(),
+        Err(e) => e.as_response_error().status_code(),
+    }
+}
+
+async fn mint(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    body: Value,
+) -> String {
+    let req = test::TestRequest::post()
+        .uri("/auth/dev/token")
+        .set_json(body)
+        .to_request();
+    let res = test::call_service(app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let token: AccessToken = test::read_body_json(res).await;
+
+    token.access_token
+}
+
+fn get_all(token: &str) -> Request {
+    test::TestRequest::get()
+        .uri("/api/all")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request()
+}
+
+#[actix_web::test]
+async fn test_minted_tokens() {
+    let ctx = Context::new("dev_auth_test");
+    let app = init_app(&ctx).await;
+
+    let user = mint(&app, json!({"sub": "user-1"})).await;
+    assert_eq!(status(&app, get_all(&user)).await, StatusCode::OK);
+
+    let scopeless = mint(&app, json!({"sub": "user-1", "scopes": []})).await;
+    assert_eq!(
+        status(&app, get_all(&scopeless)).await,
+        StatusCode::FORBIDDEN
+    );
+    let admin = mint(
+        &app,
+        json!({"sub": "admin-1", "username": "root", "groups": ["admin"], "scopes": []}),
+    )
+    .await;
+    assert_eq!(
+        status(&app, get_all(&admin)).await,
+        StatusCode::OK,
+        "Minted groups must be honored by route policies"
+    );
+
+    let req = test::TestRequest::post()
+        .uri("/auth/dev/token")
+        .set_json(json!({"sub": ""}))
+        .to_request();
+    assert_eq!(status(&app, req).await, StatusCode::BAD_REQUEST);
+
+    let req = test::TestRequest::get()
+        .uri("/auth/dev/jwks.json")
+        .to_request();
+    let jwks: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(jwks["keys"][0]["alg"], "RS256");
+}
@@ -0,0 +1,204 @@
+mod common;
+
+use actix_http::{Request, StatusCode};
+use actix_web::{
+    dev::{Service, ServiceResponse},
+    http::header,
+    test, web, App, Error,
+};
+use common::IdpStandIn;
+use oauth2::{devicecode::StandardDeviceAuthorizationResponse, url::Url};
+use serde_json::Value;
+use std::{collections::HashMap, time::Duration};
+use zeronote::{
+    handlers::auth::*,
+    services::{auth::PendingLogins, device::PendingDevices},
+};
+
+// Integration tests for the device authorization grant brokered on top of the PKCE login
+// The IdP's token endpoint is a local stand-in, so no real Cognito user pool is needed
+
+const GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+async fn init_app(
+    idp: &IdpStandIn,
+    devices: PendingDevices,
+) -> impl Service<Request, Response = ServiceResponse, Error = Error> {
+    test::init_service(
+        App::new()
+            .app_data(web::Data::new(idp.cognito_config("client-a")))
+            .app_data(web::Data::new(PendingLogins::default()))
+            .app_data(web::Data::new(devices))
+            .service(
+                web::scope("/auth")
+                    .service(login_callback)
+                    .service(device_authorization)
+                    .service(device_verification)
+                    .service(device_token),
+            ),
+    )
+    .await
+}
+
+async fn authorize_device(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+) -> Value {
+    let req = test::TestRequest::post()
+        .uri("/auth/device/code")
+        .to_request();
+    let res = test::call_service(app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    test::read_body_json(res).await
+}
+
+async fn poll(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    grant_type: &str,
+    device_code: &str,
+) -> (StatusCode, Value) {
+    let req = test::TestRequest::post()
+        .uri("/auth/device/token")
+        .set_form([("grant_type", grant_type), ("device_code", device_code)])
+        .to_request();
+    let res = test::call_service(app, req).await;
+
+    (res.status(), test::read_body_json(res).await)
+}
+
+// Opens the verification link & returns the state of the IdP redirect
+async fn verify(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    user_code: &str,
+) -> Result<String, StatusCode> {
+    let req = test::TestRequest::get()
+        .uri(&format!("/auth/device?user_code={}", user_code))
+        .to_request();
+    let res = test::call_service(app, req).await;
+    if res.status() != StatusCode::FOUND {
+        return Err(res.status());
+    }
+
+    let location = res
+        .headers()
+        .get(header::LOCATION)
+        .unwrap()
+        .to_str()
+        .unwrap();
+    let params: HashMap<String, String> = Url::parse(location)
+        .unwrap()
+        .query_pairs()
+        .into_owned()
+        .collect();
+    Ok(params["state"].clone())
+}
+
+async fn callback(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    query: &str,
+) -> StatusCode {
+    let req = test::TestRequest::get()
+        .uri(&format!("/auth/callback?{}", query))
+        .to_request();
+    test::call_service(app, req).await.status()
+}
+
+#[actix_web::test]
+async fn test_device_authorization() {
+    let idp = IdpStandIn::start(&[]).await;
+    let app = init_app(&idp, PendingDevices::with_interval(Duration::ZERO)).await;
+
+    let device = authorize_device(&app).await;
+    let parsed: StandardDeviceAuthorizationResponse =
+        serde_json::from_value(device.clone()).expect("Response must follow RFC 8628");
+    assert_eq!(
+        parsed.verification_uri().as_str(),
+        "https://localhost/auth/device"
+    );
+    assert_eq!(parsed.expires_in(), Duration::from_secs(600));
+    let device_code = device["device_code"].as_str().unwrap();
+    let user_code = device["user_code"].as_str().unwrap();
+    assert!(device["verification_uri_complete"]
+        .as_str()
+        .unwrap()
+        .ends_with(&format!("?user_code={}", user_code)));
+
+    let (status, body) = poll(&app, GRANT_TYPE, device_code).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "authorization_pending");
+    let (_, body) = poll(&app, "authorization_code", device_code).await;
+    assert_eq!(body["error"], "unsupported_grant_type");
+
+    assert_eq!(
+        verify(&app, "BCDF-GHJK").await,
+        Err(StatusCode::UNAUTHORIZED),
+        "Unknown user codes must be rejected"
+    );
+    // User codes are accepted regardless of case & dashes
+    let state = verify(&app, &user_code.replace('-', "").to_lowercase())
+        .await
+        .unwrap();
+    let query = format!("code=valid-code&state={}", state);
+    assert_eq!(callback(&app, &query).await, StatusCode::OK);
+
+    let (status, body) = poll(&app, GRANT_TYPE, device_code).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["access_token"], "stand-in-access-token");
+    assert_eq!(body["refresh_token"], "stand-in-refresh-token");
+
+    let (_, body) = poll(&app, GRANT_TYPE, device_code).await;
+    assert_eq!(
+        body["error"], "invalid_grant",
+        "Device codes must be single use"
+    );
+    assert_eq!(
+        verify(&app, user_code).await,
+        Err(StatusCode::UNAUTHORIZED),
+        "User codes must be single use"
+    );
+}
+
+#[actix_web::test]
+async fn test_device_authorization_denied() {
+    let idp = IdpStandIn::start(&[]).await;
+    let app = init_app(&idp, PendingDevices::with_interval(Duration::ZERO)).await;
+    let device = authorize_device(&app).await;
+    let device_code = device["device_code"].as_str().unwrap();
+
+    let state = verify(&app, device["user_code"].as_str().unwrap())
+        .await
+        .unwrap();
+    let query = format!("error=access_denied&state={}", state);
+    assert_eq!(callback(&app, &query).await, StatusCode::UNAUTHORIZED);
+
+    let (status, body) = poll(&app, GRANT_TYPE, device_code).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "access_denied");
+    assert!(idp.token_requests().is_empty());
+}
+
+#[actix_web::test]
+async fn test_device_polling_interval() {
+    let idp = IdpStandIn::start(&[]).await;
+    let app = init_app(&idp, PendingDevices::default()).await;
+    let device = authorize_device(&app).await;
+    assert_eq!(device["interval"], 5);
+    let device_code = device["device_code"].as_str().unwrap();
+
+    let (_, body) = poll(&app, GRANT_TYPE, device_code).await;
+    assert_eq!(body["error"], "authorization_pending");
+    let (_, body) = poll(&app, GRANT_TYPE, device_code).await;
+    assert_eq!(
+        body["error"], "slow_down",
+        "Polling faster than the interval must be throttled"
+    );
+
+    let req = test::TestRequest::get().uri("/auth/device").to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let page = test::read_body(res).await;
+    assert!(
+        std::str::from_utf8(&page).unwrap().contains("user_code"),
+        "Verification page should ask for the user code"
+    );
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use actix_web::rt::time::sleep;
+use common::{IdpStandIn, SigningKey};
+use jsonwebtokens::Verifier;
+use serde_json::json;
+use std::{sync::Arc, time::Duration};
+use zeronote::{errors::app_error::AppError, utils::jwks::JwksCache};
+
+// Tests for the shared JWKS cache against a local stand-in of the identity provider
+// The stand-in counts fetches, can rotate its keys & can simulate an outage
+
+#[actix_web::test]
+async fn test_cached_keys_are_reused() {
+    let key = SigningKey::generate("key-1");
+    let idp = IdpStandIn::start(&[&key]).await;
+    let cache = JwksCache::new(idp.jwks_url()).with_min_fetch_interval(Duration::ZERO);
+
+    cache.refresh().await.expect("Initial JWKS fetch failed");
+    let token = key.sign(&json!({"sub": "user", "exp": 4102444800u64}));
+    let verifier = Verifier::create().build().unwrap();
+    for _ in 0..3 {
+        let algorithm = cache.algorithm(&key.kid).await.unwrap();
+        assert!(
+            verifier.verify(&token, &algorithm).is_ok(),
+            "Token signed with a cached key failed verification"
+        );
+    }
+    assert_eq!(idp.hits(), 1, "Known key IDs shouldn't trigger refetches");
+}
+
+#[actix_web::test]
+async fn test_unknown_kid_triggers_refetch() {
+    let old_key = SigningKey::generate("key-old");
+    let new_key = SigningKey::generate("key-new");
+    let idp = IdpStandIn::start(&[&old_key]).await;
+    let cache = JwksCache::new(idp.jwks_url()).with_min_fetch_interval(Duration::ZERO);
+    cache.refresh().await.unwrap();
+
+    idp.rotate(&[&new_key]);
+    let algorithm = cache.algorithm(&new_key.kid).await;
+    assert!(algorithm.is_ok(), "Rotated key wasn't fetched on demand");
+    assert_eq!(
+        idp.hits(),
+        2,
+        "Unknown key ID should trigger exactly one refetch"
+    );
+    assert_eq!(
+        cache.key_ids(),
+        vec![new_key.kid.clone()],
+        "Keys dropped by the IdP should be dropped from the cache"
+    );
+}
+
+#[actix_web::test]
+async fn test_unknown_kid_refetch_is_throttled() {
+    let key = SigningKey::generate("key-1");
+    let idp = IdpStandIn::start(&[&key]).await;
+    let cache = JwksCache::new(idp.jwks_url()).with_min_fetch_interval(Duration::from_secs(60));
+    cache.refresh().await.unwrap();
+
+    for _ in 0..3 {
+        assert!(matches!(
+            cache.algorithm("bogus").await,
+            Err(AppError::JwtUnknownKey(_))
+        ));
+    }
+    assert_eq!(idp.hits(), 1, "Bogus key IDs shouldn't hammer the IdP");
+}
+
+#[actix_web::test]
+async fn test_last_known_good_keys_survive_outage() {
+    let key = SigningKey::generate("key-1");
+    let idp = IdpStandIn::start(&[&key]).await;
+    let cache = JwksCache::new(idp.jwks_url()).with_min_fetch_interval(Duration::ZERO);
+    cache.refresh().await.unwrap();
+
+    idp.set_failing(true);
+    assert!(
+        matches!(cache.refresh().await, Err(AppError::JwksFetch(_))),
+        "Refresh should report the IdP outage"
+    );
+    assert!(
+        cache.algorithm(&key.kid).await.is_ok(),
+        "Last-known-good key should still be served during an outage"
+    );
+    assert!(matches!(
+        cache.algorithm("key-2").await,
+        Err(AppError::JwtUnknownKey(_))
+    ));
+}
+
+#[actix_web::test]
+async fn test_background_refresh_picks_up_rotation() {
+    let old_key = SigningKey::generate("key-old");
+    let new_key = SigningKey::generate("key-new");
+    let idp = IdpStandIn::start(&[&old_key]).await;
+    let cache = Arc::new(JwksCache::new(idp.jwks_url()).with_ttl(Duration::from_millis(100)));
+
+    JwksCache::spawn_refresh(cache.clone());
+    sleep(Duration::from_millis(50)).await;
+    assert_eq!(cache.key_ids(), vec![old_key.kid.clone()]);
+
+    idp.rotate(&[&old_key, &new_key]);
+    sleep(Duration::from_millis(250)).await;
+    let mut key_ids = cache.key_ids();
+    key_ids.sort();
+    assert_eq!(
+        key_ids,
+        vec![new_key.kid.clone(), old_key.kid.clone()],
+        "Background refresh didn't pick up the rotated key set"
+    );
+}
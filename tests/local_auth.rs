@@ -0,0 +1,203 @@
+mod common;
+
+use actix_http::{Request, StatusCode};
+use actix_web::{
+    dev::{Service, ServiceResponse},
+    test, web, App, Error,
+};
+use common::{create_pool, Context};
+use openssl::rsa::Rsa;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use zeronote::{
+    errors::app_error::AppError,
+    handlers::{local_auth::*, tasks::*},
+    middlewares::auth,
+    models::{task::Task, user::AccessToken},
+    utils::{
+        local_issuer::LocalIssuer,
+        verifier::{TokenVerifier, TrustedIssuers},
+    },
+};
+
+// Integration tests for the built-in local identity provider
+// Issued tokens must pass the same Authorization middleware as Cognito's tokens
+
+async fn init_app(
+    ctx: &Context,
+) -> impl Service<Request, Response = ServiceResponse, Error = Error> {
+    let rsa = Rsa::generate(2048).unwrap();
+    let issuer = Arc::new(LocalIssuer::from_rsa("zeronote", 300, &rsa).unwrap());
+    let verifier: Arc<dyn TokenVerifier> =
+        Arc::new(TrustedIssuers::new().with(issuer.issuer(), issuer.clone()));
+    test::init_service(
+        App::new()
+            .app_data(
+                web::JsonConfig::default()
+                    .error_handler(|err, _| AppError::JsonPayLoad(err).into()),
+            )
+            .app_data(web::Data::new(create_pool(ctx)))
+            .app_data(web::Data::from(verifier))
+            .app_data(web::Data::from(issuer))
+            .service(
+                web::scope("/auth/local")
+                    .service(local_register)
+                    .service(local_login)
+                    .service(change_password)
+                    .service(local_jwks),
+            )
+            .service(
+                web::scope("/api")
+                    .service(get_all_tasks)
+                    .service(create_new_task)
+                    .wrap(auth::Authorization),
+            ),
+    )
+    .await
+}
+
+async fn call(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    req: Request,
+) -> Result<ServiceResponse, StatusCode> {
+    app.call(req)
+        .await
+        .map_err(|e| e.as_response_error().status_code())
+}
+
+async fn post(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    uri: &str,
+    body: Value,
+) -> Result<ServiceResponse, StatusCode> {
+    let req = test::TestRequest::post()
+        .uri(uri)
+        .set_json(body)
+        .to_request();
+    call(app, req).await
+}
+
+fn status(res: Result<ServiceResponse, StatusCode>) -> StatusCode {
+    match res {
+        Ok(res) => res.status(),
+        Err(status) => status,
+    }
+}
+
+async fn login(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    username: &str,
+    password: &str,
+) -> Result<String, StatusCode> {
+    let res = post(
+        app,
+        "/auth/local/login",
+        json!({"username": username, "password": password}),
+    )
+    .await?;
+    if !res.status().is_success() {
+        return Err(res.status());
+    }
+    let token: AccessToken = test::read_body_json(res).await;
+    assert_eq!(token.token_type, "Bearer");
+    assert_eq!(token.expires_in, 300);
+
+    Ok(token.access_token)
+}
+
+#[actix_web::test]
+async fn test_register_login_and_use_token() {
+    let ctx = Context::new("local_auth_login_test");
+    let app = init_app(&ctx).await;
+
+    let credentials = json!({"username": "Alice", "password": "correct horse"});
+    let res = post(&app, "/auth/local/register", credentials.clone())
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let user: Value = test::read_body_json(res).await;
+    assert_eq!(user["username"], "alice");
+    assert!(user.get("password_hash").is_none());
+
+    assert_eq!(
+        status(post(&app, "/auth/local/register", credentials).await),
+        StatusCode::CONFLICT,
+        "Usernames must be unique regardless of case"
+    );
+    assert_eq!(
+        status(
+            post(
+                &app,
+                "/auth/local/register",
+                json!({"username": "bob", "password": "short"})
+            )
+            .await
+        ),
+        StatusCode::BAD_REQUEST
+    );
+
+    assert_eq!(
+        login(&app, "alice", "wrong horse").await,
+        Err(StatusCode::UNAUTHORIZED)
+    );
+    assert_eq!(
+        login(&app, "nobody", "correct horse").await,
+        Err(StatusCode::UNAUTHORIZED)
+    );
+    let token = login(&app, "alice", "correct horse").await.unwrap();
+
+    let req = test::TestRequest::post()
+        .uri("/api/new")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(json!({"title": "Task title", "body": "Task body"}))
+        .to_request();
+    let task: Task = test::read_body_json(call(&app, req).await.unwrap()).await;
+    assert_eq!(
+        task.owner_id,
+        user["id"].as_str().unwrap(),
+        "Tasks must be owned by the local user's ID"
+    );
+
+    let req = test::TestRequest::get()
+        .uri("/auth/local/jwks.json")
+        .to_request();
+    let jwks: Value = test::read_body_json(call(&app, req).await.unwrap()).await;
+    assert_eq!(jwks["keys"][0]["alg"], "RS256");
+}
+
+#[actix_web::test]
+async fn test_change_password() {
+    let ctx = Context::new("local_auth_password_test");
+    let app = init_app(&ctx).await;
+    let credentials = json!({"username": "alice", "password": "correct horse"});
+    post(&app, "/auth/local/register", credentials)
+        .await
+        .unwrap();
+    let token = login(&app, "alice", "correct horse").await.unwrap();
+
+    let change = |bearer: &str, current: &str| {
+        test::TestRequest::put()
+            .uri("/auth/local/password")
+            .insert_header(("Authorization", format!("Bearer {}", bearer)))
+            .set_json(json!({"current_password": current, "new_password": "battery staple"}))
+            .to_request()
+    };
+    assert_eq!(
+        status(call(&app, change(&token, "wrong horse")).await),
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        status(call(&app, change("invalid", "correct horse")).await),
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        status(call(&app, change(&token, "correct horse")).await),
+        StatusCode::NO_CONTENT
+    );
+
+    assert_eq!(
+        login(&app, "alice", "correct horse").await,
+        Err(StatusCode::UNAUTHORIZED)
+    );
+    assert!(login(&app, "alice", "battery staple").await.is_ok());
+}
@@ -0,0 +1,154 @@
+mod common;
+
+use actix_http::{Request, StatusCode};
+use actix_web::{
+    dev::{Service, ServiceResponse},
+    test, web, App, Error, HttpResponse,
+};
+use common::{create_pool, Context, IdpStandIn, SigningKey};
+use serde_json::{json, Value};
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use zeronote::{
+    errors::app_error::ProblemDetails,
+    handlers::tasks::*,
+    middlewares::{
+        auth,
+        policy::{Policy, GROUP_ADMIN},
+    },
+    utils::verifier::{IssuerSettings, OidcVerifier, TokenVerifier},
+};
+
+// Integration tests for scope & group based policies on the task routes
+// Tokens are signed by a local IdP stand-in, denials must be 403 instead of 401
+
+async fn init_app(
+    idp: &IdpStandIn,
+    ctx: &Context,
+) -> impl Service<Request, Response = ServiceResponse, Error = Error> {
+    let verifier: Arc<dyn TokenVerifier> = Arc::new(
+        OidcVerifier::discover(idp.issuer(), IssuerSettings::default())
+            .await
+            .unwrap(),
+    );
+    test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_pool(ctx)))
+            .app_data(web::Data::from(verifier))
+            .service(
+                web::scope("/api")
+                    .service(get_all_tasks)
+                    .service(create_new_task)
+                    .service(
+                        web::resource("/admin")
+                            .wrap(Policy::group(GROUP_ADMIN))
+                            .to(HttpResponse::Ok),
+                    )
+                    .wrap(auth::Authorization),
+            ),
+    )
+    .await
+}
+
+fn token(key: &SigningKey, idp: &IdpStandIn, groups: &[&str], scope: &str) -> String {
+    let exp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        + 300;
+    key.sign(&json!({
+        "iss": idp.issuer(), "sub": "user-1", "exp": exp,
+        "cognito:groups": groups, "scope": scope
+    }))
+}
+
+async fn call(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    req: test::TestRequest,
+    token: &str,
+) -> StatusCode {
+    let req = req
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
+    match app.call(req).await {
+        Ok(res) => res.status(),
+        Err(e) => e.as_response_error().status_code(),
+    }
+}
+
+fn new_task() -> Value {
+    json!({"title": "Task title", "body": "Task body"})
+}
+
+#[actix_web::test]
+async fn test_task_routes_require_scopes() {
+    let ctx = Context::new("task_scopes_test");
+    let key = SigningKey::generate("policy-key");
+    let idp = IdpStandIn::start(&[&key]).await;
+    let app = init_app(&idp, &ctx).await;
+
+    let reader = token(&key, &idp, &[], "openid tasks:read");
+    let get = || test::TestRequest::get().uri("/api/all");
+    let post = || {
+        test::TestRequest::post()
+            .uri("/api/new")
+            .set_json(new_task())
+    };
+    assert_eq!(call(&app, get(), &reader).await, StatusCode::OK);
+    assert_eq!(
+        call(&app, post(), &reader).await,
+        StatusCode::FORBIDDEN,
+        "Read scope must not allow creating tasks"
+    );
+
+    let writer = token(&key, &idp, &[], "https://api.zeronote.dev/tasks:write");
+    assert_eq!(
+        call(&app, post(), &writer).await,
+        StatusCode::OK,
+        "Resource server prefixed scopes should be accepted"
+    );
+    assert_eq!(call(&app, get(), &writer).await, StatusCode::FORBIDDEN);
+
+    let unscoped = token(&key, &idp, &[], "openid");
+    assert_eq!(call(&app, get(), &unscoped).await, StatusCode::FORBIDDEN);
+    assert_eq!(
+        call(&app, get(), "not-a-token").await,
+        StatusCode::UNAUTHORIZED,
+        "Authentication failures should stay 401"
+    );
+}
+
+#[actix_web::test]
+async fn test_admin_group_policy() {
+    let ctx = Context::new("admin_policy_test");
+    let key = SigningKey::generate("policy-key");
+    let idp = IdpStandIn::start(&[&key]).await;
+    let app = init_app(&idp, &ctx).await;
+
+    let admin = token(&key, &idp, &[GROUP_ADMIN], "openid");
+    let post = || {
+        test::TestRequest::post()
+            .uri("/api/new")
+            .set_json(new_task())
+    };
+    assert_eq!(call(&app, post(), &admin).await, StatusCode::OK);
+    assert_eq!(
+        call(&app, test::TestRequest::get().uri("/api/admin"), &admin).await,
+        StatusCode::OK
+    );
+
+    let staff = token(&key, &idp, &["staff"], "tasks:read tasks:write");
+    let req = test::TestRequest::get()
+        .uri("/api/admin")
+        .insert_header(("Authorization", format!("Bearer {}", staff)))
+        .to_request();
+    let err = app.call(req).await.expect_err("Non-admins must be denied");
+    let res = err.error_response();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    let body = actix_web::body::to_bytes(res.into_body()).await.unwrap();
+    let body: ProblemDetails = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body.status, 403);
+    assert_eq!(body.detail, "Requires group 'admin'");
+}
@@ -0,0 +1,162 @@
+mod common;
+
+use actix_http::{Request, StatusCode};
+use actix_web::{
+    body::{to_bytes, BoxBody, EitherBody},
+    dev::{Service, ServiceResponse},
+    http::header::{self, HeaderMap},
+    test, web, App, Error,
+};
+use common::{create_pool, Context, DevAuth};
+use serde_json::{json, Value};
+use zeronote::{
+    errors::app_error::{AppError, AppErrorResponse, ProblemDetails},
+    handlers::tasks::*,
+    middlewares::{auth, problem::ProblemResponses},
+};
+
+// Integration tests for RFC 7807 error responses, request IDs & the legacy error format
+
+async fn init_app(
+    ctx: &Context,
+    dev: &DevAuth,
+) -> impl Service<Request, Response = ServiceResponse<EitherBody<BoxBody>>, Error = Error> {
+    test::init_service(
+        App::new()
+            .wrap(ProblemResponses)
+            .app_data(
+                web::JsonConfig::default()
+                    .error_handler(|err, _| AppError::JsonPayLoad(err).into()),
+            )
+            .app_data(web::Data::new(create_pool(ctx)))
+            .app_data(dev.verifier())
+            .service(
+                web::scope("/api/v1")
+                    .service(create_task)
+                    .wrap(auth::Authorization),
+            ),
+    )
+    .await
+}
+
+fn create(bearer: &str, task: Value) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/api/v1/tasks")
+        .insert_header(("Authorization", bearer))
+        .set_json(task)
+}
+
+fn header_value<'a>(headers: &'a HeaderMap, name: &str) -> &'a str {
+    headers.get(name).unwrap().to_str().unwrap()
+}
+
+#[actix_web::test]
+async fn test_validation_problem() {
+    let ctx = Context::new("problem_validation_test");
+    let dev = DevAuth::new();
+    let bearer = dev.bearer("task-tests-user", &[]);
+    let app = init_app(&ctx, &dev).await;
+
+    let req = create(&bearer, json!({"title": "t".repeat(61), "body": ""}))
+        .insert_header(("X-Request-Id", "client-req_1.2"))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        header_value(res.headers(), header::CONTENT_TYPE.as_str()),
+        "application/problem+json"
+    );
+    assert_eq!(
+        header_value(res.headers(), "x-request-id"),
+        "client-req_1.2"
+    );
+
+    let problem: ProblemDetails = test::read_body_json(res).await;
+    assert_eq!(problem.problem_type, "urn:zeronote:problem:validation");
+    assert_eq!(problem.status, 400);
+    assert_eq!(problem.request_id.as_deref(), Some("client-req_1.2"));
+    assert_eq!(
+        problem.errors["title"],
+        ["Title must be between 1 and 60 characters long"]
+    );
+    assert_eq!(
+        problem.errors["body"],
+        ["Body must be at least 1 character long"]
+    );
+}
+
+#[actix_web::test]
+async fn test_middleware_errors_and_request_ids() {
+    let ctx = Context::new("problem_request_id_test");
+    let dev = DevAuth::new();
+    let app = init_app(&ctx, &dev).await;
+
+    // Errors of the Authorization middleware are rendered as problems too
+    let req = test::TestRequest::post()
+        .uri("/api/v1/tasks")
+        .insert_header(("X-Request-Id", "not a valid id"))
+        .to_request();
+    let res = app
+        .call(req)
+        .await
+        .expect_err("Requests without a token must be rejected")
+        .error_response();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    let request_id = header_value(res.headers(), "x-request-id").to_owned();
+    assert_ne!(
+        request_id, "not a valid id",
+        "Malformed request IDs must be replaced"
+    );
+
+    let body = to_bytes(res.into_body()).await.unwrap();
+    let problem: ProblemDetails = serde_json::from_slice(&body).unwrap();
+    assert_eq!(problem.problem_type, "urn:zeronote:problem:unauthenticated");
+    assert_eq!(problem.request_id, Some(request_id));
+    assert!(problem.errors.is_empty());
+
+    let bearer = dev.bearer("task-tests-user", &[]);
+    let req = create(&bearer, json!({"title": "Task title", "body": "Task body"})).to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+    assert!(
+        res.headers().contains_key("x-request-id"),
+        "Successful responses carry the request ID as well"
+    );
+}
+
+#[actix_web::test]
+async fn test_legacy_error_format() {
+    let ctx = Context::new("problem_legacy_test");
+    let dev = DevAuth::new();
+    let bearer = dev.bearer("task-tests-user", &[]);
+    let app = init_app(&ctx, &dev).await;
+
+    let req = create(&bearer, json!({"title": ""}))
+        .insert_header((header::ACCEPT, "application/json"))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        header_value(res.headers(), header::CONTENT_TYPE.as_str()),
+        "application/json"
+    );
+    let body: AppErrorResponse = test::read_body_json(res).await;
+    assert_eq!(body.code, "400");
+
+    for accept in [
+        "application/problem+json, application/json",
+        "application/json;q=0.5, application/problem+json",
+        "*/*",
+    ] {
+        let req = create(&bearer, json!({"title": ""}))
+            .insert_header((header::ACCEPT, accept))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(
+            header_value(res.headers(), header::CONTENT_TYPE.as_str()),
+            "application/problem+json",
+            "{}",
+            accept
+        );
+    }
+}
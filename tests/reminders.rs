@@ -0,0 +1,521 @@
+mod common;
+
+use actix_http::{Request, StatusCode};
+use actix_web::{
+    dev::{Service, ServiceResponse},
+    rt, test, web, App, Error, HttpRequest, HttpResponse, HttpServer,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, SubsecRound, Utc};
+use common::{
+    create_pool, delete_endpoint_res, get_endpoint_res, patch_endpoint_res, post_endpoint_res,
+    Context, DevAuth,
+};
+use diesel::{sql_query, sql_types::Uuid as SqlUuid, RunQueryDsl};
+use serde_json::{json, Value};
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+use uuid::Uuid;
+use zeronote::{
+    database::connection::Pool,
+    errors::app_error::AppError,
+    handlers::{reminders::*, tasks::*},
+    middlewares::auth,
+    models::{
+        reminder::{Notification, Reminder},
+        task::Task,
+    },
+    services::reminders::ReminderScheduler,
+    utils::notifier::{EmailRecipients, Notifier, Notifiers, SmtpNotifier, WebhookNotifier},
+};
+
+// Integration tests for the reminder endpoints & the scheduler delivering due reminders
+
+async fn init_app(
+    pool: web::Data<Pool>,
+    dev: &DevAuth,
+) -> impl Service<Request, Response = ServiceResponse, Error = Error> {
+    test::init_service(
+        App::new()
+            .app_data(pool)
+            .app_data(dev.verifier())
+            .app_data(web::Data::new(EmailRecipients::new(["example.com"])))
+            .service(
+                web::scope("/api/v1")
+                    .service(create_task)
+                    .service(patch_task)
+                    .service(remove_task)
+                    .service(list_reminders)
+                    .service(create_reminder)
+                    .service(delete_reminder)
+                    .service(snooze_reminder)
+                    .service(dismiss_reminder)
+                    .wrap(auth::Authorization),
+            ),
+    )
+    .await
+}
+
+async fn create_task_res(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    bearer: &str,
+    title: &str,
+    due_at: Option<DateTime<Utc>>,
+) -> Task {
+    let res = post_endpoint_res(
+        app,
+        json!({"title": title, "body": "Task body", "due_at": due_at}),
+        bearer,
+        "/api/v1/tasks",
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+    test::read_body_json(res).await
+}
+
+async fn create_reminder_res(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    bearer: &str,
+    task_id: Uuid,
+    reminder: Value,
+) -> Reminder {
+    let uri = format!("/api/v1/tasks/{}/reminders", task_id);
+    let res = post_endpoint_res(app, reminder, bearer, &uri).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+    test::read_body_json(res).await
+}
+
+// Moves the reminder into the past, so the next scheduler run picks it up
+fn make_due(pool: &Pool, reminder_id: Uuid) {
+    sql_query("UPDATE reminders SET remind_at = now() - interval '1 minute' WHERE id = $1")
+        .bind::<SqlUuid, _>(reminder_id)
+        .execute(&mut pool.get().unwrap())
+        .unwrap();
+}
+
+struct RecordingNotifier {
+    channel: &'static str,
+    delivered: Mutex<Vec<Notification>>,
+    failing: AtomicBool,
+}
+
+impl RecordingNotifier {
+    fn new(channel: &'static str) -> Arc<Self> {
+        Arc::new(Self {
+            channel,
+            delivered: Mutex::default(),
+            failing: AtomicBool::default(),
+        })
+    }
+
+    fn reminder_ids(&self) -> Vec<Uuid> {
+        let delivered = self.delivered.lock().unwrap();
+        delivered.iter().map(|n| n.reminder_id).collect()
+    }
+}
+
+#[async_trait(?Send)]
+impl Notifier for RecordingNotifier {
+    fn channel(&self) -> &str {
+        self.channel
+    }
+
+    async fn notify(&self, notification: &Notification) -> Result<(), AppError> {
+        if self.failing.load(Ordering::SeqCst) {
+            return Err(AppError::Notify("Stand-in failure".into()));
+        }
+        self.delivered.lock().unwrap().push(notification.clone());
+        Ok(())
+    }
+}
+
+#[actix_web::test]
+async fn test_reminder_endpoints() {
+    let ctx = Context::new("reminders_test");
+    let dev = DevAuth::new();
+    let bearer = dev.bearer("reminder-tests-user", &[]);
+    let other = dev.bearer("other-user", &[]);
+    let app = init_app(web::Data::new(create_pool(&ctx)), &dev).await;
+
+    let due_at = (Utc::now() + Duration::days(2)).round_subsecs(0);
+    let task = create_task_res(&app, &bearer, "dated", Some(due_at)).await;
+    let relative =
+        create_reminder_res(&app, &bearer, task.id, json!({"minutes_before_due": 60})).await;
+    assert_eq!(relative.remind_at, Some(due_at - Duration::hours(1)));
+    let fixed_at = (Utc::now() + Duration::hours(3)).round_subsecs(0);
+    let fixed = create_reminder_res(
+        &app,
+        &bearer,
+        task.id,
+        json!({"remind_at": fixed_at, "email": "someone@example.com"}),
+    )
+    .await;
+    assert_eq!(fixed.email.as_deref(), Some("someone@example.com"));
+
+    let uri = format!("/api/v1/tasks/{}/reminders", task.id);
+    for invalid in [
+        json!({}),
+        json!({"remind_at": Utc::now() - Duration::hours(1)}),
+        json!({"minutes_before_due": 30, "email": "not an address"}),
+    ] {
+        let res = post_endpoint_res(&app, invalid.clone(), &bearer, &uri).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{}", invalid);
+    }
+    // Only addresses in the allowed domains can be emailed
+    let elsewhere = json!({"minutes_before_due": 30, "email": "someone@elsewhere.test"});
+    let res = post_endpoint_res(&app, elsewhere, &bearer, &uri).await;
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let undated = create_task_res(&app, &bearer, "undated", None).await;
+    let res = post_endpoint_res(
+        &app,
+        json!({"minutes_before_due": 30}),
+        &bearer,
+        &format!("/api/v1/tasks/{}/reminders", undated.id),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let res = get_endpoint_res(&app, &other, &uri).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    // Moving the due date moves the relative reminder, the fixed one stays where it was
+    let new_due_at = due_at + Duration::days(1);
+    let res = patch_endpoint_res(
+        &app,
+        json!({ "due_at": new_due_at }),
+        &bearer,
+        &format!("/api/v1/tasks/{}", task.id),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let res = get_endpoint_res(&app, &bearer, &uri).await;
+    let listed: Vec<Reminder> = test::read_body_json(res).await;
+    let times: Vec<_> = listed.iter().map(|r| r.remind_at).collect();
+    assert_eq!(
+        times,
+        [Some(new_due_at - Duration::hours(1)), Some(fixed_at)]
+    );
+
+    // Snoozed reminders no longer follow the due date
+    let snooze_uri = format!("/api/v1/reminders/{}/snooze", relative.id);
+    let res = post_endpoint_res(&app, json!({"minutes": 0}), &bearer, &snooze_uri).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let res = post_endpoint_res(&app, json!({"minutes": 15}), &other, &snooze_uri).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    let res = post_endpoint_res(&app, json!({"minutes": 15}), &bearer, &snooze_uri).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let snoozed: Reminder = test::read_body_json(res).await;
+    assert_eq!(snoozed.minutes_before_due, None);
+    assert!(snoozed.remind_at.unwrap() < Utc::now() + Duration::minutes(16));
+
+    let dismiss_uri = format!("/api/v1/reminders/{}/dismiss", fixed.id);
+    let res = post_endpoint_res(&app, json!({}), &bearer, &dismiss_uri).await;
+    let dismissed: Reminder = test::read_body_json(res).await;
+    assert!(dismissed.dismissed_at.is_some());
+
+    let reminder_uri = format!("/api/v1/reminders/{}", fixed.id);
+    let res = delete_endpoint_res(&app, json!({}), &other, &reminder_uri).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    let res = delete_endpoint_res(&app, json!({}), &bearer, &reminder_uri).await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    let res = get_endpoint_res(&app, &bearer, &uri).await;
+    let listed: Vec<Reminder> = test::read_body_json(res).await;
+    assert_eq!(listed.len(), 1);
+}
+
+#[actix_web::test]
+async fn test_reminder_scheduler() {
+    let ctx = Context::new("reminders_scheduler_test");
+    let dev = DevAuth::new();
+    let bearer = dev.bearer("reminder-tests-user", &[]);
+    let pool = create_pool(&ctx);
+    let app = init_app(web::Data::new(pool.clone()), &dev).await;
+    let notifier = RecordingNotifier::new("recording");
+    let notifiers = Notifiers::new().with(notifier.clone());
+    let scheduler = ReminderScheduler::default();
+
+    let in_an_hour = json!({"remind_at": Utc::now() + Duration::hours(1)});
+    let task = create_task_res(&app, &bearer, "notified", None).await;
+    let due = create_reminder_res(&app, &bearer, task.id, in_an_hour.clone()).await;
+    let pending = create_reminder_res(&app, &bearer, task.id, in_an_hour.clone()).await;
+    let dismissed = create_reminder_res(&app, &bearer, task.id, in_an_hour.clone()).await;
+    let done = create_task_res(&app, &bearer, "done", None).await;
+    let of_done = create_reminder_res(&app, &bearer, done.id, in_an_hour.clone()).await;
+    let trashed = create_task_res(&app, &bearer, "trashed", None).await;
+    let of_trashed = create_reminder_res(&app, &bearer, trashed.id, in_an_hour).await;
+
+    let uri = format!("/api/v1/reminders/{}/dismiss", dismissed.id);
+    post_endpoint_res(&app, json!({}), &bearer, &uri).await;
+    let uri = format!("/api/v1/tasks/{}", done.id);
+    patch_endpoint_res(&app, json!({"condition": "done"}), &bearer, &uri).await;
+    let uri = format!("/api/v1/tasks/{}", trashed.id);
+    delete_endpoint_res(&app, json!({}), &bearer, &uri).await;
+    for reminder in [&due, &dismissed, &of_done, &of_trashed] {
+        make_due(&pool, reminder.id);
+    }
+
+    // Delivered once, even by another scheduler, e.g. after a restart
+    assert_eq!(scheduler.run(&pool, &notifiers).await.unwrap(), 1);
+    assert_eq!(notifier.reminder_ids(), [due.id]);
+    let restarted = ReminderScheduler::default();
+    assert_eq!(restarted.run(&pool, &notifiers).await.unwrap(), 0);
+    assert_eq!(notifier.reminder_ids(), [due.id]);
+
+    // Failed deliveries are retried with the same idempotency key once the backoff has passed
+    make_due(&pool, pending.id);
+    notifier.failing.store(true, Ordering::SeqCst);
+    assert_eq!(scheduler.run(&pool, &notifiers).await.unwrap(), 0);
+    notifier.failing.store(false, Ordering::SeqCst);
+    assert_eq!(scheduler.run(&pool, &notifiers).await.unwrap(), 0);
+    sql_query("UPDATE reminders SET claimed_until = now() WHERE id = $1")
+        .bind::<SqlUuid, _>(pending.id)
+        .execute(&mut pool.get().unwrap())
+        .unwrap();
+    assert_eq!(scheduler.run(&pool, &notifiers).await.unwrap(), 1);
+    assert_eq!(notifier.reminder_ids(), [due.id, pending.id]);
+    let notification = notifier.delivered.lock().unwrap()[1].clone();
+    assert_eq!(notification.title, "notified");
+
+    // Snoozing re-arms a fired reminder
+    let uri = format!("/api/v1/reminders/{}/snooze", due.id);
+    post_endpoint_res(&app, json!({"minutes": 5}), &bearer, &uri).await;
+    make_due(&pool, due.id);
+    assert_eq!(scheduler.run(&pool, &notifiers).await.unwrap(), 1);
+}
+
+#[actix_web::test]
+async fn test_partial_delivery() {
+    let ctx = Context::new("reminders_partial_test");
+    let dev = DevAuth::new();
+    let bearer = dev.bearer("reminder-tests-user", &[]);
+    let pool = create_pool(&ctx);
+    let app = init_app(web::Data::new(pool.clone()), &dev).await;
+    let accepting = RecordingNotifier::new("accepting");
+    let failing = RecordingNotifier::new("failing");
+    failing.failing.store(true, Ordering::SeqCst);
+    let notifiers = Notifiers::new()
+        .with(accepting.clone())
+        .with(failing.clone());
+    let scheduler = ReminderScheduler::default();
+
+    let task = create_task_res(&app, &bearer, "partial", None).await;
+    let reminder = create_reminder_res(
+        &app,
+        &bearer,
+        task.id,
+        json!({"remind_at": Utc::now() + Duration::hours(1)}),
+    )
+    .await;
+    make_due(&pool, reminder.id);
+    assert_eq!(scheduler.run(&pool, &notifiers).await.unwrap(), 0);
+    assert_eq!(accepting.reminder_ids(), [reminder.id]);
+    assert!(failing.reminder_ids().is_empty());
+
+    // The retry only goes to the channel that failed
+    failing.failing.store(false, Ordering::SeqCst);
+    sql_query("UPDATE reminders SET claimed_until = now() WHERE id = $1")
+        .bind::<SqlUuid, _>(reminder.id)
+        .execute(&mut pool.get().unwrap())
+        .unwrap();
+    assert_eq!(scheduler.run(&pool, &notifiers).await.unwrap(), 1);
+    assert_eq!(accepting.reminder_ids(), [reminder.id]);
+    assert_eq!(failing.reminder_ids(), [reminder.id]);
+
+    // Snoozing re-arms the reminder for every channel, including those that had accepted it
+    let snoozed = create_reminder_res(
+        &app,
+        &bearer,
+        task.id,
+        json!({"remind_at": Utc::now() + Duration::hours(1)}),
+    )
+    .await;
+    make_due(&pool, snoozed.id);
+    failing.failing.store(true, Ordering::SeqCst);
+    assert_eq!(scheduler.run(&pool, &notifiers).await.unwrap(), 0);
+    let uri = format!("/api/v1/reminders/{}/snooze", snoozed.id);
+    post_endpoint_res(&app, json!({"minutes": 5}), &bearer, &uri).await;
+    make_due(&pool, snoozed.id);
+    failing.failing.store(false, Ordering::SeqCst);
+    assert_eq!(scheduler.run(&pool, &notifiers).await.unwrap(), 1);
+    assert_eq!(
+        accepting.reminder_ids(),
+        [reminder.id, snoozed.id, snoozed.id]
+    );
+}
+
+// Scripted SMTP relay accepting a single connection, returns the commands & the message it was
+// sent. Recipients are answered with the given reply
+fn smtp_stand_in(rcpt_reply: &'static str) -> (u16, JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let handle = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut received = Vec::new();
+        let mut read_line = |received: &mut Vec<String>| {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            received.push(line.clone());
+            line
+        };
+
+        stream
+            .write_all(b"220 relay.example.com ESMTP\r\n")
+            .unwrap();
+        read_line(&mut received);
+        stream
+            .write_all(b"250-relay.example.com\r\n250-SIZE 1048576\r\n250 8BITMIME\r\n")
+            .unwrap();
+        read_line(&mut received);
+        stream.write_all(b"250 OK\r\n").unwrap();
+        read_line(&mut received);
+        stream.write_all(rcpt_reply.as_bytes()).unwrap();
+        if !rcpt_reply.starts_with("250") {
+            return received;
+        }
+        read_line(&mut received);
+        stream.write_all(b"354 Go ahead\r\n").unwrap();
+        while read_line(&mut received) != ".\r\n" {}
+        stream.write_all(b"250 Queued\r\n").unwrap();
+        read_line(&mut received);
+        received
+    });
+
+    (port, handle)
+}
+
+#[actix_web::test]
+async fn test_smtp_notifier() {
+    let notification = Notification {
+        reminder_id: Uuid::new_v4(),
+        task_id: Uuid::new_v4(),
+        owner_id: "reminder-tests-user".into(),
+        title: ".hidden".into(),
+        due_at: None,
+        remind_at: Utc::now(),
+        email: Some("someone@example.com".into()),
+    };
+
+    let recipients = EmailRecipients::new(["example.com"]);
+    let (port, relay) = smtp_stand_in("250 OK\r\n");
+    let notifier = SmtpNotifier::new(
+        "127.0.0.1",
+        port,
+        "zeronote@example.com",
+        recipients.clone(),
+    );
+    notifier.notify(&notification).await.unwrap();
+    let received = relay.join().unwrap();
+    assert_eq!(received[0], "EHLO zeronote\r\n");
+    assert_eq!(received[1], "MAIL FROM:<zeronote@example.com>\r\n");
+    assert_eq!(received[2], "RCPT TO:<someone@example.com>\r\n");
+    assert_eq!(received[3], "DATA\r\n");
+    let message = received[4..].concat();
+    assert!(message.contains("Subject: Reminder: .hidden\r\n"));
+    assert!(message.contains("\r\n\r\n..hidden\r\n"));
+    assert!(message.ends_with("\r\n.\r\nQUIT\r\n"));
+
+    for rejection in ["550 No such user\r\n", "451 Try again later\r\n"] {
+        let (port, relay) = smtp_stand_in(rejection);
+        let notifier = SmtpNotifier::new(
+            "127.0.0.1",
+            port,
+            "zeronote@example.com",
+            recipients.clone(),
+        );
+        assert!(
+            notifier.notify(&notification).await.is_err(),
+            "{}",
+            rejection
+        );
+        relay.join().unwrap();
+    }
+
+    // Addresses outside the allowed domains are skipped without connecting to the relay
+    let notification = Notification {
+        email: Some("someone@elsewhere.test".into()),
+        ..notification
+    };
+    let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let notifier = SmtpNotifier::new("127.0.0.1", port, "zeronote@example.com", recipients);
+    notifier.notify(&notification).await.unwrap();
+    assert!(listener.accept().is_err());
+}
+
+#[derive(Default)]
+struct WebhookState {
+    requests: Vec<(String, Value)>,
+}
+
+async fn receive_webhook(
+    req: HttpRequest,
+    body: web::Json<Value>,
+    state: web::Data<Mutex<WebhookState>>,
+) -> HttpResponse {
+    let key = req
+        .headers()
+        .get("Idempotency-Key")
+        .and_then(|key| key.to_str().ok())
+        .unwrap_or_default()
+        .to_owned();
+    state
+        .lock()
+        .unwrap()
+        .requests
+        .push((key, body.into_inner()));
+    HttpResponse::Accepted().finish()
+}
+
+#[actix_web::test]
+async fn test_webhook_notifier() {
+    let ctx = Context::new("reminders_webhook_test");
+    let dev = DevAuth::new();
+    let bearer = dev.bearer("reminder-tests-user", &[]);
+    let pool = create_pool(&ctx);
+    let app = init_app(web::Data::new(pool.clone()), &dev).await;
+
+    let state = web::Data::new(Mutex::new(WebhookState::default()));
+    let app_state = state.clone();
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(app_state.clone())
+            .route("/hooks/reminders", web::post().to(receive_webhook))
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))
+    .unwrap();
+    let url = format!("http://{}/hooks/reminders", server.addrs()[0]);
+    rt::spawn(server.run());
+
+    let task = create_task_res(&app, &bearer, "webhook", None).await;
+    let reminder = create_reminder_res(
+        &app,
+        &bearer,
+        task.id,
+        json!({"remind_at": Utc::now() + Duration::hours(1), "email": "someone@example.com"}),
+    )
+    .await;
+    make_due(&pool, reminder.id);
+
+    let notifiers = Notifiers::new().with(Arc::new(WebhookNotifier::new(url)));
+    let scheduler = ReminderScheduler::default();
+    assert_eq!(scheduler.run(&pool, &notifiers).await.unwrap(), 1);
+
+    let requests = state.lock().unwrap().requests.clone();
+    assert_eq!(requests.len(), 1);
+    let (key, body) = &requests[0];
+    assert!(key.starts_with(&reminder.id.to_string()));
+    assert_eq!(body["task_id"], json!(task.id));
+    assert_eq!(body["title"], "webhook");
+    // Email addresses are only given to the SMTP relay
+    assert!(body.get("email").is_none());
+}
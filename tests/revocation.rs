@@ -0,0 +1,259 @@
+mod common;
+
+use actix_http::{Request, StatusCode};
+use actix_web::{
+    dev::{Service, ServiceResponse},
+    test, web, App, Error,
+};
+use chrono::{Duration as ChronoDuration, Local, NaiveDateTime};
+use common::{create_pool, Context, IdpStandIn, SigningKey};
+use diesel::{QueryDsl, RunQueryDsl};
+use openssl::rsa::Rsa;
+use serde_json::{json, Value};
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use zeronote::{
+    database::connection::Pool,
+    handlers::{auth::*, tasks::*},
+    middlewares::auth,
+    models::schema::revoked_tokens,
+    services::revocations::RevocationCache,
+    utils::{
+        local_issuer::LocalIssuer,
+        verifier::{IssuerSettings, OidcVerifier, TokenVerifier, TrustedIssuers},
+    },
+};
+
+// Integration tests for server-side logout & admin revocations
+// User tokens come from the local issuer & admin tokens from an OIDC stand-in
+
+struct Setup {
+    issuer: Arc<LocalIssuer>,
+    verifier: Arc<dyn TokenVerifier>,
+    admin_token: String,
+    _idp: IdpStandIn,
+}
+
+async fn setup() -> Setup {
+    setup_with_ttl(300).await
+}
+
+async fn setup_with_ttl(token_ttl: u64) -> Setup {
+    let rsa = Rsa::generate(2048).unwrap();
+    let issuer = Arc::new(LocalIssuer::from_rsa("zeronote", token_ttl, &rsa).unwrap());
+    let key = SigningKey::generate("admin-key");
+    let idp = IdpStandIn::start(&[&key]).await;
+    let oidc = OidcVerifier::discover(idp.issuer(), IssuerSettings::default())
+        .await
+        .unwrap();
+    let verifier: Arc<dyn TokenVerifier> = Arc::new(
+        TrustedIssuers::new()
+            .with(issuer.issuer(), issuer.clone())
+            .with(idp.issuer(), Arc::new(oidc)),
+    );
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let admin_token = key.sign(&json!({
+        "iss": idp.issuer(), "sub": "admin-1", "exp": now + 300, "iat": now,
+        "cognito:groups": ["admin"]
+    }));
+
+    Setup {
+        issuer,
+        verifier,
+        admin_token,
+        _idp: idp,
+    }
+}
+
+async fn init_app(
+    setup: &Setup,
+    pool: Pool,
+    cache: RevocationCache,
+) -> impl Service<Request, Response = ServiceResponse, Error = Error> {
+    test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool))
+            .app_data(web::Data::from(setup.verifier.clone()))
+            .app_data(web::Data::new(cache))
+            .service(web::scope("/auth").service(logout))
+            .service(
+                web::scope("/api")
+                    .service(get_all_tasks)
+                    .service(revoke_tokens)
+                    .wrap(auth::Authorization),
+            ),
+    )
+    .await
+}
+
+async fn status(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    req: test::TestRequest,
+    token: &str,
+) -> StatusCode {
+    let req = req
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
+    match app.call(req).await {
+        Ok(res) => res.status(),
+        Err(e) => e.as_response_error().status_code(),
+    }
+}
+
+fn get_all() -> test::TestRequest {
+    test::TestRequest::get().uri("/api/all")
+}
+
+fn user_token(setup: &Setup, sub: &str) -> String {
+    setup.issuer.issue(sub, sub, &["tasks:read"]).unwrap()
+}
+
+fn revoke(body: Value) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/api/admin/revocations")
+        .set_json(body)
+}
+
+#[actix_web::test]
+async fn test_logout() {
+    let ctx = Context::new("logout_test");
+    let setup = setup().await;
+    let app = init_app(&setup, create_pool(&ctx), RevocationCache::default()).await;
+
+    let laptop = user_token(&setup, "user-1");
+    let phone = user_token(&setup, "user-1");
+    assert_eq!(status(&app, get_all(), &laptop).await, StatusCode::OK);
+
+    let req = test::TestRequest::post().uri("/auth/logout");
+    assert_eq!(status(&app, req, &laptop).await, StatusCode::NO_CONTENT);
+    assert_eq!(
+        status(&app, get_all(), &laptop).await,
+        StatusCode::UNAUTHORIZED,
+        "Logged out token must be rejected"
+    );
+    assert_eq!(
+        status(&app, get_all(), &phone).await,
+        StatusCode::OK,
+        "Other tokens of the user must stay valid"
+    );
+
+    let req = test::TestRequest::post().uri("/auth/logout?all=true");
+    assert_eq!(status(&app, req, &phone).await, StatusCode::NO_CONTENT);
+    assert_eq!(
+        status(&app, get_all(), &phone).await,
+        StatusCode::UNAUTHORIZED
+    );
+
+    // `iat` has a precision of seconds, tokens issued in a later second are unaffected
+    actix_web::rt::time::sleep(Duration::from_millis(1100)).await;
+    let fresh = user_token(&setup, "user-1");
+    assert_eq!(status(&app, get_all(), &fresh).await, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn test_admin_revocations() {
+    let ctx = Context::new("admin_revocation_test");
+    let setup = setup().await;
+    let app = init_app(&setup, create_pool(&ctx), RevocationCache::default()).await;
+
+    let token = user_token(&setup, "user-1");
+    let other = user_token(&setup, "user-1");
+    let claims = setup.issuer.verify(&token).await.unwrap();
+    let body = json!({"sub": "user-1", "jti": claims["jti"]});
+
+    assert_eq!(
+        status(&app, revoke(body.clone()), &other).await,
+        StatusCode::FORBIDDEN,
+        "Only admins may revoke tokens"
+    );
+    assert_eq!(
+        status(&app, revoke(body), &setup.admin_token).await,
+        StatusCode::NO_CONTENT
+    );
+    assert_eq!(
+        status(&app, get_all(), &token).await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(status(&app, get_all(), &other).await, StatusCode::OK);
+
+    assert_eq!(
+        status(&app, revoke(json!({"sub": "user-1"})), &setup.admin_token).await,
+        StatusCode::NO_CONTENT
+    );
+    assert_eq!(
+        status(&app, get_all(), &other).await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        status(&app, revoke(json!({"sub": ""})), &setup.admin_token).await,
+        StatusCode::BAD_REQUEST
+    );
+}
+
+#[actix_web::test]
+async fn test_revocations_reach_other_instances_after_cache_ttl() {
+    let ctx = Context::new("revocation_cache_test");
+    let pool = create_pool(&ctx);
+    let setup = setup().await;
+    let ttl = Duration::from_millis(500);
+    let first = init_app(&setup, pool.clone(), RevocationCache::with_ttl(ttl)).await;
+    let second = init_app(&setup, pool, RevocationCache::with_ttl(ttl)).await;
+
+    let token = user_token(&setup, "user-1");
+    assert_eq!(status(&second, get_all(), &token).await, StatusCode::OK);
+
+    let req = test::TestRequest::post().uri("/auth/logout");
+    assert_eq!(status(&first, req, &token).await, StatusCode::NO_CONTENT);
+    assert_eq!(
+        status(&second, get_all(), &token).await,
+        StatusCode::OK,
+        "Cached lookups are reused within the TTL"
+    );
+
+    actix_web::rt::time::sleep(ttl).await;
+    assert_eq!(
+        status(&second, get_all(), &token).await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[actix_web::test]
+async fn test_long_lived_token_revocations() {
+    let ctx = Context::new("long_lived_revocation_test");
+    let pool = create_pool(&ctx);
+    let setup = setup_with_ttl(48 * 3600).await;
+    let token = user_token(&setup, "user-1");
+
+    // The default window of a day can't cover the token, so it isn't accepted at all
+    let app = init_app(&setup, pool.clone(), RevocationCache::default()).await;
+    assert_eq!(
+        status(&app, get_all(), &token).await,
+        StatusCode::UNAUTHORIZED
+    );
+
+    let cache = RevocationCache::default().with_token_lifetime(ChronoDuration::hours(48));
+    let app = init_app(&setup, pool.clone(), cache).await;
+    assert_eq!(status(&app, get_all(), &token).await, StatusCode::OK);
+    let claims = setup.issuer.verify(&token).await.unwrap();
+    let body = json!({"sub": "user-1", "jti": claims["jti"]});
+    assert_eq!(
+        status(&app, revoke(body), &setup.admin_token).await,
+        StatusCode::NO_CONTENT
+    );
+    assert_eq!(
+        status(&app, get_all(), &token).await,
+        StatusCode::UNAUTHORIZED
+    );
+
+    // The revocation is kept until the token has expired
+    let expires_at: NaiveDateTime = revoked_tokens::table
+        .select(revoked_tokens::expires_at)
+        .first(&mut pool.get().unwrap())
+        .unwrap();
+    assert!(expires_at > Local::now().naive_local() + ChronoDuration::hours(47));
+}
@@ -0,0 +1,217 @@
+mod common;
+
+use actix_http::{header, Request, StatusCode};
+use actix_web::{
+    dev::{Service, ServiceResponse},
+    test, web, App, Error,
+};
+use common::{
+    create_pool, delete_endpoint_res, get_endpoint_res, patch_endpoint_res, post_endpoint_res,
+    Context, DevAuth,
+};
+use serde_json::{json, Value};
+use uuid::Uuid;
+use zeronote::{
+    handlers::tasks::*,
+    middlewares::auth,
+    models::task::{Task, TaskCondition, TaskTree, TaskWithTags},
+};
+
+// Integration tests for subtasks, moving tasks within the hierarchy, tree retrieval &
+// auto-completing parents
+
+async fn init_app(
+    ctx: &Context,
+    dev: &DevAuth,
+) -> impl Service<Request, Response = ServiceResponse, Error = Error> {
+    test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_pool(ctx)))
+            .app_data(dev.verifier())
+            .service(
+                web::scope("/api/v1")
+                    .service(create_task)
+                    .service(list_trash)
+                    .service(restore_task)
+                    .service(get_task)
+                    .service(list_children)
+                    .service(patch_task)
+                    .service(remove_task)
+                    .wrap(auth::Authorization),
+            ),
+    )
+    .await
+}
+
+async fn create_subtask(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    bearer: &str,
+    title: &str,
+    parent: Option<Uuid>,
+    auto_complete: bool,
+) -> Task {
+    let task = json!({
+        "title": title,
+        "body": "Task body",
+        "parent_id": parent,
+        "auto_complete": auto_complete,
+    });
+    let res = post_endpoint_res(app, task, bearer, "/api/v1/tasks").await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let task: TaskWithTags = test::read_body_json(res).await;
+    task.task
+}
+
+async fn patch(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    bearer: &str,
+    task_id: Uuid,
+    patch: Value,
+) -> StatusCode {
+    let uri = format!("/api/v1/tasks/{}", task_id);
+    patch_endpoint_res(app, patch, bearer, &uri).await.status()
+}
+
+async fn get(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    bearer: &str,
+    task_id: Uuid,
+) -> Task {
+    let res = get_endpoint_res(app, bearer, &format!("/api/v1/tasks/{}", task_id)).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let task: TaskWithTags = test::read_body_json(res).await;
+    task.task
+}
+
+fn tree_titles(tree: &TaskTree) -> Value {
+    let children: Vec<Value> = tree.children.iter().map(tree_titles).collect();
+    json!({ tree.task.task.title.as_str(): children })
+}
+
+#[actix_web::test]
+async fn test_subtask_hierarchy() {
+    let ctx = Context::new("subtasks_test");
+    let dev = DevAuth::new();
+    let bearer = dev.bearer("subtask-tests-user", &[]);
+    let other = dev.bearer("other-user", &[]);
+    let app = init_app(&ctx, &dev).await;
+
+    let root = create_subtask(&app, &bearer, "root", None, false).await;
+    let first = create_subtask(&app, &bearer, "first", Some(root.id), false).await;
+    let second = create_subtask(&app, &bearer, "second", Some(root.id), false).await;
+    let nested = create_subtask(&app, &bearer, "nested", Some(first.id), false).await;
+    assert_eq!(nested.parent_id, Some(first.id));
+
+    // Other users' tasks can't be parents
+    let foreign = create_subtask(&app, &other, "foreign", None, false).await;
+    let res = post_endpoint_res(
+        &app,
+        json!({"title": "orphan", "body": "Task body", "parent_id": foreign.id}),
+        &bearer,
+        "/api/v1/tasks",
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let uri = format!("/api/v1/tasks/{}/children", root.id);
+    let res = get_endpoint_res(&app, &bearer, &uri).await;
+    let children: Vec<TaskWithTags> = test::read_body_json(res).await;
+    let titles: Vec<&str> = children.iter().map(|c| c.task.title.as_str()).collect();
+    assert_eq!(titles, ["first", "second"]);
+    let res = get_endpoint_res(&app, &other, &uri).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    let uri = format!("/api/v1/tasks/{}?include=children", root.id);
+    let res = get_endpoint_res(&app, &bearer, &uri).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(!res.headers().contains_key(header::ETAG));
+    let tree: TaskTree = test::read_body_json(res).await;
+    assert_eq!(
+        tree_titles(&tree),
+        json!({"root": [{"first": [{"nested": []}]}, {"second": []}]})
+    );
+    let uri = format!("/api/v1/tasks/{}?include=parents", root.id);
+    let res = get_endpoint_res(&app, &bearer, &uri).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    // Moves under the task itself or any of its subtasks are rejected
+    for parent in [root.id, first.id, nested.id] {
+        let status = patch(&app, &bearer, root.id, json!({ "parent_id": parent })).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+    let status = patch(&app, &bearer, nested.id, json!({ "parent_id": second.id })).await;
+    assert_eq!(status, StatusCode::OK);
+    let status = patch(&app, &bearer, first.id, json!({ "parent_id": null })).await;
+    assert_eq!(status, StatusCode::OK);
+    let uri = format!("/api/v1/tasks/{}?include=children", root.id);
+    let res = get_endpoint_res(&app, &bearer, &uri).await;
+    let tree: TaskTree = test::read_body_json(res).await;
+    assert_eq!(
+        tree_titles(&tree),
+        json!({"root": [{"second": [{"nested": []}]}]})
+    );
+
+    // Subtasks are trashed & restored along with their parent
+    let uri = format!("/api/v1/tasks/{}", root.id);
+    let res = delete_endpoint_res(&app, json!({}), &bearer, &uri).await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    let res = get_endpoint_res(&app, &bearer, "/api/v1/tasks/trash").await;
+    let trashed: Vec<Task> = test::read_body_json(res).await;
+    assert_eq!(trashed.len(), 3);
+    let uri = format!("/api/v1/tasks/trash/{}/restore", nested.id);
+    let res = post_endpoint_res(&app, json!({}), &bearer, &uri).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let restored: TaskWithTags = test::read_body_json(res).await;
+    assert_eq!(restored.task.parent_id, None);
+    let uri = format!("/api/v1/tasks/trash/{}/restore", root.id);
+    post_endpoint_res(&app, json!({}), &bearer, &uri).await;
+    let uri = format!("/api/v1/tasks/{}?include=children", root.id);
+    let res = get_endpoint_res(&app, &bearer, &uri).await;
+    let tree: TaskTree = test::read_body_json(res).await;
+    assert_eq!(tree_titles(&tree), json!({"root": [{"second": []}]}));
+}
+
+#[actix_web::test]
+async fn test_auto_complete() {
+    let ctx = Context::new("subtasks_auto_complete_test");
+    let dev = DevAuth::new();
+    let bearer = dev.bearer("subtask-tests-user", &[]);
+    let app = init_app(&ctx, &dev).await;
+
+    let project = create_subtask(&app, &bearer, "project", None, true).await;
+    let phase = create_subtask(&app, &bearer, "phase", Some(project.id), true).await;
+    let manual = create_subtask(&app, &bearer, "manual", Some(project.id), false).await;
+    let first = create_subtask(&app, &bearer, "first", Some(phase.id), false).await;
+    let second = create_subtask(&app, &bearer, "second", Some(phase.id), false).await;
+    let third = create_subtask(&app, &bearer, "third", Some(manual.id), false).await;
+
+    let done = json!({"condition": "done"});
+    patch(&app, &bearer, first.id, done.clone()).await;
+    assert_eq!(
+        get(&app, &bearer, phase.id).await.condition,
+        TaskCondition::Undone
+    );
+
+    // The last undone subtask leaving the parent completes it as well
+    let uri = format!("/api/v1/tasks/{}", second.id);
+    delete_endpoint_res(&app, json!({}), &bearer, &uri).await;
+    let completed = get(&app, &bearer, phase.id).await;
+    assert_eq!(completed.condition, TaskCondition::Done);
+    assert_eq!(completed.version, phase.version + 1);
+    assert_eq!(
+        get(&app, &bearer, project.id).await.condition,
+        TaskCondition::Undone
+    );
+
+    // Parents without auto-completion are left to their owners, which stops the propagation
+    patch(&app, &bearer, third.id, done.clone()).await;
+    assert_eq!(
+        get(&app, &bearer, manual.id).await.condition,
+        TaskCondition::Undone
+    );
+    patch(&app, &bearer, manual.id, done).await;
+    assert_eq!(
+        get(&app, &bearer, project.id).await.condition,
+        TaskCondition::Done
+    );
+}
@@ -0,0 +1,150 @@
+mod common;
+
+use actix_http::{Request, StatusCode};
+use actix_web::{
+    dev::{Service, ServiceResponse},
+    test, web, App, Error,
+};
+use common::{create, create_pool, post_endpoint_res, titles, Context, DevAuth};
+use serde_json::{json, Value};
+use zeronote::{
+    errors::app_error::{AppError, ProblemDetails},
+    handlers::tasks::*,
+    middlewares::auth,
+    models::task::{BatchResponse, TaskCondition},
+};
+
+// Integration tests for batches of task operations in both all-or-nothing & best-effort modes
+
+async fn init_app(
+    ctx: &Context,
+    dev: &DevAuth,
+) -> impl Service<Request, Response = ServiceResponse, Error = Error> {
+    test::init_service(
+        App::new()
+            .app_data(
+                web::JsonConfig::default()
+                    .error_handler(|err, _| AppError::JsonPayLoad(err).into()),
+            )
+            .app_data(web::Data::new(create_pool(ctx)))
+            .app_data(dev.verifier())
+            .service(
+                web::scope("/api/v1")
+                    .service(list_tasks)
+                    .service(create_task)
+                    .service(batch_tasks)
+                    .wrap(auth::Authorization),
+            ),
+    )
+    .await
+}
+
+#[actix_web::test]
+async fn test_all_or_nothing_batch() {
+    let ctx = Context::new("task_batch_test");
+    let dev = DevAuth::new();
+    let bearer = dev.bearer("task-tests-user", &[]);
+    let app = init_app(&ctx, &dev).await;
+    let task = |title: &str| json!({"title": title, "body": "Task body"});
+    let first = create(&app, &bearer, task("first")).await;
+    let second = create(&app, &bearer, task("second")).await;
+
+    let batch = json!({"operations": [
+        {"op": "create", "title": "third", "body": "Task body"},
+        {"op": "update", "id": first.id, "changes": {"title": "first renamed"}},
+        {"op": "set_condition", "id": first.id, "condition": "done"},
+        {"op": "delete", "id": second.id},
+    ]});
+    let res = post_endpoint_res(&app, batch, &bearer, "/api/v1/tasks/batch").await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: BatchResponse = test::read_body_json(res).await;
+    let statuses: Vec<u16> = body.results.iter().map(|r| r.status).collect();
+    assert_eq!(statuses, [201, 200, 200, 204]);
+    let renamed = body.results[2].task.as_ref().unwrap();
+    assert_eq!(renamed.task.title, "first renamed");
+    assert_eq!(renamed.task.condition, TaskCondition::Done);
+    assert!(body.results[3].task.is_none());
+    assert_eq!(
+        titles(&app, &bearer, "/api/v1/tasks?sort=title").await,
+        ["first renamed", "third"]
+    );
+
+    // The missing task fails the last operation, so nothing is applied
+    let batch = json!({"operations": [
+        {"op": "create", "title": "fourth", "body": "Task body"},
+        {"op": "delete", "id": first.id},
+        {"op": "set_condition", "id": second.id, "condition": "active"},
+    ]});
+    let res = post_endpoint_res(&app, batch, &bearer, "/api/v1/tasks/batch").await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    let problem: ProblemDetails = test::read_body_json(res).await;
+    assert_eq!(problem.detail, "Operation 2: Task not found");
+    assert_eq!(
+        titles(&app, &bearer, "/api/v1/tasks?sort=title").await,
+        ["first renamed", "third"]
+    );
+
+    let batch = json!({"operations": [
+        {"op": "create", "title": "fourth", "body": "Task body"},
+        {"op": "create", "title": "", "body": "Task body"},
+    ]});
+    let res = post_endpoint_res(&app, batch, &bearer, "/api/v1/tasks/batch").await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let problem: ProblemDetails = test::read_body_json(res).await;
+    assert!(problem.errors.contains_key("operations[1].title"));
+    assert_eq!(
+        titles(&app, &bearer, "/api/v1/tasks?sort=title").await,
+        ["first renamed", "third"]
+    );
+}
+
+#[actix_web::test]
+async fn test_best_effort_batch() {
+    let ctx = Context::new("task_batch_best_effort_test");
+    let dev = DevAuth::new();
+    let bearer = dev.bearer("task-tests-user", &[]);
+    let other = dev.bearer("other-user", &[]);
+    let app = init_app(&ctx, &dev).await;
+    let task = |title: &str| json!({"title": title, "body": "Task body"});
+    let others = create(&app, &other, task("others")).await;
+    let task = create(&app, &bearer, task("first")).await;
+
+    let batch = json!({"mode": "best_effort", "operations": [
+        {"op": "create", "title": "second", "body": "Task body"},
+        {"op": "set_condition", "id": task.id, "condition": "down"},
+        {"op": "delete", "id": others.id},
+        {"op": "set_condition", "id": task.id, "condition": "active"},
+    ]});
+    let res = post_endpoint_res(&app, batch, &bearer, "/api/v1/tasks/batch").await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(res).await;
+    let statuses: Vec<&Value> = body["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| &r["status"])
+        .collect();
+    assert_eq!(statuses, [201, 400, 404, 200]);
+    assert_eq!(
+        body["results"][1]["error"]["errors"]["condition"][0],
+        "Invalid task condition"
+    );
+    assert_eq!(body["results"][3]["task"]["condition"], "Active");
+    assert_eq!(
+        titles(&app, &bearer, "/api/v1/tasks?sort=title").await,
+        ["first", "second"]
+    );
+    assert_eq!(
+        titles(&app, &other, "/api/v1/tasks?sort=title").await,
+        ["others"]
+    );
+
+    for invalid in [
+        json!({"operations": []}),
+        json!({"operations": [{"op": "rename", "id": task.id}]}),
+        json!({"mode": "sometimes", "operations": [{"op": "delete", "id": task.id}]}),
+    ] {
+        let res = post_endpoint_res(&app, invalid.clone(), &bearer, "/api/v1/tasks/batch").await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{}", invalid);
+    }
+}
@@ -0,0 +1,208 @@
+mod common;
+
+use actix_http::{Request, StatusCode};
+use actix_web::{
+    dev::{Service, ServiceResponse},
+    test, web, App, Error,
+};
+use common::{
+    create, create_pool, delete_endpoint_res, get_endpoint_res, patch_endpoint_res,
+    post_endpoint_res, titles, Context, DevAuth,
+};
+use serde_json::json;
+use uuid::Uuid;
+use zeronote::{
+    errors::app_error::ProblemDetails,
+    handlers::{dependencies::*, tasks::*},
+    middlewares::auth,
+    models::{
+        dependency::TaskDependency,
+        task::{TaskCondition, TaskWithTags},
+    },
+};
+
+// Integration tests for task dependencies, blocked condition changes & the blocked listing filter
+
+async fn init_app(
+    ctx: &Context,
+    dev: &DevAuth,
+) -> impl Service<Request, Response = ServiceResponse, Error = Error> {
+    test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_pool(ctx)))
+            .app_data(dev.verifier())
+            .service(
+                web::scope("/api/v1")
+                    .service(list_tasks)
+                    .service(create_task)
+                    .service(get_task)
+                    .service(patch_task)
+                    .service(remove_task)
+                    .service(list_dependencies)
+                    .service(add_dependency)
+                    .service(remove_dependency)
+                    .wrap(auth::Authorization),
+            ),
+    )
+    .await
+}
+
+async fn depend(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    bearer: &str,
+    task_id: Uuid,
+    depends_on_id: Uuid,
+) -> StatusCode {
+    let uri = format!("/api/v1/tasks/{}/dependencies", task_id);
+    let dependency = json!({ "depends_on_id": depends_on_id });
+    post_endpoint_res(app, dependency, bearer, &uri)
+        .await
+        .status()
+}
+
+async fn set_condition(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    bearer: &str,
+    task_id: Uuid,
+    condition: &str,
+) -> ServiceResponse {
+    let uri = format!("/api/v1/tasks/{}", task_id);
+    patch_endpoint_res(app, json!({ "condition": condition }), bearer, &uri).await
+}
+
+#[actix_web::test]
+async fn test_dependency_endpoints() {
+    let ctx = Context::new("task_dependencies_test");
+    let dev = DevAuth::new();
+    let bearer = dev.bearer("dependency-tests-user", &[]);
+    let other = dev.bearer("other-user", &[]);
+    let app = init_app(&ctx, &dev).await;
+    let task = |title: &str| json!({"title": title, "body": "Task body"});
+
+    let design = create(&app, &bearer, task("design")).await;
+    let build = create(&app, &bearer, task("build")).await;
+    let ship = create(&app, &bearer, task("ship")).await;
+    let foreign = create(&app, &other, task("foreign")).await;
+
+    let uri = format!("/api/v1/tasks/{}/dependencies", build.id);
+    let res = post_endpoint_res(&app, json!({"depends_on_id": design.id}), &bearer, &uri).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let dependency: TaskDependency = test::read_body_json(res).await;
+    assert_eq!(
+        (dependency.task_id, dependency.depends_on_id),
+        (build.id, design.id)
+    );
+    assert_eq!(
+        depend(&app, &bearer, build.id, design.id).await,
+        StatusCode::CONFLICT
+    );
+    assert_eq!(
+        depend(&app, &bearer, ship.id, build.id).await,
+        StatusCode::CREATED
+    );
+
+    // Direct & indirect cycles are rejected along with other users' tasks
+    for (task_id, depends_on_id) in [
+        (design.id, design.id),
+        (design.id, build.id),
+        (design.id, ship.id),
+        (design.id, foreign.id),
+    ] {
+        let status = depend(&app, &bearer, task_id, depends_on_id).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+    assert_eq!(
+        depend(&app, &other, build.id, foreign.id).await,
+        StatusCode::NOT_FOUND
+    );
+
+    let res = get_endpoint_res(&app, &bearer, &uri).await;
+    let listed: Vec<TaskWithTags> = test::read_body_json(res).await;
+    let ids: Vec<Uuid> = listed.iter().map(|task| task.task.id).collect();
+    assert_eq!(ids, [design.id]);
+
+    let uri = format!("/api/v1/tasks/{}/dependencies/{}", ship.id, build.id);
+    let res = delete_endpoint_res(&app, json!({}), &bearer, &uri).await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    let res = delete_endpoint_res(&app, json!({}), &bearer, &uri).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn test_blocked_tasks() {
+    let ctx = Context::new("task_dependencies_blocked_test");
+    let dev = DevAuth::new();
+    let bearer = dev.bearer("dependency-tests-user", &[]);
+    let app = init_app(&ctx, &dev).await;
+    let task = |title: &str| json!({"title": title, "body": "Task body"});
+
+    let design = create(&app, &bearer, task("design")).await;
+    let build = create(&app, &bearer, task("build")).await;
+    let ship = create(&app, &bearer, task("ship")).await;
+    depend(&app, &bearer, build.id, design.id).await;
+    depend(&app, &bearer, ship.id, build.id).await;
+
+    assert_eq!(
+        titles(&app, &bearer, "/api/v1/tasks?sort=title&blocked=true").await,
+        ["build", "ship"]
+    );
+    assert_eq!(
+        titles(&app, &bearer, "/api/v1/tasks?sort=title&blocked=false").await,
+        ["design"]
+    );
+
+    for condition in ["active", "done"] {
+        let res = set_condition(&app, &bearer, build.id, condition).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+        let problem: ProblemDetails = test::read_body_json(res).await;
+        assert_eq!(problem.problem_type, "urn:zeronote:problem:task-blocked");
+        assert_eq!(problem.detail, "Task is blocked by 1 unfinished dependency");
+    }
+
+    let res = set_condition(&app, &bearer, design.id, "done").await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let res = set_condition(&app, &bearer, build.id, "active").await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        titles(&app, &bearer, "/api/v1/tasks?sort=title&blocked=true").await,
+        ["ship"]
+    );
+    assert_eq!(
+        titles(
+            &app,
+            &bearer,
+            "/api/v1/tasks?sort=title&blocked=false&condition=undone,active"
+        )
+        .await,
+        ["build"]
+    );
+
+    // Reopening a dependency blocks the task again, trashed dependencies don't block
+    let res = set_condition(&app, &bearer, design.id, "undone").await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let res = set_condition(&app, &bearer, build.id, "done").await;
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+    let uri = format!("/api/v1/tasks/{}", build.id);
+    delete_endpoint_res(&app, json!({}), &bearer, &uri).await;
+    let res = set_condition(&app, &bearer, ship.id, "done").await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // Blocked parents aren't auto-completed
+    let parent = create(
+        &app,
+        &bearer,
+        json!({"title": "parent", "body": "Task body", "auto_complete": true}),
+    )
+    .await;
+    let child = create(
+        &app,
+        &bearer,
+        json!({"title": "child", "body": "Task body", "parent_id": parent.id}),
+    )
+    .await;
+    depend(&app, &bearer, parent.id, design.id).await;
+    set_condition(&app, &bearer, child.id, "done").await;
+    let res = get_endpoint_res(&app, &bearer, &format!("/api/v1/tasks/{}", parent.id)).await;
+    let parent: TaskWithTags = test::read_body_json(res).await;
+    assert_eq!(parent.task.condition, TaskCondition::Undone);
+}
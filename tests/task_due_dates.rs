@@ -0,0 +1,189 @@
+mod common;
+
+use actix_http::{Request, StatusCode};
+use actix_web::{
+    dev::{Service, ServiceResponse},
+    test, web, App, Error,
+};
+use chrono::{DateTime, Datelike, Duration, FixedOffset, Utc};
+use common::{
+    create, create_pool, get_endpoint_res, patch_endpoint_res, post_endpoint_res, put_endpoint_res,
+    titles, Context, DevAuth,
+};
+use serde_json::json;
+use zeronote::{
+    handlers::tasks::*,
+    middlewares::auth,
+    models::task::{Task, TaskPriority},
+};
+
+// Integration tests for due dates, priorities & the overdue, today & this week listing views
+
+async fn init_app(
+    ctx: &Context,
+    dev: &DevAuth,
+) -> impl Service<Request, Response = ServiceResponse, Error = Error> {
+    test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_pool(ctx)))
+            .app_data(dev.verifier())
+            .service(
+                web::scope("/api/v1")
+                    .service(list_tasks)
+                    .service(create_task)
+                    .service(replace_task)
+                    .service(patch_task)
+                    .wrap(auth::Authorization),
+            ),
+    )
+    .await
+}
+
+async fn create_due(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    bearer: &str,
+    title: &str,
+    due_at: Option<DateTime<Utc>>,
+) -> Task {
+    let task = json!({"title": title, "body": "Task body", "due_at": due_at});
+    create(app, bearer, task).await
+}
+
+#[actix_web::test]
+async fn test_due_dates_and_priorities() {
+    let ctx = Context::new("task_due_dates_test");
+    let dev = DevAuth::new();
+    let bearer = dev.bearer("due-tests-user", &[]);
+    let app = init_app(&ctx, &dev).await;
+
+    let due_at = Utc::now() + Duration::days(3);
+    let res = post_endpoint_res(
+        &app,
+        json!({"title": "first", "body": "Task body", "due_at": due_at, "priority": "HIGH"}),
+        &bearer,
+        "/api/v1/tasks",
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let task: Task = test::read_body_json(res).await;
+    assert_eq!(
+        task.due_at.map(|due| due.timestamp()),
+        Some(due_at.timestamp())
+    );
+    assert_eq!(task.priority, Some(TaskPriority::High));
+
+    // Past due dates are accepted on creation just like on updates
+    let overdue =
+        json!({"title": "overdue", "body": "Task body", "due_at": Utc::now() - Duration::hours(1)});
+    let res = post_endpoint_res(&app, overdue, &bearer, "/api/v1/tasks").await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    for invalid in [
+        json!({"title": "second", "body": "Task body", "priority": "urgent"}),
+        json!({"title": "second", "body": "Task body", "due_at": "tomorrow"}),
+    ] {
+        let res = post_endpoint_res(&app, invalid.clone(), &bearer, "/api/v1/tasks").await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{}", invalid);
+    }
+
+    // Merge patches clear them with null, PUT when they're omitted
+    let uri = format!("/api/v1/tasks/{}", task.id);
+    let res = patch_endpoint_res(&app, json!({"priority": null}), &bearer, &uri).await;
+    let patched: Task = test::read_body_json(res).await;
+    assert_eq!(patched.priority, None);
+    assert!(patched.due_at.is_some());
+    let res = put_endpoint_res(
+        &app,
+        json!({"title": "first", "body": "Task body", "condition": "active", "priority": "low"}),
+        &bearer,
+        &uri,
+    )
+    .await;
+    let replaced: Task = test::read_body_json(res).await;
+    assert_eq!(replaced.due_at, None);
+    assert_eq!(replaced.priority, Some(TaskPriority::Low));
+}
+
+#[actix_web::test]
+async fn test_due_views() {
+    let ctx = Context::new("task_due_views_test");
+    let dev = DevAuth::new();
+    let bearer = dev.bearer("due-tests-user", &[]);
+    let app = init_app(&ctx, &dev).await;
+
+    let now = Utc::now();
+    let midnight = now
+        .date_naive()
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_local_timezone(Utc)
+        .unwrap();
+    let earlier_today = midnight + Duration::seconds(1);
+    let tomorrow = midnight + Duration::days(1) + Duration::seconds(1);
+    create_due(&app, &bearer, "a today", Some(earlier_today)).await;
+    create_due(&app, &bearer, "b tomorrow", Some(tomorrow)).await;
+    create_due(
+        &app,
+        &bearer,
+        "c next month",
+        Some(now + Duration::days(31)),
+    )
+    .await;
+    create_due(&app, &bearer, "d undated", None).await;
+    let done = create_due(&app, &bearer, "e done", Some(now - Duration::days(2))).await;
+    let uri = format!("/api/v1/tasks/{}", done.id);
+    patch_endpoint_res(&app, json!({"condition": "done"}), &bearer, &uri).await;
+
+    assert_eq!(
+        titles(&app, &bearer, "/api/v1/tasks?sort=title&due=overdue").await,
+        ["a today"]
+    );
+    assert_eq!(
+        titles(&app, &bearer, "/api/v1/tasks?sort=title&due=today").await,
+        ["a today"]
+    );
+    let mut this_week = vec!["a today"];
+    if tomorrow.iso_week() == now.iso_week() {
+        this_week.push("b tomorrow");
+    }
+    if (now - Duration::days(2)).iso_week() == now.iso_week() {
+        this_week.push("e done");
+    }
+    assert_eq!(
+        titles(
+            &app,
+            &bearer,
+            "/api/v1/tasks?sort=title&due=this_week&tz=UTC"
+        )
+        .await,
+        this_week
+    );
+
+    // Kiritimati is 14 hours ahead of UTC all year round
+    let kiritimati = FixedOffset::east_opt(14 * 3600).unwrap();
+    let local_today = now.with_timezone(&kiritimati).date_naive();
+    let expected: Vec<&str> = [("a today", earlier_today), ("b tomorrow", tomorrow)]
+        .into_iter()
+        .filter(|(_, due)| due.with_timezone(&kiritimati).date_naive() == local_today)
+        .map(|(title, _)| title)
+        .collect();
+    assert_eq!(
+        titles(
+            &app,
+            &bearer,
+            "/api/v1/tasks?sort=title&due=today&tz=Pacific/Kiritimati"
+        )
+        .await,
+        expected
+    );
+
+    for invalid in [
+        "due=tomorrow",
+        "due=today&tz=Mars/Olympus_Mons",
+        "due=today&tz=",
+    ] {
+        let uri = format!("/api/v1/tasks?{}", invalid);
+        let res = get_endpoint_res(&app, &bearer, &uri).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{}", invalid);
+    }
+}
@@ -2,31 +2,32 @@ mod common;
 
 use actix_http::StatusCode;
 use actix_web::{
+    http::header,
     test,
     web::{self, Bytes},
     App,
 };
 use common::{
-    create_pool, delete_endpoint_res, fetch_jwt, get_endpoint_res, post_endpoint_res,
-    put_endpoint_res, Context,
+    create_pool, delete_endpoint_res, get_endpoint_res, post_endpoint_res, put_endpoint_res,
+    Context, DevAuth,
 };
 use serde_json::json;
 use zeronote::{
-    errors::app_error::{AppError, AppErrorResponse},
+    errors::app_error::{AppError, ProblemDetails},
     handlers::tasks::*,
-    middlewares::auth::{self, CognitoConfig},
+    middlewares::auth,
     models::task::{Task, TaskCondition},
 };
 
-// Integration tests for authentication/authorization with JWTs & querying the DB according to CRUD endpoints
-// Each test runs synchronously, creates individual DBs & uses unique JWT
+// Integration tests for authentication/authorization with offline dev tokens & querying the DB according to CRUD endpoints
+// Each test runs synchronously, creates individual DBs & uses its own in-memory signing key
 
 #[actix_web::test]
 async fn test_create_task_req() {
     let ctx = Context::new("create_task_test");
     let pool = create_pool(&ctx);
-    let bearer = fetch_jwt();
-    let config = CognitoConfig::default();
+    let dev = DevAuth::new();
+    let bearer = dev.bearer("task-tests-user", &[]);
     let app = test::init_service(
         App::new()
             .app_data(
@@ -34,7 +35,7 @@ async fn test_create_task_req() {
                     .error_handler(|err, _| AppError::JsonPayLoad(err).into()),
             )
             .app_data(web::Data::new(pool.clone()))
-            .app_data(web::Data::new(config.clone()))
+            .app_data(dev.verifier())
             .service(
                 web::scope("/api")
                     .service(create_new_task)
@@ -72,8 +73,8 @@ async fn test_create_task_req() {
 async fn test_update_task_req() {
     let ctx = Context::new("update_task_test");
     let pool = create_pool(&ctx);
-    let bearer = fetch_jwt();
-    let config = CognitoConfig::default();
+    let dev = DevAuth::new();
+    let bearer = dev.bearer("task-tests-user", &[]);
     let app = test::init_service(
         App::new()
             .app_data(
@@ -81,7 +82,7 @@ async fn test_update_task_req() {
                     .error_handler(|err, _| AppError::JsonPayLoad(err).into()),
             )
             .app_data(web::Data::new(pool.clone()))
-            .app_data(web::Data::new(config.clone()))
+            .app_data(dev.verifier())
             .service(
                 web::scope("/api")
                     .service(update_task)
@@ -126,14 +127,26 @@ async fn test_update_task_req() {
         create_res_body.created_at, update_res_body.updated_at,
         "Field 'updated_at' not updated properly"
     );
+
+    let other = dev.bearer("other-user", &[]);
+    let update =
+        json!({"id": create_res_body.id, "title": "Stolen", "body": "Stolen", "condition": "done"});
+    let update_res = put_endpoint_res(&app, update, &other, "/api/update").await;
+    assert_eq!(
+        update_res.status(),
+        StatusCode::NOT_FOUND,
+        "Other users' tasks must be reported as missing"
+    );
+    let res_body: ProblemDetails = test::read_body_json(update_res).await;
+    assert_eq!(res_body.status, 404);
 }
 
 #[actix_web::test]
 async fn test_read_tasks_req() {
     let ctx = Context::new("read_tasks_test");
     let pool = create_pool(&ctx);
-    let bearer = fetch_jwt();
-    let config = CognitoConfig::default();
+    let dev = DevAuth::new();
+    let bearer = dev.bearer("task-tests-user", &[]);
     let app = test::init_service(
         App::new()
             .app_data(
@@ -141,7 +154,7 @@ async fn test_read_tasks_req() {
                     .error_handler(|err, _| AppError::JsonPayLoad(err).into()),
             )
             .app_data(web::Data::new(pool.clone()))
-            .app_data(web::Data::new(config.clone()))
+            .app_data(dev.verifier())
             .service(
                 web::scope("/api")
                     .service(get_all_tasks)
@@ -199,12 +212,207 @@ async fn test_read_tasks_req() {
     );
 }
 
+#[actix_web::test]
+async fn test_paginate_tasks_req() {
+    let ctx = Context::new("paginate_tasks_test");
+    let pool = create_pool(&ctx);
+    let dev = DevAuth::new();
+    let bearer = dev.bearer("task-tests-user", &[]);
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(dev.verifier())
+            .service(
+                web::scope("/api")
+                    .service(get_all_tasks)
+                    .service(create_new_task)
+                    .wrap(auth::Authorization),
+            ),
+    )
+    .await;
+
+    for i in 1..=5 {
+        let res = post_endpoint_res(
+            &app,
+            json!({"title": format!("Task title {}", i), "body": "Task body"}),
+            &bearer,
+            "/api/new",
+        )
+        .await;
+        assert!(res.status().is_success());
+    }
+
+    let mut titles = Vec::new();
+    let mut uri = "/api/all?limit=2".to_string();
+    loop {
+        let res = get_endpoint_res(&app, &bearer, &uri).await;
+        assert!(res.status().is_success());
+        let next = res
+            .headers()
+            .get("X-Next-Cursor")
+            .map(|cursor| cursor.to_str().unwrap().to_owned());
+        let link = res
+            .headers()
+            .get(header::LINK)
+            .map(|link| link.to_str().unwrap().to_owned());
+        let page: Vec<Task> = test::read_body_json(res).await;
+        assert!(page.len() <= 2, "Page size must not exceed the limit");
+        titles.extend(page.into_iter().map(|task| task.title));
+
+        match next {
+            Some(cursor) => {
+                uri = format!("/api/all?limit=2&cursor={}", cursor);
+                assert_eq!(link, Some(format!("<{}>; rel=\"next\"", uri)));
+            }
+            None => break,
+        }
+    }
+    assert_eq!(
+        titles,
+        (1..=5)
+            .map(|i| format!("Task title {}", i))
+            .collect::<Vec<_>>(),
+        "Pages must list every task once in creation order"
+    );
+
+    let res = get_endpoint_res(&app, &bearer, "/api/all?limit=1000").await;
+    let page: Vec<Task> = test::read_body_json(res).await;
+    assert_eq!(
+        page.len(),
+        5,
+        "Oversized limits are accepted & lowered to the maximum"
+    );
+    for uri in ["/api/all?limit=0", "/api/all?cursor=invalid"] {
+        let res = get_endpoint_res(&app, &bearer, uri).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{}", uri);
+    }
+}
+
+#[actix_web::test]
+async fn test_filter_and_sort_tasks_req() {
+    let ctx = Context::new("filter_tasks_test");
+    let pool = create_pool(&ctx);
+    let dev = DevAuth::new();
+    let bearer = dev.bearer("task-tests-user", &[]);
+    let app = test::init_service(
+        App::new()
+            .app_data(
+                web::QueryConfig::default()
+                    .error_handler(|err, _| AppError::QueryPayload(err).into()),
+            )
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(dev.verifier())
+            .service(
+                web::scope("/api")
+                    .service(get_all_tasks)
+                    .service(create_new_task)
+                    .service(update_task)
+                    .wrap(auth::Authorization),
+            ),
+    )
+    .await;
+
+    for (title, condition) in [
+        ("groceries", "undone"),
+        ("gym", "active"),
+        ("taxes", "done"),
+        ("g_100%", "done"),
+    ] {
+        let res = post_endpoint_res(
+            &app,
+            json!({"title": title, "body": "Task body"}),
+            &bearer,
+            "/api/new",
+        )
+        .await;
+        let task: Task = test::read_body_json(res).await;
+        let update =
+            json!({"id": task.id, "title": title, "body": "Task body", "condition": condition});
+        let res = put_endpoint_res(&app, update, &bearer, "/api/update").await;
+        assert!(res.status().is_success());
+    }
+
+    let titles = |uri: String| {
+        let app = &app;
+        let bearer = &bearer;
+        async move {
+            let res = get_endpoint_res(app, bearer, &uri).await;
+            assert!(res.status().is_success(), "{}", uri);
+            let page: Vec<Task> = test::read_body_json(res).await;
+            page.into_iter().map(|task| task.title).collect::<Vec<_>>()
+        }
+    };
+    assert_eq!(
+        titles("/api/all?condition=active,done&sort=title".into()).await,
+        ["g_100%", "gym", "taxes"]
+    );
+    assert_eq!(
+        titles("/api/all?title_prefix=G&sort=title&order=desc".into()).await,
+        ["gym", "groceries", "g_100%"],
+        "Title prefixes are matched case-insensitively"
+    );
+    assert_eq!(
+        titles("/api/all?title_prefix=g_".into()).await,
+        ["g_100%"],
+        "Wildcards in title prefixes are matched literally"
+    );
+    assert!(titles("/api/all?created_after=2100-01-01T00:00:00".into())
+        .await
+        .is_empty());
+    let by_condition =
+        titles("/api/all?updated_before=2100-01-01T00:00:00&sort=condition&order=desc".into())
+            .await;
+    assert_eq!(by_condition.len(), 4);
+    assert!(
+        by_condition[..2]
+            .iter()
+            .all(|t| t == "taxes" || t == "g_100%"),
+        "Done tasks come first in descending order"
+    );
+
+    // Pages continue in the requested order
+    let res = get_endpoint_res(&app, &bearer, "/api/all?sort=title&order=desc&limit=2").await;
+    let cursor = res
+        .headers()
+        .get("X-Next-Cursor")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_owned();
+    let first: Vec<Task> = test::read_body_json(res).await;
+    let second = titles(format!(
+        "/api/all?sort=title&order=desc&limit=2&cursor={}",
+        cursor
+    ))
+    .await;
+    assert_eq!(
+        first
+            .into_iter()
+            .map(|task| task.title)
+            .chain(second)
+            .collect::<Vec<_>>(),
+        ["taxes", "gym", "groceries", "g_100%"]
+    );
+
+    for uri in [
+        "/api/all?condition=down".to_string(),
+        "/api/all?sort=body".into(),
+        "/api/all?order=up".into(),
+        "/api/all?created_after=yesterday".into(),
+        "/api/all?created_after=2022-11-02T00:00:00&created_before=2022-11-01T00:00:00".into(),
+        format!("/api/all?sort=updated_at&cursor={}", cursor),
+    ] {
+        let res = get_endpoint_res(&app, &bearer, &uri).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{}", uri);
+    }
+}
+
 #[actix_web::test]
 async fn test_delete_task_req() {
     let ctx = Context::new("delete_task_test");
     let pool = create_pool(&ctx);
-    let bearer = fetch_jwt();
-    let config = CognitoConfig::default();
+    let dev = DevAuth::new();
+    let bearer = dev.bearer("task-tests-user", &[]);
     let app = test::init_service(
         App::new()
             .app_data(
@@ -212,7 +420,7 @@ async fn test_delete_task_req() {
                     .error_handler(|err, _| AppError::JsonPayLoad(err).into()),
             )
             .app_data(web::Data::new(pool.clone()))
-            .app_data(web::Data::new(config.clone()))
+            .app_data(dev.verifier())
             .service(
                 web::scope("/api")
                     .service(delete_task)
@@ -261,8 +469,8 @@ async fn test_delete_task_req() {
 async fn test_invalid_json_body_req() {
     let ctx = Context::new("invalid_json_test");
     let pool = create_pool(&ctx);
-    let bearer = fetch_jwt();
-    let config = CognitoConfig::default();
+    let dev = DevAuth::new();
+    let bearer = dev.bearer("task-tests-user", &[]);
     let app = test::init_service(
         App::new()
             .app_data(
@@ -270,7 +478,7 @@ async fn test_invalid_json_body_req() {
                     .error_handler(|err, _| AppError::JsonPayLoad(err).into()),
             )
             .app_data(web::Data::new(pool.clone()))
-            .app_data(web::Data::new(config.clone()))
+            .app_data(dev.verifier())
             .service(
                 web::scope("/api")
                     .service(create_new_task)
@@ -282,8 +490,8 @@ async fn test_invalid_json_body_req() {
     //let res = get_invalid_json_res(&app, &bearer).await;
     let res = post_endpoint_res(&app, json!({"title": "Task title"}), &bearer, "/api/new").await;
     assert_eq!(res.status(), StatusCode::BAD_REQUEST);
-    let res_body: AppErrorResponse = test::read_body_json(res).await;
-    assert_eq!(res_body.code, "400");
+    let res_body: ProblemDetails = test::read_body_json(res).await;
+    assert_eq!(res_body.status, 400);
 }
 
 #[actix_web::test]
@@ -291,7 +499,7 @@ async fn test_invalid_json_body_req() {
 async fn test_missing_jwt_req() {
     let ctx = Context::new("missing_jwt_test");
     let pool = create_pool(&ctx);
-    let config = CognitoConfig::default();
+    let dev = DevAuth::new();
 
     let app = test::init_service(
         App::new()
@@ -300,7 +508,7 @@ async fn test_missing_jwt_req() {
                     .error_handler(|err, _| AppError::JsonPayLoad(err).into()),
             )
             .app_data(web::Data::new(pool.clone()))
-            .app_data(web::Data::new(config.clone()))
+            .app_data(dev.verifier())
             .service(
                 web::scope("/api")
                     .service(create_new_task)
@@ -318,6 +526,6 @@ async fn test_missing_jwt_req() {
     )
     .await;
     assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
-    let res_body: AppErrorResponse = test::read_body_json(res).await;
-    assert_eq!(res_body.code, "401");
+    let res_body: ProblemDetails = test::read_body_json(res).await;
+    assert_eq!(res_body.status, 401);
 }
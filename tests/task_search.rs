@@ -0,0 +1,124 @@
+mod common;
+
+use actix_http::{Request, StatusCode};
+use actix_web::{
+    dev::{Service, ServiceResponse},
+    test, web, App, Error,
+};
+use common::{create_pool, get_endpoint_res, post_endpoint_res, Context, DevAuth};
+use serde_json::json;
+use zeronote::{handlers::tasks::*, middlewares::auth, models::task::TaskSearchHit};
+
+// Integration tests for the ranked full-text search over tasks
+
+async fn create_task(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    bearer: &str,
+    title: &str,
+    body: &str,
+) {
+    let res = post_endpoint_res(
+        app,
+        json!({"title": title, "body": body}),
+        bearer,
+        "/api/new",
+    )
+    .await;
+    assert!(res.status().is_success());
+}
+
+async fn search(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    bearer: &str,
+    q: &str,
+) -> Vec<TaskSearchHit> {
+    let res = get_endpoint_res(app, bearer, &format!("/api/search?q={}", q)).await;
+    assert_eq!(res.status(), StatusCode::OK, "{}", q);
+
+    test::read_body_json(res).await
+}
+
+#[actix_web::test]
+async fn test_search_tasks_req() {
+    let ctx = Context::new("search_tasks_test");
+    let pool = create_pool(&ctx);
+    let dev = DevAuth::new();
+    let bearer = dev.bearer("task-tests-user", &[]);
+    let other = dev.bearer("other-user", &[]);
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(dev.verifier())
+            .service(
+                web::scope("/api")
+                    .service(search_tasks)
+                    .service(create_new_task)
+                    .wrap(auth::Authorization),
+            ),
+    )
+    .await;
+
+    create_task(&app, &bearer, "Groceries", "Buy milk, eggs & bread").await;
+    create_task(
+        &app,
+        &bearer,
+        "Taxes",
+        "Send the receipts to the accountant",
+    )
+    .await;
+    create_task(
+        &app,
+        &bearer,
+        "Baking",
+        "Bread needs flour from the groceries",
+    )
+    .await;
+    create_task(&app, &other, "Other groceries", "Not visible").await;
+
+    let hits = search(&app, &bearer, "grocery").await;
+    assert_eq!(
+        hits.iter()
+            .map(|h| h.task.task.title.as_str())
+            .collect::<Vec<_>>(),
+        ["Groceries", "Baking"],
+        "Title matches rank first & other users' tasks are never returned"
+    );
+    assert_eq!(hits[0].title_highlight, "<mark>Groceries</mark>");
+    assert!(hits[1].snippet.contains("<mark>groceries</mark>"));
+    assert!(hits[0].rank > hits[1].rank);
+
+    let hits = search(&app, &bearer, "acountant").await;
+    assert_eq!(hits.len(), 1, "Misspelled terms should match fuzzily");
+    assert_eq!(hits[0].task.task.title, "Taxes");
+
+    assert!(search(&app, &bearer, "vacation").await.is_empty());
+    for blank in ["", "%20%20"] {
+        let res = get_endpoint_res(&app, &bearer, &format!("/api/search?q={}", blank)).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    // Markup stored in the task is escaped, only the highlights are left as HTML
+    create_task(
+        &app,
+        &bearer,
+        "<img src=x onerror=alert(1)> payload",
+        "<script>alert(1)</script> payload & \"more\"",
+    )
+    .await;
+    let hits = search(&app, &bearer, "payload").await;
+    assert_eq!(hits.len(), 1);
+    let highlights = format!("{} {}", hits[0].title_highlight, hits[0].snippet);
+    assert!(!highlights.contains("<img") && !highlights.contains("<script"));
+    assert!(highlights.contains("&lt;img"), "{}", highlights);
+    assert!(
+        highlights.contains("alert(1)&lt;/script&gt;"),
+        "{}",
+        highlights
+    );
+    assert!(
+        highlights.contains("<mark>payload</mark>"),
+        "{}",
+        highlights
+    );
+    assert!(highlights.contains("&amp; &quot;more"), "{}", highlights);
+}
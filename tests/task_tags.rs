@@ -0,0 +1,243 @@
+mod common;
+
+use actix_http::{Request, StatusCode};
+use actix_web::{
+    dev::{Service, ServiceResponse},
+    test, web, App, Error,
+};
+use common::{
+    create_pool, delete_endpoint_res, get_endpoint_res, patch_endpoint_res, post_endpoint_res,
+    put_endpoint_res, titles, Context, DevAuth,
+};
+use serde_json::json;
+use uuid::Uuid;
+use zeronote::{
+    handlers::{tags::*, tasks::*},
+    middlewares::auth,
+    models::{
+        tag::Tag,
+        task::{BatchResponse, TaskSearchHit, TaskWithTags},
+    },
+};
+
+// Integration tests for the tag endpoints, attaching tags to tasks & filtering tasks by tags
+
+async fn init_app(
+    ctx: &Context,
+    dev: &DevAuth,
+) -> impl Service<Request, Response = ServiceResponse, Error = Error> {
+    test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_pool(ctx)))
+            .app_data(dev.verifier())
+            .service(
+                web::scope("/api/v1")
+                    .service(list_tasks)
+                    .service(create_task)
+                    .service(search_tasks)
+                    .service(batch_tasks)
+                    .service(list_trash)
+                    .service(get_task)
+                    .service(replace_task)
+                    .service(patch_task)
+                    .service(remove_task)
+                    .service(list_tags)
+                    .service(create_tag)
+                    .service(get_tag)
+                    .service(replace_tag)
+                    .service(delete_tag)
+                    .wrap(auth::Authorization),
+            ),
+    )
+    .await
+}
+
+async fn create_tag_res(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    bearer: &str,
+    name: &str,
+) -> Tag {
+    let res = post_endpoint_res(
+        app,
+        json!({"name": name, "color": "#1e90ff"}),
+        bearer,
+        "/api/v1/tags",
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+    test::read_body_json(res).await
+}
+
+async fn create_task_res(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    bearer: &str,
+    title: &str,
+    tags: &[Uuid],
+) -> TaskWithTags {
+    let res = post_endpoint_res(
+        app,
+        json!({"title": title, "body": "Task body", "tags": tags}),
+        bearer,
+        "/api/v1/tasks",
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+    test::read_body_json(res).await
+}
+
+fn tag_names(task: &TaskWithTags) -> Vec<&str> {
+    task.tags.iter().map(|tag| tag.name.as_str()).collect()
+}
+
+#[actix_web::test]
+async fn test_tag_endpoints() {
+    let ctx = Context::new("task_tags_test");
+    let dev = DevAuth::new();
+    let bearer = dev.bearer("tag-tests-user", &[]);
+    let other = dev.bearer("other-user", &[]);
+    let app = init_app(&ctx, &dev).await;
+
+    let work = create_tag_res(&app, &bearer, "work").await;
+    assert_eq!(work.color.as_deref(), Some("#1e90ff"));
+    let res = post_endpoint_res(&app, json!({"name": "Work"}), &bearer, "/api/v1/tags").await;
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+    let res = post_endpoint_res(
+        &app,
+        json!({"name": "home", "color": "blue"}),
+        &bearer,
+        "/api/v1/tags",
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    // Names are only unique per user
+    create_tag_res(&app, &other, "work").await;
+
+    let uri = format!("/api/v1/tags/{}", work.id);
+    let res = get_endpoint_res(&app, &other, &uri).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    let res = put_endpoint_res(&app, json!({"name": "office"}), &bearer, &uri).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let office: Tag = test::read_body_json(res).await;
+    assert_eq!(
+        (office.name.as_str(), office.color.as_deref()),
+        ("office", None)
+    );
+
+    let res = get_endpoint_res(&app, &bearer, "/api/v1/tags").await;
+    let tags_vec: Vec<Tag> = test::read_body_json(res).await;
+    assert_eq!(tags_vec, [office]);
+
+    let res = delete_endpoint_res(&app, json!({}), &other, &uri).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    let res = delete_endpoint_res(&app, json!({}), &bearer, &uri).await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    let res = get_endpoint_res(&app, &bearer, &uri).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn test_task_tags() {
+    let ctx = Context::new("task_tags_attach_test");
+    let dev = DevAuth::new();
+    let bearer = dev.bearer("tag-tests-user", &[]);
+    let other = dev.bearer("other-user", &[]);
+    let app = init_app(&ctx, &dev).await;
+    let work = create_tag_res(&app, &bearer, "work").await;
+    let urgent = create_tag_res(&app, &bearer, "urgent").await;
+    let others = create_tag_res(&app, &other, "others").await;
+
+    let first = create_task_res(&app, &bearer, "first", &[work.id, urgent.id]).await;
+    assert_eq!(tag_names(&first), ["urgent", "work"]);
+    let second = create_task_res(&app, &bearer, "second", &[work.id]).await;
+    create_task_res(&app, &bearer, "third", &[]).await;
+
+    // Tags of other users can't be attached
+    let res = post_endpoint_res(
+        &app,
+        json!({"title": "fourth", "body": "Task body", "tags": [others.id]}),
+        &bearer,
+        "/api/v1/tasks",
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        titles(&app, &bearer, "/api/v1/tasks?sort=title").await,
+        ["first", "second", "third"]
+    );
+
+    let any = format!("/api/v1/tasks?sort=title&tags={},{}", work.id, urgent.id);
+    assert_eq!(titles(&app, &bearer, &any).await, ["first", "second"]);
+    let all = format!("{}&tag_match=all", any);
+    assert_eq!(titles(&app, &bearer, &all).await, ["first"]);
+    let res = get_endpoint_res(&app, &bearer, "/api/v1/tasks?tags=work").await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    // Merge patches replace the attached tags, PUT leaves them as they are when omitted
+    let uri = format!("/api/v1/tasks/{}", second.task.id);
+    let res = patch_endpoint_res(&app, json!({"tags": [urgent.id]}), &bearer, &uri).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let patched: TaskWithTags = test::read_body_json(res).await;
+    assert_eq!(tag_names(&patched), ["urgent"]);
+    assert_eq!(patched.task.version, second.task.version + 1);
+    let res = put_endpoint_res(
+        &app,
+        json!({"title": "second", "body": "New body", "condition": "active"}),
+        &bearer,
+        &uri,
+    )
+    .await;
+    let replaced: TaskWithTags = test::read_body_json(res).await;
+    assert_eq!(tag_names(&replaced), ["urgent"]);
+    let res = patch_endpoint_res(&app, json!({"tags": []}), &bearer, &uri).await;
+    let patched: TaskWithTags = test::read_body_json(res).await;
+    assert!(patched.tags.is_empty());
+
+    // Deleting a tag detaches it & changes the version of the tasks it was attached to
+    let tag_uri = format!("/api/v1/tags/{}", urgent.id);
+    let res = delete_endpoint_res(&app, json!({}), &bearer, &tag_uri).await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    let res = get_endpoint_res(&app, &bearer, &format!("/api/v1/tasks/{}", first.task.id)).await;
+    let task: TaskWithTags = test::read_body_json(res).await;
+    assert_eq!(tag_names(&task), ["work"]);
+    assert_eq!(task.task.version, first.task.version + 1);
+}
+
+#[actix_web::test]
+async fn test_embedded_tags() {
+    let ctx = Context::new("task_tags_embedded_test");
+    let dev = DevAuth::new();
+    let bearer = dev.bearer("tag-tests-user", &[]);
+    let app = init_app(&ctx, &dev).await;
+    let work = create_tag_res(&app, &bearer, "work").await;
+    let taxes = create_task_res(&app, &bearer, "Taxes", &[work.id]).await;
+
+    let res = get_endpoint_res(&app, &bearer, "/api/v1/search?q=taxes").await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let hits: Vec<TaskSearchHit> = test::read_body_json(res).await;
+    assert_eq!(hits.len(), 1);
+    assert_eq!(tag_names(&hits[0].task), ["work"]);
+
+    // Tasks returned more than once by a batch carry their tags in every result
+    let batch = json!({"mode": "best_effort", "operations": [
+        {"op": "create", "title": "Invoices", "body": "Task body", "tags": [work.id]},
+        {"op": "set_condition", "id": taxes.task.id, "condition": "active"},
+        {"op": "delete", "id": Uuid::new_v4()},
+        {"op": "set_condition", "id": taxes.task.id, "condition": "done"},
+    ]});
+    let res = post_endpoint_res(&app, batch, &bearer, "/api/v1/tasks/batch").await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let res: BatchResponse = test::read_body_json(res).await;
+    let statuses: Vec<u16> = res.results.iter().map(|result| result.status).collect();
+    assert_eq!(statuses, [201, 200, 404, 200]);
+    for index in [0, 1, 3] {
+        let task = res.results[index].task.as_ref().unwrap();
+        assert_eq!(tag_names(task), ["work"], "Result {}", index);
+    }
+
+    let uri = format!("/api/v1/tasks/{}", taxes.task.id);
+    delete_endpoint_res(&app, json!({}), &bearer, &uri).await;
+    let res = get_endpoint_res(&app, &bearer, "/api/v1/tasks/trash").await;
+    let trashed: Vec<TaskWithTags> = test::read_body_json(res).await;
+    assert_eq!(trashed.len(), 1);
+    assert_eq!(tag_names(&trashed[0]), ["work"]);
+}
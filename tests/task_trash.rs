@@ -0,0 +1,146 @@
+mod common;
+
+use actix_http::{Request, StatusCode};
+use actix_web::{
+    dev::{Service, ServiceResponse},
+    test, web, App, Error,
+};
+use common::{create, create_pool, get_endpoint_res, titles, Context, DevAuth};
+use serde_json::json;
+use std::time::Duration;
+use zeronote::{
+    database::connection::Pool, handlers::tasks::*, middlewares::auth, models::task::TaskSearchHit,
+    services::trash::TrashPurge,
+};
+
+// Integration tests for soft deletion, the trash endpoints & purging of old trashed tasks
+
+async fn init_app(
+    pool: web::Data<Pool>,
+    dev: &DevAuth,
+) -> impl Service<Request, Response = ServiceResponse, Error = Error> {
+    test::init_service(
+        App::new().app_data(pool).app_data(dev.verifier()).service(
+            web::scope("/api/v1")
+                .service(list_tasks)
+                .service(create_task)
+                .service(search_tasks)
+                .service(list_trash)
+                .service(empty_trash)
+                .service(restore_task)
+                .service(get_task)
+                .service(remove_task)
+                .wrap(auth::Authorization),
+        ),
+    )
+    .await
+}
+
+async fn send(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    req: test::TestRequest,
+    bearer: &str,
+) -> StatusCode {
+    let req = req.insert_header(("Authorization", bearer)).to_request();
+    test::call_service(app, req).await.status()
+}
+
+#[actix_web::test]
+async fn test_trash_reqs() {
+    let ctx = Context::new("task_trash_test");
+    let dev = DevAuth::new();
+    let bearer = dev.bearer("task-tests-user", &[]);
+    let other = dev.bearer("other-user", &[]);
+    let app = init_app(web::Data::new(create_pool(&ctx)), &dev).await;
+    let task = |title: &str| json!({"title": title, "body": "Task body"});
+
+    let kept = create(&app, &bearer, task("kept")).await;
+    let trashed = create(&app, &bearer, task("trashed")).await;
+    let uri = format!("/api/v1/tasks/{}", trashed.id);
+    let delete = || test::TestRequest::delete().uri(&uri);
+    assert_eq!(send(&app, delete(), &bearer).await, StatusCode::NO_CONTENT);
+
+    assert_eq!(titles(&app, &bearer, "/api/v1/tasks").await, ["kept"]);
+    assert_eq!(
+        titles(&app, &bearer, "/api/v1/tasks/trash").await,
+        ["trashed"]
+    );
+    assert!(titles(&app, &other, "/api/v1/tasks/trash").await.is_empty());
+    let res = get_endpoint_res(&app, &bearer, "/api/v1/search?q=trashed").await;
+    let hits: Vec<TaskSearchHit> = test::read_body_json(res).await;
+    assert!(hits.is_empty(), "Trashed tasks must not be searchable");
+    assert_eq!(
+        get_endpoint_res(&app, &bearer, &uri).await.status(),
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(send(&app, delete(), &bearer).await, StatusCode::NOT_FOUND);
+
+    let restore =
+        |id| test::TestRequest::post().uri(&format!("/api/v1/tasks/trash/{}/restore", id));
+    assert_eq!(
+        send(&app, restore(trashed.id), &other).await,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        send(&app, restore(kept.id), &bearer).await,
+        StatusCode::NOT_FOUND,
+        "Only trashed tasks can be restored"
+    );
+    assert_eq!(
+        send(&app, restore(trashed.id), &bearer).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        titles(&app, &bearer, "/api/v1/tasks?sort=title").await,
+        ["kept", "trashed"]
+    );
+
+    assert_eq!(send(&app, delete(), &bearer).await, StatusCode::NO_CONTENT);
+    let empty = test::TestRequest::delete().uri("/api/v1/tasks/trash");
+    assert_eq!(send(&app, empty, &bearer).await, StatusCode::NO_CONTENT);
+    assert!(titles(&app, &bearer, "/api/v1/tasks/trash")
+        .await
+        .is_empty());
+    assert_eq!(
+        send(&app, restore(trashed.id), &bearer).await,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(titles(&app, &bearer, "/api/v1/tasks").await, ["kept"]);
+}
+
+#[actix_web::test]
+async fn test_trash_purge() {
+    let ctx = Context::new("task_purge_test");
+    let dev = DevAuth::new();
+    let bearer = dev.bearer("task-tests-user", &[]);
+    let other = dev.bearer("other-user", &[]);
+    let pool = create_pool(&ctx);
+    let app = init_app(web::Data::new(pool.clone()), &dev).await;
+    let task = |title: &str| json!({"title": title, "body": "Task body"});
+
+    create(&app, &bearer, task("kept")).await;
+    for (bearer, title) in [(&bearer, "trashed"), (&other, "other trashed")] {
+        let task = create(&app, bearer, task(title)).await;
+        let req = test::TestRequest::delete().uri(&format!("/api/v1/tasks/{}", task.id));
+        assert_eq!(send(&app, req, bearer).await, StatusCode::NO_CONTENT);
+    }
+
+    let hour = Duration::from_secs(3600);
+    let retained = TrashPurge::new(chrono::Duration::days(30), hour);
+    assert_eq!(retained.purge(&pool).unwrap(), 0);
+    assert_eq!(
+        titles(&app, &bearer, "/api/v1/tasks/trash").await,
+        ["trashed"]
+    );
+
+    let expired = TrashPurge::new(chrono::Duration::zero(), hour);
+    assert_eq!(
+        expired.purge(&pool).unwrap(),
+        2,
+        "Trashed tasks of all users must be purged"
+    );
+    assert!(titles(&app, &bearer, "/api/v1/tasks/trash")
+        .await
+        .is_empty());
+    assert_eq!(titles(&app, &bearer, "/api/v1/tasks").await, ["kept"]);
+}
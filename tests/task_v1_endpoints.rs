@@ -0,0 +1,398 @@
+mod common;
+
+use actix_http::{Request, StatusCode};
+use actix_web::{
+    dev::{Service, ServiceResponse},
+    http::header,
+    test, web, App, Error,
+};
+use common::{
+    create_pool, get_endpoint_res, patch_endpoint_res, post_endpoint_res, put_endpoint_res,
+    Context, DevAuth,
+};
+use serde_json::json;
+use zeronote::{
+    errors::app_error::AppError,
+    handlers::tasks::*,
+    middlewares::{
+        auth,
+        deprecation::{deprecated, deprecated_paths},
+    },
+    models::task::{Task, TaskCondition, MERGE_PATCH_CONTENT_TYPE},
+};
+
+// Integration tests for the resource-oriented /api/v1/tasks routes & their deprecated aliases
+
+async fn init_app(
+    ctx: &Context,
+    dev: &DevAuth,
+) -> impl Service<Request, Response = ServiceResponse, Error = Error> {
+    test::init_service(
+        App::new()
+            .app_data(
+                web::JsonConfig::default()
+                    .error_handler(|err, _| AppError::JsonPayLoad(err).into()),
+            )
+            .app_data(web::Data::new(create_pool(ctx)))
+            .app_data(dev.verifier())
+            .service(
+                web::scope("/api")
+                    .service(
+                        web::scope("/v1")
+                            .service(list_tasks)
+                            .service(create_task)
+                            .service(get_task)
+                            .service(replace_task)
+                            .service(patch_task)
+                            .service(remove_task),
+                    )
+                    .service(
+                        web::scope("")
+                            .guard(deprecated_paths())
+                            .wrap(deprecated())
+                            .service(create_new_task)
+                            .service(get_all_tasks),
+                    )
+                    .wrap(auth::Authorization),
+            ),
+    )
+    .await
+}
+
+async fn delete(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    bearer: &str,
+    uri: &str,
+) -> StatusCode {
+    let req = test::TestRequest::delete()
+        .uri(uri)
+        .insert_header(("Authorization", bearer))
+        .to_request();
+    test::call_service(app, req).await.status()
+}
+
+#[actix_web::test]
+async fn test_task_resource_reqs() {
+    let ctx = Context::new("task_v1_test");
+    let dev = DevAuth::new();
+    let bearer = dev.bearer("task-tests-user", &[]);
+    let other = dev.bearer("other-user", &[]);
+    let app = init_app(&ctx, &dev).await;
+
+    let res = post_endpoint_res(
+        &app,
+        json!({"title": "Task title", "body": "Task body"}),
+        &bearer,
+        "/api/v1/tasks",
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let location = res
+        .headers()
+        .get(header::LOCATION)
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_owned();
+    let created: Task = test::read_body_json(res).await;
+    assert_eq!(location, format!("/api/v1/tasks/{}", created.id));
+
+    let res = get_endpoint_res(&app, &bearer, &location).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let task: Task = test::read_body_json(res).await;
+    assert_eq!(task.id, created.id);
+    assert_eq!(
+        get_endpoint_res(&app, &other, &location).await.status(),
+        StatusCode::NOT_FOUND,
+        "Other users' tasks must not be visible"
+    );
+    let unknown = "/api/v1/tasks/1f4c6c5e-5f7a-4b4e-9b1a-3c2d8e0f1a2b";
+    assert_eq!(
+        get_endpoint_res(&app, &bearer, unknown).await.status(),
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        get_endpoint_res(&app, &bearer, "/api/v1/tasks/not-a-uuid")
+            .await
+            .status(),
+        StatusCode::NOT_FOUND
+    );
+
+    let res = put_endpoint_res(
+        &app,
+        json!({"title": "New title", "body": "New body", "condition": "active"}),
+        &bearer,
+        &location,
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let task: Task = test::read_body_json(res).await;
+    assert_eq!(task.title, "New title");
+    assert_eq!(task.body, "New body");
+    assert_eq!(task.condition, TaskCondition::Active);
+    let res = put_endpoint_res(&app, json!({"title": "Missing fields"}), &bearer, &location).await;
+    assert_eq!(
+        res.status(),
+        StatusCode::BAD_REQUEST,
+        "PUT replaces the whole task"
+    );
+
+    let res = patch_endpoint_res(&app, json!({"condition": "done"}), &bearer, &location).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let task: Task = test::read_body_json(res).await;
+    assert_eq!(task.title, "New title", "PATCH keeps the omitted fields");
+    assert_eq!(task.condition, TaskCondition::Done);
+    let res = patch_endpoint_res(&app, json!({"title": "Stolen"}), &other, &location).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    assert_eq!(delete(&app, &other, &location).await, StatusCode::NOT_FOUND);
+    assert_eq!(
+        delete(&app, &bearer, &location).await,
+        StatusCode::NO_CONTENT
+    );
+    assert_eq!(
+        delete(&app, &bearer, &location).await,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        get_endpoint_res(&app, &bearer, &location).await.status(),
+        StatusCode::NOT_FOUND
+    );
+}
+
+#[actix_web::test]
+async fn test_deprecated_task_routes() {
+    let ctx = Context::new("task_v1_deprecation_test");
+    let dev = DevAuth::new();
+    let bearer = dev.bearer("task-tests-user", &[]);
+    let app = init_app(&ctx, &dev).await;
+
+    let res = post_endpoint_res(
+        &app,
+        json!({"title": "Task title", "body": "Task body"}),
+        &bearer,
+        "/api/new",
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers().get("Deprecation").unwrap(), "true");
+
+    let res = get_endpoint_res(&app, &bearer, "/api/all").await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers().get("Deprecation").unwrap(), "true");
+    assert!(res
+        .headers()
+        .get(header::LINK)
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .contains("successor-version"));
+
+    let res = get_endpoint_res(&app, &bearer, "/api/al").await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    assert!(res.headers().get("Deprecation").is_none());
+
+    let res = get_endpoint_res(&app, &bearer, "/api/v1/tasks").await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.headers().get("Deprecation").is_none());
+    let tasks: Vec<Task> = test::read_body_json(res).await;
+    assert_eq!(tasks.len(), 1);
+}
+
+#[actix_web::test]
+async fn test_merge_patch_task_req() {
+    let ctx = Context::new("task_merge_patch_test");
+    let dev = DevAuth::new();
+    let bearer = dev.bearer("task-tests-user", &[]);
+    let app = init_app(&ctx, &dev).await;
+
+    let res = post_endpoint_res(
+        &app,
+        json!({"title": "Task title", "body": "Task body"}),
+        &bearer,
+        "/api/v1/tasks",
+    )
+    .await;
+    let created: Task = test::read_body_json(res).await;
+    let uri = format!("/api/v1/tasks/{}", created.id);
+
+    let res = patch_endpoint_res(&app, json!({"condition": "active"}), &bearer, &uri).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        res.headers().get("Accept-Patch").unwrap(),
+        "application/merge-patch+json"
+    );
+    let task: Task = test::read_body_json(res).await;
+    assert_eq!(task.condition, TaskCondition::Active);
+    assert_eq!(task.body, "Task body", "Omitted fields must be kept");
+
+    let res = patch_endpoint_res(&app, json!({}), &bearer, &uri).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let unchanged: Task = test::read_body_json(res).await;
+    assert_eq!(
+        unchanged.updated_at, task.updated_at,
+        "Empty patches must not touch the task"
+    );
+
+    for invalid in [
+        json!({"title": ""}),
+        json!({"title": null}),
+        json!({"body": null}),
+        json!({"condition": "down"}),
+        json!({"owner_id": "other-user"}),
+    ] {
+        let res = patch_endpoint_res(&app, invalid.clone(), &bearer, &uri).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{}", invalid);
+    }
+
+    for content_type in [
+        "application/json",
+        "text/plain",
+        "application/x-www-form-urlencoded",
+    ] {
+        let req = test::TestRequest::patch()
+            .uri(&uri)
+            .insert_header(("Authorization", bearer.as_str()))
+            .insert_header((header::CONTENT_TYPE, content_type))
+            .set_payload(r#"{"title": "Other type"}"#)
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "{}",
+            content_type
+        );
+    }
+    let req = test::TestRequest::patch()
+        .uri(&uri)
+        .insert_header(("Authorization", bearer.as_str()))
+        .insert_header((header::CONTENT_TYPE, MERGE_PATCH_CONTENT_TYPE))
+        .set_payload(r#"{"title": "#)
+        .to_request();
+    assert_eq!(
+        test::call_service(&app, req).await.status(),
+        StatusCode::BAD_REQUEST
+    );
+
+    let res = get_endpoint_res(&app, &bearer, &uri).await;
+    let task: Task = test::read_body_json(res).await;
+    assert_eq!(task.title, "Task title");
+    assert_eq!(task.condition, TaskCondition::Active);
+}
+
+fn etag(res: &ServiceResponse) -> String {
+    res.headers()
+        .get(header::ETAG)
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_owned()
+}
+
+async fn conditional(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    req: test::TestRequest,
+    bearer: &str,
+    precondition: (header::HeaderName, &str),
+) -> ServiceResponse {
+    let req = req
+        .insert_header(("Authorization", bearer))
+        .insert_header(precondition)
+        .to_request();
+    test::call_service(app, req).await
+}
+
+#[actix_web::test]
+async fn test_conditional_task_reqs() {
+    let ctx = Context::new("task_etag_test");
+    let dev = DevAuth::new();
+    let bearer = dev.bearer("task-tests-user", &[]);
+    let app = init_app(&ctx, &dev).await;
+
+    let res = post_endpoint_res(
+        &app,
+        json!({"title": "Task title", "body": "Task body"}),
+        &bearer,
+        "/api/v1/tasks",
+    )
+    .await;
+    let created = etag(&res);
+    let task: Task = test::read_body_json(res).await;
+    let uri = format!("/api/v1/tasks/{}", task.id);
+    assert_eq!(created, "\"1\"");
+
+    let res = get_endpoint_res(&app, &bearer, &uri).await;
+    assert_eq!(etag(&res), created);
+    let res = conditional(
+        &app,
+        test::TestRequest::get().uri(&uri),
+        &bearer,
+        (header::IF_NONE_MATCH, &format!("W/{}", created)),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(etag(&res), created);
+
+    // The first device updates the task, the second one still holds the old ETag
+    let res = conditional(
+        &app,
+        test::TestRequest::put()
+            .uri(&uri)
+            .set_json(json!({"title": "First", "body": "Task body", "condition": "active"})),
+        &bearer,
+        (header::IF_MATCH, &created),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let updated = etag(&res);
+    assert_ne!(updated, created, "Updates must change the ETag");
+    let res = conditional(
+        &app,
+        test::TestRequest::patch()
+            .uri(&uri)
+            .set_json(json!({"title": "Second"}))
+            .insert_header((header::CONTENT_TYPE, "application/merge-patch+json")),
+        &bearer,
+        (header::IF_MATCH, &created),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED);
+    for stale in [created.as_str(), "\"not-a-version\"", "W/\"2\""] {
+        let res = conditional(
+            &app,
+            test::TestRequest::delete().uri(&uri),
+            &bearer,
+            (header::IF_MATCH, stale),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED, "{}", stale);
+    }
+
+    let res = conditional(
+        &app,
+        test::TestRequest::get().uri(&uri),
+        &bearer,
+        (header::IF_NONE_MATCH, &created),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let task: Task = test::read_body_json(res).await;
+    assert_eq!(task.title, "First", "Stale writes must not be applied");
+
+    let res = conditional(
+        &app,
+        test::TestRequest::delete().uri(&uri),
+        &bearer,
+        (header::IF_MATCH, &format!("\"0\", {}", updated)),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    let res = conditional(
+        &app,
+        test::TestRequest::delete().uri(&uri),
+        &bearer,
+        (header::IF_MATCH, "*"),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
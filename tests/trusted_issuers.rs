@@ -0,0 +1,234 @@
+mod common;
+
+use actix_http::{Request, StatusCode};
+use actix_web::{
+    dev::{Service, ServiceResponse},
+    test, web, App, Error, HttpResponse,
+};
+use common::{IdpStandIn, SigningKey};
+use serde_json::{json, Value};
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use zeronote::{
+    middlewares::auth,
+    models::principal::AuthenticatedUser,
+    utils::{
+        jwks::JwksCache,
+        verifier::{CognitoVerifier, IssuerSettings, OidcVerifier, TokenVerifier, TrustedIssuers},
+    },
+};
+
+// Integration tests for the Authorization middleware with several trusted issuers
+// Both identity providers are local stand-ins serving discovery documents & JWKS
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+async fn whoami(user: AuthenticatedUser) -> HttpResponse {
+    HttpResponse::Ok().json(user)
+}
+
+async fn init_app(
+    issuers: TrustedIssuers,
+) -> impl Service<Request, Response = ServiceResponse, Error = Error> {
+    let verifier: Arc<dyn TokenVerifier> = Arc::new(issuers);
+    test::init_service(
+        App::new().app_data(web::Data::from(verifier)).service(
+            web::scope("/api")
+                .route("/me", web::get().to(whoami))
+                .wrap(auth::Authorization),
+        ),
+    )
+    .await
+}
+
+async fn status_for(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    token: &str,
+) -> StatusCode {
+    status_for_header(app, &format!("Bearer {}", token)).await
+}
+
+async fn status_for_header(
+    app: &impl Service<Request, Response = ServiceResponse, Error = Error>,
+    auth_header: &str,
+) -> StatusCode {
+    let req = test::TestRequest::get()
+        .uri("/api/me")
+        .insert_header(("Authorization", auth_header))
+        .to_request();
+    match app.call(req).await {
+        Ok(res) => res.status(),
+        Err(e) => e.as_response_error().status_code(),
+    }
+}
+
+fn oidc_claims(idp: &IdpStandIn, aud: Value, exp: u64) -> Value {
+    json!({"iss": idp.issuer(), "sub": "user-1", "aud": aud, "exp": exp})
+}
+
+#[actix_web::test]
+async fn test_oidc_discovery_and_audience() {
+    let key = SigningKey::generate("oidc-key");
+    let idp = IdpStandIn::start(&[&key]).await;
+    let settings = IssuerSettings {
+        audiences: vec!["zeronote".into()],
+        leeway: 0,
+    };
+    let verifier = OidcVerifier::discover(idp.issuer(), settings)
+        .await
+        .expect("OIDC discovery failed");
+    let app = init_app(TrustedIssuers::new().with(idp.issuer(), Arc::new(verifier))).await;
+
+    let exp = now() + 300;
+    let token = key.sign(&oidc_claims(&idp, json!("zeronote"), exp));
+    assert_eq!(status_for(&app, &token).await, StatusCode::OK);
+
+    let token = key.sign(&oidc_claims(&idp, json!(["account", "zeronote"]), exp));
+    assert_eq!(
+        status_for(&app, &token).await,
+        StatusCode::OK,
+        "Audience arrays containing a trusted audience should be accepted"
+    );
+
+    let token = key.sign(&oidc_claims(&idp, json!("account"), exp));
+    assert_eq!(status_for(&app, &token).await, StatusCode::UNAUTHORIZED);
+
+    let token = key.sign(&json!({"iss": idp.issuer(), "sub": "user-1", "aud": "zeronote"}));
+    assert_eq!(
+        status_for(&app, &token).await,
+        StatusCode::UNAUTHORIZED,
+        "Tokens without an expiry should be rejected"
+    );
+}
+
+#[actix_web::test]
+async fn test_multiple_trusted_issuers() {
+    let oidc_key = SigningKey::generate("oidc-key");
+    let cognito_key = SigningKey::generate("cognito-key");
+    let oidc_idp = IdpStandIn::start(&[&oidc_key]).await;
+    let cognito_idp = IdpStandIn::start(&[&cognito_key]).await;
+
+    let oidc = OidcVerifier::discover(oidc_idp.issuer(), IssuerSettings::default())
+        .await
+        .unwrap();
+    let config = cognito_idp.cognito_config("client-b");
+    let keys = Arc::new(JwksCache::new(config.jwks_url.clone()));
+    let cognito = CognitoVerifier::new(&config, keys, IssuerSettings::default()).unwrap();
+    let app = init_app(
+        TrustedIssuers::new()
+            .with(oidc_idp.issuer(), Arc::new(oidc))
+            .with(cognito_idp.issuer(), Arc::new(cognito)),
+    )
+    .await;
+
+    let exp = now() + 300;
+    let token = oidc_key.sign(&oidc_claims(&oidc_idp, json!("anything"), exp));
+    assert_eq!(status_for(&app, &token).await, StatusCode::OK);
+
+    let cognito_claims = json!({
+        "iss": cognito_idp.issuer(), "sub": "user-2", "client_id": "client-b",
+        "token_use": "access", "exp": exp
+    });
+    let token = cognito_key.sign(&cognito_claims);
+    assert_eq!(status_for(&app, &token).await, StatusCode::OK);
+
+    let token = oidc_key.sign(&cognito_claims);
+    assert_eq!(
+        status_for(&app, &token).await,
+        StatusCode::UNAUTHORIZED,
+        "Token signed by another issuer's key should be rejected"
+    );
+
+    let mut id_token_claims = cognito_claims.clone();
+    id_token_claims["token_use"] = json!("id");
+    let token = cognito_key.sign(&id_token_claims);
+    assert_eq!(status_for(&app, &token).await, StatusCode::UNAUTHORIZED);
+
+    let token =
+        oidc_key.sign(&json!({"iss": "https://untrusted.example.com", "sub": "x", "exp": exp}));
+    assert_eq!(status_for(&app, &token).await, StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn test_per_issuer_clock_skew() {
+    let lenient_key = SigningKey::generate("lenient-key");
+    let strict_key = SigningKey::generate("strict-key");
+    let lenient_idp = IdpStandIn::start(&[&lenient_key]).await;
+    let strict_idp = IdpStandIn::start(&[&strict_key]).await;
+
+    let lenient = IssuerSettings {
+        audiences: Vec::new(),
+        leeway: 120,
+    };
+    let lenient = OidcVerifier::discover(lenient_idp.issuer(), lenient)
+        .await
+        .unwrap();
+    let strict = OidcVerifier::discover(strict_idp.issuer(), IssuerSettings::default())
+        .await
+        .unwrap();
+    let app = init_app(
+        TrustedIssuers::new()
+            .with(lenient_idp.issuer(), Arc::new(lenient))
+            .with(strict_idp.issuer(), Arc::new(strict)),
+    )
+    .await;
+
+    let expired = now() - 30;
+    let token = lenient_key.sign(&oidc_claims(&lenient_idp, json!("a"), expired));
+    assert_eq!(
+        status_for(&app, &token).await,
+        StatusCode::OK,
+        "Token expired within the issuer's leeway should be accepted"
+    );
+
+    let token = strict_key.sign(&oidc_claims(&strict_idp, json!("a"), expired));
+    assert_eq!(status_for(&app, &token).await, StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn test_authenticated_user_from_verified_claims() {
+    let key = SigningKey::generate("oidc-key");
+    let idp = IdpStandIn::start(&[&key]).await;
+    let verifier = OidcVerifier::discover(idp.issuer(), IssuerSettings::default())
+        .await
+        .unwrap();
+    let app = init_app(TrustedIssuers::new().with(idp.issuer(), Arc::new(verifier))).await;
+
+    let exp = now() + 300;
+    let token = key.sign(&json!({
+        "iss": idp.issuer(), "sub": "user-1", "exp": exp, "username": "alice",
+        "cognito:groups": ["admin"], "scope": "openid tasks:read"
+    }));
+    let req = test::TestRequest::get()
+        .uri("/api/me")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
+    let user: AuthenticatedUser = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(user.sub, "user-1");
+    assert_eq!(user.username.as_deref(), Some("alice"));
+    assert_eq!(user.groups, vec!["admin"]);
+    assert_eq!(user.scopes, vec!["openid", "tasks:read"]);
+    assert_eq!(user.expires_at.unwrap().timestamp() as u64, exp);
+
+    for malformed in [
+        "",
+        "Bearer",
+        "Bearer ",
+        token.as_str(),
+        "Basic dXNlcjpwdw==",
+    ] {
+        assert_eq!(
+            status_for_header(&app, malformed).await,
+            StatusCode::UNAUTHORIZED,
+            "Malformed Authorization header '{}' should be rejected",
+            malformed
+        );
+    }
+}